@@ -0,0 +1,146 @@
+use fltk::app::set_visual;
+use fltk::enums::Mode;
+use fltk::{prelude::*, window::Window};
+use rs_cpurenderer::model;
+use rs_cpurenderer::renderer::{cull_meshes, FaceCull};
+use rs_cpurenderer::shader::{Attributes, Vertex};
+use rs_cpurenderer::texture::TextureStorage;
+use rs_cpurenderer::{camera, cpu_renderer, gpu_renderer, math, renderer::RendererInterface};
+
+const WINDOW_WIDTH: u32 = 1024;
+const WINDOW_HEIGHT: u32 = 720;
+
+// attribute location
+const ATTR_NORMAL: usize = 0; // vec3
+
+// how many cubes in a row/column of the field
+const FIELD_SIZE: i32 = 10;
+const CUBE_SPACING: f32 = 4.0;
+
+fn swap_context(renderer: &mut Box<dyn RendererInterface>) {
+    let result = renderer.get_rendered_image();
+    fltk::draw::draw_image(
+        result,
+        0,
+        0,
+        renderer.get_canva_width() as i32,
+        renderer.get_canva_height() as i32,
+        fltk::enums::ColorDepth::Rgb8,
+    )
+    .unwrap();
+}
+
+pub fn create_renderer(w: u32, h: u32, camera: camera::Camera) -> Box<dyn RendererInterface> {
+    if cfg!(feature = "cpu") {
+        println!("use cpu renderer");
+        Box::new(cpu_renderer::Renderer::new(w, h, camera))
+    } else {
+        println!("use gpu renderer");
+        Box::new(gpu_renderer::Renderer::new(w, h, camera))
+    }
+}
+
+fn main() {
+    let app = fltk::app::App::default();
+    let mut wind = Window::new(
+        100,
+        100,
+        WINDOW_WIDTH as i32,
+        WINDOW_HEIGHT as i32,
+        "culling",
+    );
+    let mut camera = camera::Camera::new(
+        1.0,
+        1000.0,
+        WINDOW_WIDTH as f32 / WINDOW_HEIGHT as f32,
+        30f32.to_radians(),
+    );
+    camera.move_to(math::Vec3::new(0.0, 2.0, 0.0));
+
+    let mut renderer = create_renderer(WINDOW_WIDTH, WINDOW_HEIGHT, camera);
+    let texture_storage = TextureStorage::default();
+
+    // data prepare, a field of cubes around the camera
+    let (cube_meshes, _) =
+        model::load_from_file("./resources/cube/cube.obj", model::PreOperation::NONE).unwrap();
+    let cube = &cube_meshes[0];
+    renderer.set_front_face(model::suggest_front_face(cube));
+    renderer.set_face_cull(FaceCull::Back);
+    let cube_vertices: Vec<Vertex> = cube
+        .vertices
+        .iter()
+        .map(|v| {
+            let mut attr = Attributes::default();
+            attr.set_vec3(ATTR_NORMAL, v.normal);
+            Vertex::new(v.position, attr)
+        })
+        .collect();
+
+    let mut meshes = vec![];
+    let mut positions = vec![];
+    for x in -FIELD_SIZE / 2..FIELD_SIZE / 2 {
+        for z in -FIELD_SIZE / 2..FIELD_SIZE / 2 {
+            meshes.push(cube.clone());
+            positions.push(math::Vec3::new(
+                x as f32 * CUBE_SPACING,
+                0.0,
+                z as f32 * CUBE_SPACING,
+            ));
+        }
+    }
+
+    renderer.get_shader().pixel_shading = Box::new(|attr, _, _, _| {
+        let normal = attr.vec3[ATTR_NORMAL];
+        math::Vec4::new(
+            normal.x * 0.5 + 0.5,
+            normal.y * 0.5 + 0.5,
+            normal.z * 0.5 + 0.5,
+            1.0,
+        )
+    });
+
+    let mut yaw = 0.0f32;
+    let mut frame = 0u32;
+
+    wind.draw(move |_| {
+        // turn around to see how many cubes are culled
+        renderer
+            .get_camera()
+            .set_rotation(math::Vec3::new(0.0, yaw.to_radians(), 0.0));
+
+        renderer.clear(&math::Vec4::new(0.2, 0.2, 0.2, 1.0));
+        renderer.clear_depth();
+
+        let models: Vec<math::Mat4> = positions.iter().map(math::create_translate).collect();
+        let visibility = cull_meshes(&meshes, &models, renderer.get_camera());
+
+        for (model, visible) in models.iter().zip(visibility.iter()) {
+            if *visible {
+                renderer.draw_triangle(model, &cube_vertices, &texture_storage);
+            }
+        }
+
+        if frame.is_multiple_of(60) {
+            println!(
+                "draw {}/{} cubes",
+                visibility.iter().filter(|v| **v).count(),
+                visibility.len()
+            );
+        }
+
+        yaw += 0.5;
+        frame += 1;
+
+        swap_context(&mut renderer);
+    });
+
+    wind.end();
+    set_visual(Mode::Rgb).unwrap();
+    wind.show();
+
+    fltk::app::add_idle3(move |_| {
+        wind.redraw();
+    });
+
+    app.run().unwrap();
+}
@@ -96,7 +96,7 @@ fn main() {
     const MODEL_ROOT_DIR: &str = "./resources/Son Goku";
     let (meshes, mtllibs) = model::load_from_file(
         &format!("{}/{}", MODEL_ROOT_DIR, "Goku.obj"),
-        model::PreOperation::None,
+        model::PreOperation::NONE,
     )
     .unwrap();
     let vertex_datas = restruct_model_vertex(&meshes);
@@ -115,14 +115,12 @@ fn main() {
     renderer.get_shader().vertex_changing = Box::new(|vertex, _, _| *vertex);
 
     // pixel shading shader(as fragment shader in OpenGL)
-    renderer.get_shader().pixel_shading = Box::new(|attr, uniforms, texture_storage| {
+    renderer.get_shader().pixel_shading = Box::new(|attr, uniforms, texture_storage, _| {
         let mut frag_color = *uniforms
             .vec4
             .get(&UNIFORM_COLOR)
             .unwrap_or(&math::Vec4::new(1.0, 1.0, 1.0, 1.0));
-        let mut texcoord = attr.vec2[ATTR_TEXCOORD];
-        texcoord.x = texcoord.x.clamp(0.0, 1.0);
-        texcoord.y = texcoord.y.clamp(0.0, 1.0);
+        let texcoord = attr.vec2[ATTR_TEXCOORD];
         if let Some(texture_id) = uniforms.texture.get(&UNIFORM_TEXTURE) {
             if let Some(texture) = texture_storage.get_by_id(*texture_id) {
                 frag_color *= texture_sample(texture, &texcoord);
@@ -175,12 +173,10 @@ fn main() {
                 let mtllib = &mtllibs[data.mtllib.unwrap() as usize];
                 if let Some(material) = mtllib.materials.get(&data.material.clone().unwrap()) {
                     if let Some(ambient) = material.ambient {
-                        uniforms
-                            .vec4
-                            .insert(UNIFORM_COLOR, math::Vec4::from_vec3(&ambient, 1.0));
+                        uniforms.set_vec4(UNIFORM_COLOR, math::Vec4::from_vec3(&ambient, 1.0));
                     }
                     if let Some(diffuse_texture) = &material.texture_maps.diffuse {
-                        uniforms.texture.insert(
+                        uniforms.set_texture(
                             UNIFORM_TEXTURE,
                             *texture_storage.get_id(diffuse_texture).unwrap(),
                         );
@@ -1,47 +1,164 @@
 use crate::math;
+use crate::renderer::Viewport;
 
 pub struct Frustum {
     near: f32,
     far: f32,
     aspect: f32,
     fovy: f32,
+    // `(left, right, bottom, top)` of orthographic box, `None` for perspective
+    ortho: Option<(f32, f32, f32, f32)>,
+    // z range in NDC, `None` when z isn't mapped with far plane
+    z_range: Option<NdcZRange>,
+    // offset in NDC, already applied to `mat`
+    jitter: math::Vec2,
 
     mat: math::Mat4,
 }
 
+/// which axis field of view angle is measured along, `tan(horizontal) = tan(vertical) * aspect` for half angles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FovAxis {
+    Vertical,
+    Horizontal,
+}
+
+impl FovAxis {
+    /// convert half field of view along this axis into horizontal half field of view, `aspect` is width / height
+    pub fn to_horizontal(&self, fov: f32, aspect: f32) -> f32 {
+        match self {
+            FovAxis::Horizontal => fov,
+            FovAxis::Vertical => (fov.tan() * aspect).atan(),
+        }
+    }
+}
+
+/// range of z in NDC after perspective divide, near plane maps to the first value and far plane to the second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NdcZRange {
+    /// `[-1, 1]`, OpenGL convention
+    NegOneToOne,
+    /// `[0, 1]`, Direct3D/Vulkan convention
+    ZeroToOne,
+}
+
+impl NdcZRange {
+    /// `(near, far)` end of the range
+    pub fn bounds(&self) -> (f32, f32) {
+        match self {
+            NdcZRange::NegOneToOne => (-1.0, 1.0),
+            NdcZRange::ZeroToOne => (0.0, 1.0),
+        }
+    }
+}
+
 impl Frustum {
     #[rustfmt::skip]
     pub fn new(near: f32, far: f32, aspect: f32, fovy: f32) -> Self {
+        if !cfg!(feature="cpu") {
+            return Self::new_with_z_range(near, far, aspect, fovy, NdcZRange::NegOneToOne);
+        }
+
+        let a = 1.0 / (near * fovy.tan());
         Self {
             near,
             far,
             aspect,
             fovy,
-            mat: if cfg!(feature="cpu") {
-                let a = 1.0 / (near * fovy.tan());
-                // without far plane, clamp x,y in [-1, 1], z = near
-                math::Mat4::from_row(&[
-                    a,          0.0,         0.0, 0.0,
-                    0.0, aspect * a,         0.0, 0.0,
-                    0.0,        0.0,         1.0, 0.0,
-                    0.0,        0.0, -1.0 / near, 0.0,
-                ])
-            } else { // when in GPU, [we use opengl matrix](http://www.songho.ca/opengl/gl_projectionmatrix.html)
-                let half_w = near * fovy.tan();
-                let half_h = half_w / aspect;
-                let near = near.abs();
-                let far = far.abs();
-                // with far plane, clamp x,y,z in [-1, 1]
-                math::Mat4::from_row(&[
-                    near / half_w,           0.0,                       0.0,                             0.0,
-                              0.0, near / half_h,                       0.0,                             0.0,
-                              0.0,           0.0,(far + near) / (near - far), 2.0 * far * near / (near - far),
-                              0.0,           0.0,                      -1.0,                             0.0,
-                ])
-            },
+            ortho: None,
+            z_range: None,
+            jitter: math::Vec2::zero(),
+            // without far plane, clamp x,y in [-1, 1], z = near
+            mat: math::Mat4::from_row(&[
+                a,          0.0,         0.0, 0.0,
+                0.0, aspect * a,         0.0, 0.0,
+                0.0,        0.0,         1.0, 0.0,
+                0.0,        0.0, -1.0 / near, 0.0,
+            ]),
         }
     }
 
+    /// like `Frustum::new`, but `fov` is half field of view along `axis`
+    pub fn new_with_fov_axis(near: f32, far: f32, aspect: f32, fov: f32, axis: FovAxis) -> Self {
+        Self::new(near, far, aspect, axis.to_horizontal(fov, aspect))
+    }
+
+    /// perspective frustum which maps z into `z_range` with near and far plane.
+    /// `z_range` only affects the projection matrix(clip z), renderers still write view z remapped by `DepthRange` into depth buffer.
+    /// In CPU, `w` keeps `-z / near` as `Frustum::new`, in GPU [we use opengl matrix](http://www.songho.ca/opengl/gl_projectionmatrix.html)
+    #[rustfmt::skip]
+    pub fn new_with_z_range(near: f32, far: f32, aspect: f32, fovy: f32, z_range: NdcZRange) -> Self {
+        let half_w = near * fovy.tan();
+        let half_h = half_w / aspect;
+        let n = near.abs();
+        let f = far.abs();
+        // z row for `w = -z`
+        let (a, b) = match z_range {
+            NdcZRange::NegOneToOne => ((f + n) / (n - f), 2.0 * f * n / (n - f)),
+            NdcZRange::ZeroToOne => (f / (n - f), f * n / (n - f)),
+        };
+        // CPU divides the whole matrix by near, this doesn't change result after perspective divide
+        let scale = if cfg!(feature="cpu") { 1.0 / near } else { 1.0 };
+
+        Self {
+            near,
+            far,
+            aspect,
+            fovy,
+            ortho: None,
+            z_range: Some(z_range),
+            jitter: math::Vec2::zero(),
+            mat: math::Mat4::from_row(&[
+                n / half_w * scale,                0.0,       0.0,       0.0,
+                               0.0, n / half_h * scale,       0.0,       0.0,
+                               0.0,                0.0, a * scale, b * scale,
+                               0.0,                0.0,    -scale,       0.0,
+            ]),
+        }
+    }
+
+    /// orthographic box in view space, `near` and `far` are distances along view direction.
+    /// `fovy` is 0 and `aspect` is the ratio of box width and height
+    pub fn new_ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        Self {
+            near,
+            far,
+            aspect: (right - left) / (top - bottom),
+            fovy: 0.0,
+            ortho: Some((left, right, bottom, top)),
+            z_range: Some(NdcZRange::NegOneToOne),
+            jitter: math::Vec2::zero(),
+            mat: math::create_orthographic(left, right, bottom, top, near, far),
+        }
+    }
+
+    pub fn is_ortho(&self) -> bool {
+        self.ortho.is_some()
+    }
+
+    pub fn z_range(&self) -> Option<NdcZRange> {
+        self.z_range
+    }
+
+    /// shift projected image by `jitter` in NDC(one pixel is `2 / width` along x), clip planes are not changed
+    #[rustfmt::skip]
+    pub fn set_jitter(&mut self, jitter: math::Vec2) {
+        let delta = jitter - self.jitter;
+        // x += delta.x * w, so offset is the same after perspective divide
+        let shear = math::Mat4::from_row(&[
+            1.0, 0.0, 0.0, delta.x,
+            0.0, 1.0, 0.0, delta.y,
+            0.0, 0.0, 1.0,     0.0,
+            0.0, 0.0, 0.0,     1.0,
+        ]);
+        self.mat = shear * self.mat;
+        self.jitter = jitter;
+    }
+
+    pub fn jitter(&self) -> math::Vec2 {
+        self.jitter
+    }
+
     pub fn get_mat(&self) -> &math::Mat4 {
         &self.mat
     }
@@ -50,25 +167,75 @@ impl Frustum {
         self.near
     }
 
-    ///! judge is pt in frustum
-    pub fn contain(&self, pt: &math::Vec3) -> bool {
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// half of the horizontal field of view
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    /// planes of frustum in view space, as `(normal, d)` of `normal.dot(pt) + d = 0`
+    fn planes(&self) -> [(math::Vec3, f32); 6] {
+        if let Some((left, right, bottom, top)) = self.ortho {
+            return [
+                (math::Vec3::new(1.0, 0.0, 0.0), -right),
+                (math::Vec3::new(-1.0, 0.0, 0.0), left),
+                (math::Vec3::new(0.0, 1.0, 0.0), -top),
+                (math::Vec3::new(0.0, -1.0, 0.0), bottom),
+                (math::Vec3::new(0.0, 0.0, 1.0), self.near),
+                (math::Vec3::new(0.0, 0.0, -1.0), -self.far),
+            ];
+        }
+
         let half_h = self.near * self.fovy.tan() / self.aspect;
         let h_fovy_cos = self.fovy.cos();
         let h_fovy_sin = self.fovy.sin();
 
-        /* Use plane formular `A(x-x0)+B(y-y0)+C(z-z0)=0` and here coordinate origin is on planes, so (x0, y0, z0) = (0, 0, 0), so use `Ax+By+Cz=0`.
+        /* Use plane formular `A(x-x0)+B(y-y0)+C(z-z0)=0` and here coordinate origin is on side planes, so (x0, y0, z0) = (0, 0, 0), so use `Ax+By+Cz=0`.
             The normal of plane `(A, B, C)` must point from the inside of frustum to outside.
             Then put pt into formular and if result >= 0, pt is at out side of plane.
         */
-        !(math::Vec3::new(h_fovy_cos, 0.0, h_fovy_sin).dot(pt) >= 0.0   // right plane
-            || math::Vec3::new(-h_fovy_cos, 0.0, h_fovy_sin).dot(pt) >= 0.0 // left plane
-            || math::Vec3::new(0.0, self.near, half_h).dot(pt) >= 0.0   // top plane
-            || math::Vec3::new(0.0, -self.near, half_h).dot(pt) >= 0.0  // bottom plane
-            || pt.z >= -self.near // near plane
-            || pt.z <= -self.far) // far plane
+        [
+            (math::Vec3::new(h_fovy_cos, 0.0, h_fovy_sin), 0.0), // right plane
+            (math::Vec3::new(-h_fovy_cos, 0.0, h_fovy_sin), 0.0), // left plane
+            (math::Vec3::new(0.0, self.near, half_h), 0.0),      // top plane
+            (math::Vec3::new(0.0, -self.near, half_h), 0.0),     // bottom plane
+            (math::Vec3::new(0.0, 0.0, 1.0), self.near),         // near plane
+            (math::Vec3::new(0.0, 0.0, -1.0), -self.far),        // far plane
+        ]
+    }
+
+    ///! judge is pt in frustum
+    pub fn contain(&self, pt: &math::Vec3) -> bool {
+        self.planes()
+            .iter()
+            .all(|(normal, d)| normal.dot(pt) + d < 0.0)
+    }
+
+    /// judge is a box(8 corners in view space) intersect with frustum.
+    /// It is conservative: may return true for some boxes near the frustum edges
+    pub fn intersect_box(&self, corners: &[math::Vec3; 8]) -> bool {
+        self.intersect_points(corners)
+    }
+
+    /// judge is the convex hull of points(in view space) intersect with frustum, conservative as `intersect_box`
+    pub fn intersect_points(&self, points: &[math::Vec3]) -> bool {
+        !self
+            .planes()
+            .iter()
+            .any(|(normal, d)| points.iter().all(|pt| normal.dot(pt) + d >= 0.0))
     }
 }
 
+/// max pitch angle in degree used by `Camera::rotate_delta`
+pub const MAX_PITCH: f32 = 89.0;
+
 pub struct Camera {
     frustum: Frustum,
     position: math::Vec3,
@@ -80,8 +247,17 @@ pub struct Camera {
 
 impl Camera {
     pub fn new(near: f32, far: f32, aspect: f32, fovy: f32) -> Self {
+        Self::from_frustum(Frustum::new(near, far, aspect, fovy))
+    }
+
+    /// camera with orthographic projection, see `Frustum::new_ortho`
+    pub fn new_ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        Self::from_frustum(Frustum::new_ortho(left, right, bottom, top, near, far))
+    }
+
+    pub fn from_frustum(frustum: Frustum) -> Self {
         Self {
-            frustum: Frustum::new(near, far, aspect, fovy),
+            frustum,
             position: math::Vec3::new(0.0, 0.0, 0.0),
             view_mat: math::Mat4::identity(),
             rotation: math::Vec3::zero(),
@@ -93,6 +269,11 @@ impl Camera {
         &self.frustum
     }
 
+    /// see `Frustum::set_jitter`
+    pub fn set_jitter(&mut self, jitter: math::Vec2) {
+        self.frustum.set_jitter(jitter);
+    }
+
     pub fn move_to(&mut self, position: math::Vec3) {
         self.position = position;
         self.recalc_view_mat();
@@ -129,15 +310,93 @@ impl Camera {
         self.rotation = math::Vec3::new(x, y, z);
     }
 
+    /// move camera back along the current view direction until the box fills the frustum
+    pub fn fit_aabb(&mut self, aabb: &math::Aabb) {
+        let radius = aabb.size().length() * 0.5;
+        let half_w_fov = self.frustum.fovy();
+        let half_h_fov = (half_w_fov.tan() / self.frustum.aspect()).atan();
+        let distance =
+            (radius / half_w_fov.min(half_h_fov).sin()).max(radius + self.frustum.near());
+
+        let (_, _, forward) = self.basis();
+        self.move_to(aabb.center() - forward * distance);
+    }
+
+    /// pixel space bounding rectangle of the projected box, clamped in viewport.
+    /// Return `None` if the box is fully behind camera
+    pub fn project_aabb(
+        &self,
+        aabb: &math::Aabb,
+        model: &math::Mat4,
+        viewport: &Viewport,
+    ) -> Option<Viewport> {
+        let mvp = *self.frustum.get_mat() * self.view_mat * *model;
+        let clips = aabb
+            .corners()
+            .map(|corner| mvp * math::Vec4::from_vec3(&corner, 1.0));
+        if clips.iter().all(|clip| clip.w <= 0.0) {
+            return None;
+        }
+
+        let viewport_min = math::Vec2::new(viewport.x as f32, viewport.y as f32);
+        let viewport_max = math::Vec2::new(
+            (viewport.x + viewport.w as i32) as f32,
+            (viewport.y + viewport.h as i32) as f32,
+        );
+        // corners behind camera project to infinity, so the box covers the whole viewport
+        let (min, max) = if clips.iter().any(|clip| clip.w <= 0.0) {
+            (viewport_min, viewport_max)
+        } else {
+            clips.iter().fold(
+                (
+                    math::Vec2::new(f32::MAX, f32::MAX),
+                    math::Vec2::new(f32::MIN, f32::MIN),
+                ),
+                |(min, max), clip| {
+                    let screen =
+                        viewport.transform(&math::Vec2::new(clip.x / clip.w, clip.y / clip.w));
+                    (
+                        math::Vec2::new(min.x.min(screen.x), min.y.min(screen.y)),
+                        math::Vec2::new(max.x.max(screen.x), max.y.max(screen.y)),
+                    )
+                },
+            )
+        };
+
+        let min_x = min.x.floor().clamp(viewport_min.x, viewport_max.x);
+        let min_y = min.y.floor().clamp(viewport_min.y, viewport_max.y);
+        let max_x = max.x.ceil().clamp(viewport_min.x, viewport_max.x);
+        let max_y = max.y.ceil().clamp(viewport_min.y, viewport_max.y);
+        Some(Viewport {
+            x: min_x as i32,
+            y: min_y as i32,
+            w: (max_x - min_x) as u32,
+            h: (max_y - min_y) as u32,
+        })
+    }
+
     pub fn set_rotation(&mut self, rotation: math::Vec3) {
         self.rotation = rotation;
         self.recalc_view_mat();
     }
 
+    /// rotate by mouse drag, `dx` yaws and `dy` pitches, pitch is clamped in `[-MAX_PITCH, MAX_PITCH]` to avoid flipping
+    pub fn rotate_delta(&mut self, dx: f32, dy: f32, sensitivity: f32) {
+        let max_pitch = MAX_PITCH.to_radians();
+        self.rotation.y -= dx * sensitivity;
+        self.rotation.x = (self.rotation.x - dy * sensitivity).clamp(-max_pitch, max_pitch);
+        self.recalc_view_mat();
+    }
+
+    /// camera yaws(`rotation.y`) around world Y axis, then pitches(`rotation.x`) and rolls(`rotation.z`) around its own axes,
+    /// so pitching after yaw never rolls the view
     fn recalc_view_mat(&mut self) {
-        let rotation = math::create_eular_rotate_xyz(&-self.rotation);
+        let rotation = math::create_eular_rotate_z(-self.rotation.z)
+            * math::create_eular_rotate_x(-self.rotation.x)
+            * math::create_eular_rotate_y(-self.rotation.y);
         self.view_mat = rotation * math::create_translate(&-self.position);
-        self.view_dir = (rotation * math::Vec4::new(0.0, 0.0, -1.0, 1.0)).truncated_to_vec3();
+        let (_, _, forward) = self.basis();
+        self.view_dir = forward;
     }
 
     pub fn get_rotation(&self) -> &math::Vec3 {
@@ -151,4 +410,166 @@ impl Camera {
     pub fn view_dir(&self) -> &math::Vec3 {
         &self.view_dir
     }
+
+    /// (right, up, forward) of camera in world space
+    pub fn basis(&self) -> (math::Vec3, math::Vec3, math::Vec3) {
+        // rows of view matrix are camera's right, up and back direction
+        let row = |y| {
+            math::Vec3::new(
+                self.view_mat.get(0, y),
+                self.view_mat.get(1, y),
+                self.view_mat.get(2, y),
+            )
+        };
+        (row(0), row(1), -row(2))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ortho_frustum_contain() {
+        let camera = Camera::new_ortho(-2.0, 2.0, -1.0, 1.0, 1.0, 10.0);
+        let frustum = camera.get_frustum();
+        assert!(frustum.is_ortho());
+        assert_eq!(frustum.aspect(), 2.0);
+        assert!(frustum.contain(&math::Vec3::new(1.9, -0.9, -9.0)));
+        assert!(!frustum.contain(&math::Vec3::new(2.1, 0.0, -5.0)));
+        assert!(!frustum.contain(&math::Vec3::new(0.0, 1.1, -5.0)));
+        assert!(!frustum.contain(&math::Vec3::new(0.0, 0.0, -0.5)));
+        assert!(!Camera::new(1.0, 10.0, 1.0, 30f32.to_radians())
+            .get_frustum()
+            .is_ortho());
+    }
+
+    #[test]
+    fn far_plane_map_to_far_end_of_z_range() {
+        for z_range in [NdcZRange::NegOneToOne, NdcZRange::ZeroToOne] {
+            let frustum = Frustum::new_with_z_range(1.0, 10.0, 1.0, 45f32.to_radians(), z_range);
+            assert_eq!(frustum.z_range(), Some(z_range));
+            let (near_end, far_end) = z_range.bounds();
+            for (z, expected) in [(-1.0, near_end), (-10.0, far_end)] {
+                let clip = *frustum.get_mat() * math::Vec4::new(0.5, -0.5, z, 1.0);
+                assert!(clip.w > 0.0);
+                assert!((clip.z / clip.w - expected).abs() < 1e-5);
+            }
+            let clip = *frustum.get_mat() * math::Vec4::new(0.0, 0.0, -5.0, 1.0);
+            let ndc_z = clip.z / clip.w;
+            assert!(ndc_z > near_end && ndc_z < far_end);
+        }
+    }
+
+    #[test]
+    fn fov_axis() {
+        let fov = 30f32.to_radians();
+        let mat = |aspect: f32, axis: FovAxis| {
+            *Frustum::new_with_fov_axis(1.0, 10.0, aspect, fov, axis).get_mat()
+        };
+        assert_eq!(mat(1.0, FovAxis::Vertical), mat(1.0, FovAxis::Horizontal));
+        assert_ne!(mat(2.0, FovAxis::Vertical), mat(2.0, FovAxis::Horizontal));
+
+        // point on the edge of field of view along the axis maps to the edge of NDC
+        let aspect = 16.0 / 9.0;
+        let edge = math::Vec4::new(fov.tan() * 5.0, fov.tan() * 5.0, -5.0, 1.0);
+        let clip = mat(aspect, FovAxis::Vertical) * edge;
+        assert!((clip.y / clip.w - 1.0).abs() < 1e-5);
+        let clip = mat(aspect, FovAxis::Horizontal) * edge;
+        assert!((clip.x / clip.w - 1.0).abs() < 1e-5);
+        assert!(
+            (Frustum::new_with_fov_axis(1.0, 10.0, aspect, fov, FovAxis::Vertical).fovy()
+                - FovAxis::Vertical.to_horizontal(fov, aspect))
+            .abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn fit_aabb_in_ndc() {
+        let mut camera = Camera::new(1.0, 100.0, 4.0 / 3.0, 30f32.to_radians());
+        camera.set_rotation(math::Vec3::new(0.0, 40f32.to_radians(), 0.0));
+        let aabb = math::Aabb::new(
+            math::Vec3::new(5.0, 0.0, 5.0),
+            math::Vec3::new(6.0, 1.0, 6.0),
+        );
+
+        camera.fit_aabb(&aabb);
+
+        for corner in aabb.corners() {
+            let view = *camera.view_mat() * math::Vec4::from_vec3(&corner, 1.0);
+            assert!(-view.z > camera.get_frustum().near() && -view.z < camera.get_frustum().far());
+            let clip = *camera.get_frustum().get_mat() * view;
+            assert!(clip.w > 0.0);
+            assert!((clip.x / clip.w).abs() <= 1.0);
+            assert!((clip.y / clip.w).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn rotate_by_mouse_delta() {
+        let mut camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+
+        camera.rotate_delta(10.0, 0.0, 0.01);
+        assert!((camera.get_rotation().y + 0.1).abs() < 1e-6);
+        assert_eq!(camera.get_rotation().x, 0.0);
+
+        camera.rotate_delta(0.0, -1000.0, 0.01);
+        assert!((camera.get_rotation().x - MAX_PITCH.to_radians()).abs() < 1e-6);
+        camera.rotate_delta(0.0, 1000.0, 0.01);
+        assert!((camera.get_rotation().x + MAX_PITCH.to_radians()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn basis_after_rotation() {
+        let mut camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+        camera.set_rotation(math::Vec3::new(0.0, 90f32.to_radians(), 0.0));
+
+        let (right, up, forward) = camera.basis();
+        assert!((forward - math::Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((up - math::Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+        assert!((right - math::Vec3::new(0.0, 0.0, -1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn basis_after_yaw_then_pitch() {
+        let mut camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+        let pitch = 30f32.to_radians();
+        camera.rotate_delta(-90f32.to_radians() / 0.01, 0.0, 0.01);
+        camera.rotate_delta(0.0, -pitch / 0.01, 0.01);
+
+        // no roll, right stays horizontal
+        let (right, up, forward) = camera.basis();
+        let expect_forward = math::Vec3::new(-pitch.cos(), pitch.sin(), 0.0);
+        assert!((forward - expect_forward).length() < 1e-5);
+        assert!((up - math::Vec3::new(pitch.sin(), pitch.cos(), 0.0)).length() < 1e-5);
+        assert!((right - math::Vec3::new(0.0, 0.0, -1.0)).length() < 1e-5);
+        assert!((*camera.view_dir() - expect_forward).length() < 1e-5);
+    }
+
+    #[test]
+    fn project_aabb_in_viewport() {
+        let mut camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+        let viewport = Viewport {
+            x: 0,
+            y: 0,
+            w: 64,
+            h: 64,
+        };
+        let aabb = math::Aabb::new(
+            math::Vec3::new(-0.5, -0.5, -0.5),
+            math::Vec3::new(0.5, 0.5, 0.5),
+        );
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -5.0));
+
+        let rect = camera.project_aabb(&aabb, &model, &viewport).unwrap();
+        assert!(rect.w > 0 && rect.h > 0);
+        assert!(rect.w < viewport.w && rect.h < viewport.h);
+        assert!(rect.x >= 0 && rect.x as u32 + rect.w <= viewport.w);
+        assert!(rect.y >= 0 && rect.y as u32 + rect.h <= viewport.h);
+
+        // turn around, box is behind camera
+        camera.set_rotation(math::Vec3::new(0.0, 180f32.to_radians(), 0.0));
+        assert!(camera.project_aabb(&aabb, &model, &viewport).is_none());
+    }
 }
@@ -1,50 +1,177 @@
+use std::cell::OnceCell;
+
 use crate::{
     camera,
-    image::{ColorAttachment, DepthAttachment},
+    image::{ColorAttachment, DepthAttachment, IdAttachment, RgbaColorAttachment},
     line::Line,
     math,
-    renderer::{self, rasterize_line, should_cull, FaceCull, FrontFace},
+    renderer::{
+        self, change_vertex, is_front_face_screen, rasterize_line, should_cull, should_cull_screen,
+        snap_to_subpixel, BlendMode, CullMethod, DepthRange, FaceCull, FrontFace, PixelWrite,
+        RenderTarget, TriangleCallback, TriangleInfo, DEFAULT_SUBPIXEL_BITS,
+    },
     scanline::Trapezoid,
     scanline::*,
-    shader::{self, Shader, Uniforms, Vertex},
+    shader::{self, Attributes, FragmentContext, Shader, Uniforms, Vertex},
     texture::TextureStorage,
 };
 
 pub struct Renderer {
-    color_attachment: ColorAttachment,
-    depth_attachment: DepthAttachment,
+    target: RenderTarget,
+    // the presented buffer when double buffer enabled
+    present_attachment: Option<ColorAttachment>,
     camera: camera::Camera,
     viewport: renderer::Viewport,
     shader: Shader,
     uniforms: Uniforms,
     front_face: FrontFace,
     cull: FaceCull,
+    cull_method: CullMethod,
+    depth_range: DepthRange,
+    blend_mode: BlendMode,
 
-    cliped_triangles: Vec<Vertex>,
     enable_framework: bool,
+    back_face_framework_color: Option<math::Vec4>,
+    subpixel_bits: Option<u32>,
+    triangle_callback: Option<TriangleCallback>,
+    // id of the first triangle in next draw call
+    next_triangle_id: u32,
+    supersample_scale: u32,
+    // downsampled image when supersampling, reset whenever color changes
+    resolved: OnceCell<ColorAttachment>,
 }
 
-enum RasterizeResult {
-    Ok,
-    Discard,
-    GenerateNewFace,
+/// position of one vertex after each pipeline stage, see `Renderer::debug_trace_vertex`
+#[derive(Clone, Copy, Debug)]
+pub struct VertexTrace {
+    pub world: math::Vec4,
+    pub view: math::Vec4,
+    /// vertex is in front of near plane and would be clipped
+    pub near_clipped: bool,
+    pub clip: math::Vec4,
+    /// x, y after perspective divide, z keeps view space z as the rasterizer does
+    pub ndc: math::Vec3,
+    /// x, y in screen space, z keeps view space z
+    pub screen: math::Vec3,
 }
 
 impl renderer::RendererInterface for Renderer {
     fn clear(&mut self, color: &math::Vec4) {
-        self.color_attachment.clear(color);
+        self.resolved.take();
+        self.target.clear_color(color);
     }
 
     fn get_canva_width(&self) -> u32 {
-        self.color_attachment.width()
+        self.target.width() / self.supersample_scale
     }
 
     fn get_canva_height(&self) -> u32 {
-        self.color_attachment.height()
+        self.target.height() / self.supersample_scale
+    }
+
+    fn set_viewport(&mut self, viewport: renderer::Viewport) {
+        self.viewport = viewport;
+    }
+
+    fn get_viewport(&self) -> &renderer::Viewport {
+        &self.viewport
+    }
+
+    fn bind_target(&mut self, target: RenderTarget) -> RenderTarget {
+        let old_target = std::mem::replace(&mut self.target, target);
+        self.supersample_scale = 1;
+        self.resolved.take();
+        self.viewport = renderer::Viewport {
+            x: 0,
+            y: 0,
+            w: self.target.width(),
+            h: self.target.height(),
+        };
+        if self.present_attachment.is_some() {
+            self.present_attachment = Some(ColorAttachment::new(
+                self.target.width(),
+                self.target.height(),
+            ));
+        }
+        old_target
+    }
+
+    fn get_target(&self) -> &RenderTarget {
+        &self.target
     }
 
     fn get_rendered_image(&self) -> &[u8] {
-        self.color_attachment.data()
+        let image = match &self.present_attachment {
+            Some(present_attachment) => present_attachment,
+            None => match &self.target.color {
+                Some(color_attachment) => color_attachment,
+                None => return &[],
+            },
+        };
+        if self.supersample_scale == 1 {
+            image.data()
+        } else {
+            self.resolved
+                .get_or_init(|| image.downsample(self.supersample_scale))
+                .data()
+        }
+    }
+
+    fn set_supersample_scale(&mut self, scale: u32) {
+        let scale = scale.max(1);
+        let w = self.get_canva_width() * scale;
+        let h = self.get_canva_height() * scale;
+        self.supersample_scale = scale;
+        self.target = RenderTarget {
+            color: self
+                .target
+                .color
+                .as_ref()
+                .map(|_| ColorAttachment::new(w, h)),
+            depth: self
+                .target
+                .depth
+                .as_ref()
+                .map(|_| DepthAttachment::new(w, h)),
+            id: self.target.id.as_ref().map(|_| IdAttachment::new(w, h)),
+            rgba: self
+                .target
+                .rgba
+                .as_ref()
+                .map(|_| RgbaColorAttachment::new(w, h)),
+        };
+        if self.present_attachment.is_some() {
+            self.present_attachment = Some(ColorAttachment::new(w, h));
+        }
+        self.resolved.take();
+    }
+
+    fn get_supersample_scale(&self) -> u32 {
+        self.supersample_scale
+    }
+
+    fn enable_double_buffer(&mut self) {
+        self.resolved.take();
+        if self.present_attachment.is_none() {
+            self.present_attachment = Some(ColorAttachment::new(
+                self.target.width(),
+                self.target.height(),
+            ));
+        }
+    }
+
+    fn disable_double_buffer(&mut self) {
+        self.present_attachment = None;
+        self.resolved.take();
+    }
+
+    fn swap_buffers(&mut self) {
+        self.resolved.take();
+        if let (Some(present_attachment), Some(color_attachment)) =
+            (&mut self.present_attachment, &mut self.target.color)
+        {
+            std::mem::swap(present_attachment, color_attachment);
+        }
     }
 
     fn draw_triangle(
@@ -53,30 +180,62 @@ impl renderer::RendererInterface for Renderer {
         vertices: &[Vertex],
         texture_storage: &TextureStorage,
     ) {
+        self.resolved.take();
+        // premultiply once per draw, clipping needs view space so projection is applied later
+        let model_view = *self.camera.view_mat() * *model;
         for i in 0..vertices.len() / 3_usize {
-            // convert 3D coordination to Homogeneous coordinates
-            let vertices = [vertices[i * 3], vertices[1 + i * 3], vertices[2 + i * 3]];
-
-            match self.rasterize_trianlge(model, vertices, texture_storage) {
-                RasterizeResult::Ok | RasterizeResult::Discard => {}
-                RasterizeResult::GenerateNewFace => {
-                    for i in 0..self.cliped_triangles.len() / 3 {
-                        let vertices = [
-                            self.cliped_triangles[i * 3],
-                            self.cliped_triangles[1 + i * 3],
-                            self.cliped_triangles[2 + i * 3],
-                        ];
-                        match self.rasterize_trianlge(model, vertices, texture_storage) {
-                            RasterizeResult::Ok => {}
-                            RasterizeResult::Discard | RasterizeResult::GenerateNewFace => {
-                                panic!("discard or generate new face from clipped face")
-                            }
-                        }
-                        self.cliped_triangles.clear();
-                    }
-                }
-            }
+            let vertices = [vertices[i * 3], vertices[1 + i * 3], vertices[2 + i * 3]]
+                .map(|v| change_vertex(&self.shader, &self.uniforms, model, &v, texture_storage));
+            self.draw_changed_triangle(i, &model_view, vertices, texture_storage);
+        }
+        self.next_triangle_id = self
+            .next_triangle_id
+            .wrapping_add((vertices.len() / 3) as u32);
+    }
+
+    fn draw_triangle_indices(
+        &mut self,
+        model: &math::Mat4,
+        vertices: &[Vertex],
+        triangles: &[[u32; 3]],
+        texture_storage: &TextureStorage,
+    ) {
+        self.resolved.take();
+        // premultiply once per draw, clipping needs view space so projection is applied later
+        let model_view = *self.camera.view_mat() * *model;
+        // shared vertices are changed only once
+        let changed: Vec<Vertex> = vertices
+            .iter()
+            .map(|v| change_vertex(&self.shader, &self.uniforms, model, v, texture_storage))
+            .collect();
+        for (i, triangle) in triangles.iter().enumerate() {
+            let vertices = triangle.map(|index| changed[index as usize]);
+            self.draw_changed_triangle(i, &model_view, vertices, texture_storage);
         }
+        self.next_triangle_id = self.next_triangle_id.wrapping_add(triangles.len() as u32);
+    }
+
+    fn draw_line_3d(
+        &mut self,
+        model: &math::Mat4,
+        start: Vertex,
+        end: Vertex,
+        texture_storage: &TextureStorage,
+    ) {
+        self.resolved.take();
+        let viewport = self.raster_viewport();
+        renderer::draw_line_3d(
+            model,
+            [start, end],
+            &self.shader,
+            &self.uniforms,
+            texture_storage,
+            &mut self.target,
+            &self.camera,
+            &viewport,
+            self.depth_range,
+            self.blend_mode,
+        );
     }
 
     fn get_shader(&mut self) -> &mut shader::Shader {
@@ -88,7 +247,9 @@ impl renderer::RendererInterface for Renderer {
     }
 
     fn clear_depth(&mut self) {
-        self.depth_attachment.clear(f32::MIN);
+        if let Some(depth_attachment) = &mut self.target.depth {
+            depth_attachment.clear(f32::MAX);
+        }
     }
 
     fn get_camera(&mut self) -> &mut camera::Camera {
@@ -115,6 +276,54 @@ impl renderer::RendererInterface for Renderer {
         self.cull
     }
 
+    fn set_cull_method(&mut self, method: CullMethod) {
+        self.cull_method = method;
+    }
+
+    fn get_cull_method(&self) -> CullMethod {
+        self.cull_method
+    }
+
+    fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    fn get_blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    fn set_depth_range(&mut self, near: f32, far: f32) {
+        self.depth_range = DepthRange { near, far };
+    }
+
+    fn get_depth_range(&self) -> DepthRange {
+        self.depth_range
+    }
+
+    fn set_triangle_callback(&mut self, callback: TriangleCallback) {
+        self.triangle_callback = Some(callback);
+    }
+
+    fn reset_triangle_callback(&mut self) {
+        self.triangle_callback = None;
+    }
+
+    fn set_subpixel_bits(&mut self, bits: Option<u32>) {
+        self.subpixel_bits = bits;
+    }
+
+    fn get_subpixel_bits(&self) -> Option<u32> {
+        self.subpixel_bits
+    }
+
+    fn set_back_face_framework_color(&mut self, color: Option<math::Vec4>) {
+        self.back_face_framework_color = color;
+    }
+
+    fn get_back_face_framework_color(&self) -> Option<math::Vec4> {
+        self.back_face_framework_color
+    }
+
     fn enable_framework(&mut self) {
         self.enable_framework = true;
     }
@@ -123,6 +332,23 @@ impl renderer::RendererInterface for Renderer {
         self.enable_framework = false;
     }
 
+    fn enable_id_buffer(&mut self) {
+        if self.target.id.is_none() {
+            self.target.id = Some(IdAttachment::new(self.target.width(), self.target.height()));
+        }
+    }
+
+    fn disable_id_buffer(&mut self) {
+        self.target.id = None;
+    }
+
+    fn clear_id(&mut self) {
+        if let Some(id_attachment) = &mut self.target.id {
+            id_attachment.clear();
+        }
+        self.next_triangle_id = 0;
+    }
+
     fn toggle_framework(&mut self) {
         self.enable_framework = !self.enable_framework;
     }
@@ -131,84 +357,145 @@ impl renderer::RendererInterface for Renderer {
 impl Renderer {
     pub fn new(w: u32, h: u32, camera: camera::Camera) -> Self {
         Self {
-            color_attachment: ColorAttachment::new(w, h),
-            depth_attachment: DepthAttachment::new(w, h),
+            target: RenderTarget::new(w, h),
+            present_attachment: None,
             camera,
             viewport: renderer::Viewport { x: 0, y: 0, w, h },
             shader: Default::default(),
             uniforms: Default::default(),
             front_face: FrontFace::CW,
             cull: FaceCull::None,
-            cliped_triangles: Vec::new(),
+            cull_method: CullMethod::ViewDir,
+            depth_range: DepthRange::default(),
+            blend_mode: BlendMode::None,
             enable_framework: false,
+            back_face_framework_color: None,
+            subpixel_bits: Some(DEFAULT_SUBPIXEL_BITS),
+            triangle_callback: None,
+            next_triangle_id: 0,
+            supersample_scale: 1,
+            resolved: OnceCell::new(),
         }
     }
 
-    fn rasterize_trianlge(
+    // viewport in internal target, which is scaled when supersampling
+    fn raster_viewport(&self) -> renderer::Viewport {
+        self.viewport.scaled(self.supersample_scale)
+    }
+
+    /// run one vertex(as output of vertex changing) through model, view, projection,
+    /// near clip test, perspective divide and viewport transform, recording position of each stage
+    pub fn debug_trace_vertex(&self, model: &math::Mat4, vertex: &Vertex) -> VertexTrace {
+        let frustum = self.camera.get_frustum();
+        let world = *model * vertex.position;
+        let view = *self.camera.view_mat() * world;
+        let near_clipped = view.z > -frustum.near();
+        let clip = *frustum.get_mat() * view;
+        let z = view.z;
+        let ndc = math::Vec3::new(clip.x / clip.w, clip.y / clip.w, z);
+        let screen = self.viewport.transform(&math::Vec2::new(ndc.x, ndc.y));
+
+        VertexTrace {
+            world,
+            view,
+            near_clipped,
+            clip,
+            ndc,
+            screen: math::Vec3::new(screen.x, screen.y, z),
+        }
+    }
+
+    /// cull, clip and rasterize triangle `index` of a draw call, `vertices` are after vertex changing
+    fn draw_changed_triangle(
         &mut self,
-        model: &math::Mat4,
+        index: usize,
+        model_view: &math::Mat4,
         mut vertices: [Vertex; 3],
         texture_storage: &TextureStorage,
-    ) -> RasterizeResult {
-        // call vertex changing function to change vertex position and set attribtues
-        for v in &mut vertices {
-            *v = self
-                .shader
-                .call_vertex_changing(v, &self.uniforms, texture_storage);
-        }
+    ) {
+        self.shader.interp_modes.flatten(&mut vertices);
 
-        // Model transform
+        // Model View transform
         for v in &mut vertices {
-            v.position = *model * v.position;
+            v.position = *model_view * v.position;
         }
 
-        // Face Cull
-        if should_cull(
-            &vertices.map(|v| v.position.truncated_to_vec3()),
-            self.camera.view_dir(),
-            self.front_face,
-            self.cull,
-        ) {
-            return RasterizeResult::Discard;
+        // Face Cull in view space, camera looks at -z
+        if self.cull_method == CullMethod::ViewDir
+            && should_cull(
+                &vertices.map(|v| v.position.truncated_to_vec3()),
+                &-*math::Vec3::z_axis(),
+                self.front_face,
+                self.cull,
+            )
+        {
+            return;
         }
 
-        // view transform
-        for v in &mut vertices {
-            v.position = *self.camera.view_mat() * v.position;
+        // frustum clip, skip triangles outside one of the planes
+        if !self
+            .camera
+            .get_frustum()
+            .intersect_points(&vertices.map(|v| v.position.truncated_to_vec3()))
+        {
+            return;
         }
 
-        // frustum clip
-        if vertices.iter().all(|v| {
-            !self
-                .camera
-                .get_frustum()
-                .contain(&v.position.truncated_to_vec3())
-        }) {
-            return RasterizeResult::Discard;
+        // near plane clip, clipped faces are already in view space
+        let near = self.camera.get_frustum().near();
+        if vertices.iter().any(|v| v.position.z > -near) {
+            let (face1, face2) = crate::scanline::near_plane_clip(&vertices, near);
+            self.far_plane_clip_and_rasterize(index, face1, true, texture_storage);
+            if let Some(face) = face2 {
+                self.far_plane_clip_and_rasterize(index, face, true, texture_storage);
+            }
+        } else {
+            self.far_plane_clip_and_rasterize(index, vertices, false, texture_storage);
         }
+    }
 
-        // near plane clip
-        if vertices
-            .iter()
-            .any(|v| v.position.z > self.camera.get_frustum().near())
-        {
-            let (face1, face2) =
-                crate::scanline::near_plane_clip(&vertices, self.camera.get_frustum().near());
-            self.cliped_triangles.extend(face1.iter());
+    /// clip triangle in view space against far plane, then rasterize remaining faces
+    fn far_plane_clip_and_rasterize(
+        &mut self,
+        index: usize,
+        vertices: [Vertex; 3],
+        clipped: bool,
+        texture_storage: &TextureStorage,
+    ) {
+        let far = self.camera.get_frustum().far();
+        // totally beyond far plane, nothing is left after clipping
+        if vertices.iter().all(|v| v.position.z < -far) {
+            return;
+        }
+        if vertices.iter().any(|v| v.position.z < -far) {
+            let (face1, face2) = crate::scanline::far_plane_clip(&vertices, far);
+            self.rasterize_trianlge(index, face1, true, texture_storage);
             if let Some(face) = face2 {
-                self.cliped_triangles.extend(face.iter());
+                self.rasterize_trianlge(index, face, true, texture_storage);
             }
-            return RasterizeResult::GenerateNewFace;
+        } else {
+            self.rasterize_trianlge(index, vertices, clipped, texture_storage);
         }
+    }
 
-        // project transform
+    /// rasterize a triangle in view space which is in front of near plane,
+    /// `index` and `clipped` are reported to triangle callback
+    fn rasterize_trianlge(
+        &mut self,
+        index: usize,
+        mut vertices: [Vertex; 3],
+        clipped: bool,
+        texture_storage: &TextureStorage,
+    ) {
+        // project transform, keep view space z
+        let view_z = vertices.map(|v| v.position.z);
         for v in &mut vertices {
             v.position = *self.camera.get_frustum().get_mat() * v.position;
         }
 
         // save truely z into v.position.z
-        for v in &mut vertices {
-            v.position.z = -v.position.w * self.camera.get_frustum().near();
+        for (v, z) in vertices.iter_mut().zip(view_z) {
+            v.position.z = z;
         }
 
         // perspective divide
@@ -220,14 +507,66 @@ impl Renderer {
 
         // Viewport transform
         for v in &mut vertices {
-            v.position.x = (v.position.x + 1.0) * 0.5 * (self.viewport.w as f32 - 1.0)
-                + self.viewport.x as f32;
-            v.position.y = self.viewport.h as f32
-                - (v.position.y + 1.0) * 0.5 * (self.viewport.h as f32 - 1.0)
-                + self.viewport.y as f32;
+            let screen = self
+                .raster_viewport()
+                .transform(&v.position.truncated_to_vec2());
+            v.position.x = screen.x;
+            v.position.y = screen.y;
+        }
+
+        // snap to subpixel grid so coverage is stable when vertices move slowly
+        if let Some(bits) = self.subpixel_bits {
+            for v in &mut vertices {
+                v.position.x = snap_to_subpixel(v.position.x, bits);
+                v.position.y = snap_to_subpixel(v.position.y, bits);
+            }
+        }
+
+        // degenerate projection(like `0 / 0`) can't be rasterized
+        if vertices.iter().any(|v| {
+            !(v.position.x.is_finite() && v.position.y.is_finite() && v.position.z.is_finite())
+        }) {
+            return;
+        }
+
+        // Face Cull in screen space
+        if self.cull_method == CullMethod::ScreenArea
+            && should_cull_screen(
+                &vertices.map(|v| v.position.truncated_to_vec2()),
+                self.front_face,
+                self.cull,
+            )
+        {
+            return;
+        }
+
+        let id = self.next_triangle_id.wrapping_add(index as u32);
+
+        if let Some(callback) = &mut self.triangle_callback {
+            callback(&TriangleInfo::new(
+                index,
+                vertices.map(|v| v.position.truncated_to_vec2()),
+                clipped,
+            ));
         }
 
         if self.enable_framework {
+            let back_face_color = self.back_face_framework_color.filter(|_| {
+                !is_front_face_screen(
+                    &vertices.map(|v| v.position.truncated_to_vec2()),
+                    self.front_face,
+                )
+            });
+            let shader = &self.shader;
+            let shading =
+                |attr: &Attributes,
+                 uniforms: &Uniforms,
+                 texture_storage: &TextureStorage,
+                 context: &FragmentContext| match back_face_color {
+                    Some(color) => Some(color),
+                    None => shader.call_pixel_shading(attr, uniforms, texture_storage, context),
+                };
+
             // draw line framework
             for i in 0..3 {
                 let mut v1 = vertices[i];
@@ -236,13 +575,25 @@ impl Renderer {
                 shader::vertex_rhw_init(&mut v1);
                 shader::vertex_rhw_init(&mut v2);
 
+                let depth_range = self.depth_range;
+                let frustum = self.camera.get_frustum();
+                let (near, far) = (frustum.near(), frustum.far());
+                let bounds = self
+                    .raster_viewport()
+                    .pixel_bounds(self.target.width(), self.target.height());
                 rasterize_line(
                     &mut Line::new(v1, v2),
-                    &self.shader.pixel_shading,
+                    &shading,
                     &self.uniforms,
                     texture_storage,
-                    &mut self.color_attachment,
-                    &mut self.depth_attachment,
+                    &mut self.target,
+                    |z| depth_range.map(z, near, far),
+                    PixelWrite {
+                        id: Some(id),
+                        blend_mode: self.blend_mode,
+                        bounds,
+                        may_discard: shader.may_discard(),
+                    },
                 );
             }
         } else {
@@ -252,65 +603,347 @@ impl Renderer {
 
             // rasterization trapeziods
             if let Some(trap) = trap1 {
-                self.draw_trapezoid(trap, texture_storage);
+                self.draw_trapezoid(trap, id, texture_storage);
             }
             if let Some(trap) = trap2 {
-                self.draw_trapezoid(trap, texture_storage);
+                self.draw_trapezoid(trap, id, texture_storage);
             }
         }
-
-        RasterizeResult::Ok
     }
 
-    fn draw_trapezoid(&mut self, trap: &mut Trapezoid, texture_storage: &TextureStorage) {
-        let top = (trap.top.ceil().max(0.0)) as i32;
-        let bottom =
-            (trap.bottom.ceil()).min(self.color_attachment.height() as f32 - 1.0) as i32 - 1;
+    fn draw_trapezoid(&mut self, trap: &mut Trapezoid, id: u32, texture_storage: &TextureStorage) {
+        let (min, max) = self
+            .raster_viewport()
+            .pixel_bounds(self.target.width(), self.target.height());
+        // cover rows in [ceil(top), ceil(bottom)), with scanlines covering [ceil(left), ceil(right)) this is
+        // the top-left rule, so pixels on an edge shared by two triangles are only drawn once
+        let top = (trap.top.ceil().max(min.y)) as i32;
+        let bottom = (trap.bottom.ceil()).min(max.y + 1.0) as i32 - 1;
         let mut y = top as f32;
 
-        shader::vertex_rhw_init(&mut trap.left.v1);
-        shader::vertex_rhw_init(&mut trap.left.v2);
-        shader::vertex_rhw_init(&mut trap.right.v1);
-        shader::vertex_rhw_init(&mut trap.right.v2);
+        // orthographic projection keeps z and attributes linear in screen space
+        if !self.camera.get_frustum().is_ortho() {
+            let modes = &self.shader.interp_modes;
+            shader::vertex_rhw_init_with_modes(&mut trap.left.v1, modes);
+            shader::vertex_rhw_init_with_modes(&mut trap.left.v2, modes);
+            shader::vertex_rhw_init_with_modes(&mut trap.right.v1, modes);
+            shader::vertex_rhw_init_with_modes(&mut trap.right.v2, modes);
+        }
 
         while y <= bottom as f32 {
             let mut scanline = Scanline::from_trapezoid(trap, y);
-            self.draw_scanline(&mut scanline, texture_storage);
+            self.draw_scanline(&mut scanline, id, texture_storage);
             y += 1.0;
         }
     }
 
-    fn draw_scanline(&mut self, scanline: &mut Scanline, texture_storage: &TextureStorage) {
+    fn draw_scanline(
+        &mut self,
+        scanline: &mut Scanline,
+        id: u32,
+        texture_storage: &TextureStorage,
+    ) {
+        let (min, max) = self
+            .raster_viewport()
+            .pixel_bounds(self.target.width(), self.target.height());
         let vertex = &mut scanline.vertex;
         let y = scanline.y as u32;
-        while scanline.width > 0.0 {
-            let rhw = vertex.position.z;
-            let z = 1.0 / rhw;
-
-            let x = vertex.position.x;
+        let frustum = self.camera.get_frustum();
+        let (near, far) = (frustum.near(), frustum.far());
+        let ortho = frustum.is_ortho();
+
+        // scanline starts on a pixel, walk integer x instead of accumulated position
+        let mut x = vertex.position.x;
+        let end = (x + scanline.width).min(max.x + 1.0);
+        // skip pixels left of viewport at once, walking from far outside accumulates error
+        if x < min.x {
+            let skip = min.x - x;
+            let mut attributes_skip = scanline.step.attributes;
+            shader::attributes_foreach(&mut attributes_skip, |value| value * skip);
+            vertex.position += scanline.step.position * skip;
+            vertex.attributes = shader::attributes_add(&vertex.attributes, &attributes_skip);
+            x = min.x;
+        }
 
-            if x >= 0.0 && x < self.color_attachment.width() as f32 {
-                let x = x as u32;
-                if self.depth_attachment.get(x, y) <= z {
+        while x < end {
+            let (z, rhw) = if ortho {
+                (vertex.position.z, 1.0)
+            } else {
+                (1.0 / vertex.position.z, vertex.position.z)
+            };
+
+            let pixel_x = x as u32;
+            let depth = self.depth_range.map(z, near, far);
+            if self.target.depth_test(pixel_x, y, depth) {
+                // no need to shade for depth only target, unless fragment may be discarded
+                let color = if self.target.has_color() || self.shader.may_discard() {
                     let mut attr = vertex.attributes;
-                    shader::attributes_foreach(&mut attr, |value| value / rhw);
-                    // call pixel shading function to get shading color
-                    let color =
+                    if !ortho {
                         self.shader
-                            .call_pixel_shading(&attr, &self.uniforms, texture_storage);
-                    self.color_attachment.set(x, y, &color);
-                    self.depth_attachment.set(x, y, z);
+                            .interp_modes
+                            .foreach_perspective(&mut attr, |value| value / rhw);
+                    }
+                    // call pixel shading function to get shading color
+                    let context = FragmentContext::new(
+                        pixel_x,
+                        y,
+                        depth,
+                        z,
+                        rhw,
+                        self.target.get_depth(pixel_x, y),
+                    )
+                    .with_world_position(&attr);
+                    self.shader
+                        .call_pixel_shading(&attr, &self.uniforms, texture_storage, &context)
+                } else {
+                    Some(math::Vec4::zero())
+                };
+                // discarded fragment writes nothing
+                if let Some(color) = color {
+                    self.target.blend_color(pixel_x, y, &color, self.blend_mode);
+                    self.target.set_depth(pixel_x, y, depth);
+                    self.target.set_id(pixel_x, y, id);
                 }
             }
 
-            scanline.width -= 1.0;
+            x += 1.0;
             vertex.position += scanline.step.position;
-            vertex.attributes = shader::interp_attributes(
-                &vertex.attributes,
-                &scanline.step.attributes,
-                |value1, value2, _| value1 + value2,
-                0.0,
-            );
+            vertex.attributes =
+                shader::attributes_add(&vertex.attributes, &scanline.step.attributes);
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderer::RendererInterface;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 64;
+
+    fn create_renderer() -> Renderer {
+        let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let mut renderer = Renderer::new(WIDTH, HEIGHT, camera);
+        renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+        renderer.clear_depth();
+        renderer
+    }
+
+    fn triangle(positions: [math::Vec3; 3]) -> [Vertex; 3] {
+        positions.map(|p| Vertex::new(p, Default::default()))
+    }
+
+    fn drawn_pixel_count(renderer: &Renderer) -> usize {
+        renderer
+            .target
+            .depth
+            .as_ref()
+            .unwrap()
+            .data()
+            .iter()
+            .filter(|depth| **depth != f32::MAX)
+            .count()
+    }
+
+    #[test]
+    fn depth_range() {
+        let mut renderer = create_renderer();
+        renderer.set_depth_range(0.25, 0.5);
+        renderer.draw_triangle(
+            &math::Mat4::identity(),
+            &triangle([
+                math::Vec3::new(-1.0, -1.0, -3.0),
+                math::Vec3::new(1.0, -1.0, -3.0),
+                math::Vec3::new(0.0, 1.0, -8.0),
+            ]),
+            &TextureStorage::default(),
+        );
+
+        let written: Vec<f32> = renderer
+            .target
+            .depth
+            .as_ref()
+            .unwrap()
+            .data()
+            .iter()
+            .copied()
+            .filter(|depth| *depth != f32::MAX)
+            .collect();
+        assert!(!written.is_empty());
+        assert!(written.iter().all(|depth| (0.25..=0.5).contains(depth)));
+    }
+
+    #[test]
+    fn cull_method() {
+        // in plane x = 3 and facing +x, so camera at origin sees its back face,
+        // but its normal is perpendicular to view direction
+        let side_triangle = triangle([
+            math::Vec3::new(3.0, -1.0, -5.0),
+            math::Vec3::new(3.0, 0.0, -8.0),
+            math::Vec3::new(3.0, 1.0, -5.0),
+        ]);
+        let draw = |method, vertices: &[Vertex]| {
+            let mut renderer = create_renderer();
+            renderer.set_front_face(FrontFace::CCW);
+            renderer.set_face_cull(FaceCull::Back);
+            renderer.set_cull_method(method);
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                vertices,
+                &TextureStorage::default(),
+            );
+            drawn_pixel_count(&renderer)
+        };
+
+        assert!(draw(CullMethod::ViewDir, &side_triangle) > 0);
+        assert_eq!(draw(CullMethod::ScreenArea, &side_triangle), 0);
+
+        let reversed = [side_triangle[0], side_triangle[2], side_triangle[1]];
+        assert!(draw(CullMethod::ScreenArea, &reversed) > 0);
+    }
+
+    #[test]
+    fn double_buffer() {
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let green = math::Vec4::new(0.0, 1.0, 0.0, 1.0);
+        let mut renderer = create_renderer();
+        renderer.enable_double_buffer();
+
+        renderer.clear(&red);
+        renderer.swap_buffers();
+        renderer.clear(&green);
+        assert_eq!(renderer.get_rendered_image()[0..3], [255, 0, 0]);
+
+        renderer.swap_buffers();
+        assert_eq!(renderer.get_rendered_image()[0..3], [0, 255, 0]);
+
+        renderer.disable_double_buffer();
+        renderer.clear(&red);
+        assert_eq!(renderer.get_rendered_image()[0..3], [255, 0, 0]);
+    }
+
+    #[test]
+    fn draw_with_state() {
+        // CW triangles(back face when front face is CCW) in left and right half of screen
+        let left = triangle([
+            math::Vec3::new(-1.0, -0.5, -3.0),
+            math::Vec3::new(-0.5, 0.5, -3.0),
+            math::Vec3::new(-0.1, -0.5, -3.0),
+        ]);
+        let right = triangle([
+            math::Vec3::new(0.1, -0.5, -3.0),
+            math::Vec3::new(0.5, 0.5, -3.0),
+            math::Vec3::new(1.0, -0.5, -3.0),
+        ]);
+        let mut renderer = create_renderer();
+        renderer.set_front_face(FrontFace::CW);
+        renderer.set_face_cull(FaceCull::Front);
+
+        let texture_storage = TextureStorage::default();
+        let model = math::Mat4::identity();
+        renderer.draw_triangle_with_state(
+            &model,
+            &left,
+            &texture_storage,
+            FrontFace::CCW,
+            FaceCull::Back,
+        );
+        renderer.draw_triangle_with_state(
+            &model,
+            &right,
+            &texture_storage,
+            FrontFace::CCW,
+            FaceCull::None,
+        );
+
+        let drawn_in = |x_range: std::ops::Range<u32>| {
+            x_range
+                .flat_map(|x| (0..HEIGHT).map(move |y| (x, y)))
+                .filter(|(x, y)| renderer.target.depth.as_ref().unwrap().get(*x, *y) != f32::MAX)
+                .count()
+        };
+        assert_eq!(drawn_in(0..WIDTH / 2), 0);
+        assert!(drawn_in(WIDTH / 2..WIDTH) > 0);
+
+        assert!(matches!(renderer.get_front_face(), FrontFace::CW));
+        assert!(matches!(renderer.get_face_cull(), FaceCull::Front));
+    }
+
+    #[test]
+    fn depth_only_target() {
+        let mut renderer = create_renderer();
+        let old_target = renderer.bind_target(RenderTarget::depth_only(32, 32));
+        assert_eq!(old_target.width(), WIDTH);
+        assert_eq!(renderer.get_canva_width(), 32);
+
+        renderer.clear(&math::Vec4::new(1.0, 0.0, 0.0, 1.0));
+        renderer.clear_depth();
+        renderer.draw_triangle(
+            &math::Mat4::identity(),
+            &triangle([
+                math::Vec3::new(-1.0, -1.0, -3.0),
+                math::Vec3::new(1.0, -1.0, -3.0),
+                math::Vec3::new(0.0, 1.0, -3.0),
+            ]),
+            &TextureStorage::default(),
+        );
+
+        assert!(renderer.get_rendered_image().is_empty());
+        let depth = renderer.get_target().depth.as_ref().unwrap();
+        assert_eq!(depth.data().len(), 32 * 32);
+        assert!(depth.data().iter().any(|depth| *depth != f32::MAX));
+
+        renderer.bind_target(old_target);
+        assert_eq!(renderer.get_canva_width(), WIDTH);
+    }
+
+    #[test]
+    fn trace_vertex() {
+        let mut renderer = create_renderer();
+        renderer
+            .get_camera()
+            .move_to(math::Vec3::new(1.0, 0.5, 2.0));
+        renderer
+            .get_camera()
+            .set_rotation(math::Vec3::new(0.0, 0.3, 0.0));
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -3.0));
+        let vertex = Vertex::new(math::Vec3::new(0.2, -0.4, -1.0), Default::default());
+
+        let trace = renderer.debug_trace_vertex(&model, &vertex);
+
+        let frustum = renderer.camera.get_frustum();
+        let view = *renderer.camera.view_mat() * model * vertex.position;
+        let clip = *frustum.get_mat() * view;
+        let screen_x = (clip.x / clip.w + 1.0) * 0.5 * (WIDTH as f32 - 1.0);
+        let screen_y = HEIGHT as f32 - (clip.y / clip.w + 1.0) * 0.5 * (HEIGHT as f32 - 1.0);
+        assert_eq!(trace.view, view);
+        assert_eq!(trace.clip, clip);
+        assert!(!trace.near_clipped);
+        assert!((trace.screen.x - screen_x).abs() < 1e-4);
+        assert!((trace.screen.y - screen_y).abs() < 1e-4);
+        assert!((trace.screen.z - view.z).abs() < 1e-4);
+
+        let behind = Vertex::new(math::Vec3::new(1.0, 0.5, 5.0), Default::default());
+        assert!(
+            renderer
+                .debug_trace_vertex(&math::Mat4::identity(), &behind)
+                .near_clipped
+        );
+    }
+
+    #[test]
+    fn toggle_framework() {
+        let mut renderer = create_renderer();
+        let origin = renderer.enable_framework;
+        renderer.toggle_framework();
+        assert_ne!(renderer.enable_framework, origin);
+        renderer.toggle_framework();
+        assert_eq!(renderer.enable_framework, origin);
+
+        renderer.enable_framework();
+        assert!(renderer.enable_framework);
+        renderer.disable_framework();
+        assert!(!renderer.enable_framework);
+    }
+}
@@ -1,40 +1,219 @@
+use std::cell::OnceCell;
+
 use crate::{
     camera,
-    image::{ColorAttachment, DepthAttachment},
+    image::{ColorAttachment, DepthAttachment, IdAttachment, RgbaColorAttachment},
     line::Line,
     math::{self, Berycentric},
     renderer::*,
-    shader::{*, self},
+    scanline::{clip_polygon, fan_triangles},
+    shader::{self, *},
     texture::TextureStorage,
 };
 
+/// min `w` kept by clip space clipping, avoid dividing by zero
+const CLIP_W_EPSILON: f32 = 1e-5;
+/// x, y are clipped into `[-GUARD_BAND * w, GUARD_BAND * w]`, wider than NDC so clipped edges never cross the viewport
+const GUARD_BAND: f32 = 2.0;
+
+/// return coverage in `[0, 1]` of pixel(first param) by triangle in screen space(second param)
+pub type CoverageFn = Box<dyn Fn(&math::Vec2, &[math::Vec2; 3]) -> f32>;
+
 pub struct Renderer {
-    color_attachment: ColorAttachment,
-    depth_attachment: DepthAttachment,
+    target: RenderTarget,
+    // the presented buffer when double buffer enabled
+    present_attachment: Option<ColorAttachment>,
     camera: camera::Camera,
     viewport: Viewport,
     shader: Shader,
     uniforms: Uniforms,
     front_face: FrontFace,
     cull: FaceCull,
+    cull_method: CullMethod,
+    depth_range: DepthRange,
+    blend_mode: BlendMode,
     enable_framework: bool,
+    back_face_framework_color: Option<math::Vec4>,
+    subpixel_bits: Option<u32>,
+    triangle_callback: Option<TriangleCallback>,
+    coverage_fn: Option<CoverageFn>,
+    hi_z: Option<HiZBuffer>,
+    stats: RasterStats,
+    // id of the first triangle in next draw call
+    next_triangle_id: u32,
+    supersample_scale: u32,
+    // downsampled image when supersampling, reset whenever color changes
+    resolved: OnceCell<ColorAttachment>,
+}
+
+/// counters of rasterization since last `reset_stats`
+#[derive(Default, Clone, Copy, Debug)]
+pub struct RasterStats {
+    /// pixels walked in triangle's AABB
+    pub pixels_tested: u64,
+    /// tiles skipped by Hi-Z
+    pub tiles_rejected: u64,
+}
+
+const HI_Z_TILE_SIZE: u32 = 8;
+
+/// hierarchical depth, store max depth of each tile.
+/// A tile can be skipped if the min depth of triangle is greater than the max depth of tile
+struct HiZBuffer {
+    tiles: Vec<f32>,
+    w: u32,
+}
+
+impl HiZBuffer {
+    fn new(width: u32, height: u32, depth: f32) -> Self {
+        let w = width.div_ceil(HI_Z_TILE_SIZE);
+        let h = height.div_ceil(HI_Z_TILE_SIZE);
+        Self {
+            tiles: vec![depth; w as usize * h as usize],
+            w,
+        }
+    }
+
+    fn get(&self, tile_x: u32, tile_y: u32) -> f32 {
+        self.tiles[(tile_x + tile_y * self.w) as usize]
+    }
+
+    /// recalculate max depth of tile from depth attachment
+    fn update(&mut self, depth_attachment: &DepthAttachment, tile_x: u32, tile_y: u32) {
+        let mut max_depth = f32::MIN;
+        for y in
+            tile_y * HI_Z_TILE_SIZE..((tile_y + 1) * HI_Z_TILE_SIZE).min(depth_attachment.height())
+        {
+            for x in tile_x * HI_Z_TILE_SIZE
+                ..((tile_x + 1) * HI_Z_TILE_SIZE).min(depth_attachment.width())
+            {
+                max_depth = max_depth.max(depth_attachment.get(x, y));
+            }
+        }
+        self.tiles[(tile_x + tile_y * self.w) as usize] = max_depth;
+    }
 }
 
 impl RendererInterface for Renderer {
     fn clear(&mut self, color: &math::Vec4) {
-        self.color_attachment.clear(color);
+        self.resolved.take();
+        self.target.clear_color(color);
     }
 
     fn get_canva_width(&self) -> u32 {
-        self.color_attachment.width()
+        self.target.width() / self.supersample_scale
     }
 
     fn get_canva_height(&self) -> u32 {
-        self.color_attachment.height()
+        self.target.height() / self.supersample_scale
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    fn bind_target(&mut self, target: RenderTarget) -> RenderTarget {
+        let old_target = std::mem::replace(&mut self.target, target);
+        self.supersample_scale = 1;
+        self.resolved.take();
+        // depth of new target is unknown, Hi-Z is rebuilt in next `clear_depth`
+        self.hi_z = None;
+        self.viewport = Viewport {
+            x: 0,
+            y: 0,
+            w: self.target.width(),
+            h: self.target.height(),
+        };
+        if self.present_attachment.is_some() {
+            self.present_attachment = Some(ColorAttachment::new(
+                self.target.width(),
+                self.target.height(),
+            ));
+        }
+        old_target
+    }
+
+    fn get_target(&self) -> &RenderTarget {
+        &self.target
     }
 
     fn get_rendered_image(&self) -> &[u8] {
-        self.color_attachment.data()
+        let image = match &self.present_attachment {
+            Some(present_attachment) => present_attachment,
+            None => match &self.target.color {
+                Some(color_attachment) => color_attachment,
+                None => return &[],
+            },
+        };
+        if self.supersample_scale == 1 {
+            image.data()
+        } else {
+            self.resolved
+                .get_or_init(|| image.downsample(self.supersample_scale))
+                .data()
+        }
+    }
+
+    fn set_supersample_scale(&mut self, scale: u32) {
+        let scale = scale.max(1);
+        let w = self.get_canva_width() * scale;
+        let h = self.get_canva_height() * scale;
+        self.supersample_scale = scale;
+        self.target = RenderTarget {
+            color: self
+                .target
+                .color
+                .as_ref()
+                .map(|_| ColorAttachment::new(w, h)),
+            depth: self
+                .target
+                .depth
+                .as_ref()
+                .map(|_| DepthAttachment::new(w, h)),
+            id: self.target.id.as_ref().map(|_| IdAttachment::new(w, h)),
+            rgba: self
+                .target
+                .rgba
+                .as_ref()
+                .map(|_| RgbaColorAttachment::new(w, h)),
+        };
+        if self.present_attachment.is_some() {
+            self.present_attachment = Some(ColorAttachment::new(w, h));
+        }
+        self.resolved.take();
+        self.hi_z = None;
+    }
+
+    fn get_supersample_scale(&self) -> u32 {
+        self.supersample_scale
+    }
+
+    fn enable_double_buffer(&mut self) {
+        self.resolved.take();
+        if self.present_attachment.is_none() {
+            self.present_attachment = Some(ColorAttachment::new(
+                self.target.width(),
+                self.target.height(),
+            ));
+        }
+    }
+
+    fn disable_double_buffer(&mut self) {
+        self.present_attachment = None;
+        self.resolved.take();
+    }
+
+    fn swap_buffers(&mut self) {
+        self.resolved.take();
+        if let (Some(present_attachment), Some(color_attachment)) =
+            (&mut self.present_attachment, &mut self.target.color)
+        {
+            std::mem::swap(present_attachment, color_attachment);
+        }
     }
 
     fn draw_triangle(
@@ -43,162 +222,62 @@ impl RendererInterface for Renderer {
         vertices: &[Vertex],
         texture_storage: &TextureStorage,
     ) {
+        self.resolved.take();
+        // premultiply once per draw instead of per vertex
+        let model_view = *self.camera.view_mat() * *model;
         for i in 0..vertices.len() / 3_usize {
-            // convert 3D coordination to Homogeneous coordinates
-            let mut vertices = [vertices[i * 3], vertices[1 + i * 3], vertices[2 + i * 3]];
-
-            for v in &mut vertices {
-                *v = self
-                    .shader
-                    .call_vertex_changing(v, &self.uniforms, texture_storage);
-            }
-
-            // Model View transform
-            for v in &mut vertices {
-                v.position = *self.camera.view_mat() * *model * v.position;
-            }
-
-            // Face Cull
-            if should_cull(
-                &vertices.map(|v| v.position.truncated_to_vec3()),
-                &-*math::Vec3::z_axis(),
-                self.front_face,
-                self.cull,
-            ) {
-                continue;
-            }
-
-            // project transform
-            for v in &mut vertices {
-                v.position = *self.camera.get_frustum().get_mat() * v.position;
-            }
-
-            // set truely z
-            /* NOTIC: in OpenGL, after MVP & Perspective divide, z in [-1, 1], then OpenGL do `z = (z + 1) / 2` to make z in [0, 1],
-                then, use `1 / z` to test depth.
-                But here we replace transformed z to it's original z which transformed after MVP.
-                Traditionally we will save `-1.0 / v.position.w` into v.rhw and use it interpolate attributes.
-                But here I don't do it(because I'm lazy :D, maybe do it later).
-            */
-            for v in &mut vertices {
-                v.position.z = -v.position.w;
-            }
-
-            // perspective divide
-            for v in &mut vertices {
-                v.position.x /= v.position.w;
-                v.position.y /= v.position.w;
-                v.position.w = 1.0;
-            }
-
-            // Viewport transform
-            for v in &mut vertices {
-                v.position.x = (v.position.x + 1.0) * 0.5 * (self.viewport.w as f32 - 1.0)
-                    + self.viewport.x as f32;
-                v.position.y = self.viewport.h as f32
-                    - (v.position.y + 1.0) * 0.5 * (self.viewport.h as f32 - 1.0)
-                    + self.viewport.y as f32;
-            }
+            let vertices = [vertices[i * 3], vertices[1 + i * 3], vertices[2 + i * 3]]
+                .map(|v| change_vertex(&self.shader, &self.uniforms, model, &v, texture_storage));
+            self.draw_changed_triangle(i, &model_view, vertices, texture_storage);
+        }
+        self.next_triangle_id = self
+            .next_triangle_id
+            .wrapping_add((vertices.len() / 3) as u32);
+    }
 
-            // find AABB for triangle
-            let aabb_min_x = vertices
-                .iter()
-                .fold(std::f32::MAX, |min, v| {
-                    if v.position.x < min {
-                        v.position.x
-                    } else {
-                        min
-                    }
-                })
-                .ceil()
-                .max(0.0);
-            let aabb_min_y = vertices
-                .iter()
-                .fold(std::f32::MAX, |min, v| {
-                    if v.position.y < min {
-                        v.position.y
-                    } else {
-                        min
-                    }
-                })
-                .ceil()
-                .max(0.0);
-            let aabb_max_x = vertices
-                .iter()
-                .fold(std::f32::MIN, |max, v| {
-                    if v.position.x > max {
-                        v.position.x
-                    } else {
-                        max
-                    }
-                })
-                .floor()
-                .min(self.color_attachment.width() as f32 - 1.0);
-            let aabb_max_y = vertices
-                .iter()
-                .fold(std::f32::MIN, |max, v| {
-                    if v.position.y > max {
-                        v.position.y
-                    } else {
-                        max
-                    }
-                })
-                .floor()
-                .min(self.color_attachment.height() as f32 - 1.0);
-            let aabb_min = math::Vec2::new(aabb_min_x, aabb_min_y);
-            let aabb_max = math::Vec2::new(aabb_max_x, aabb_max_y);
-
-            if self.enable_framework {
-                // draw line framework
-                for i in 0..3 {
-                    let mut v1 = vertices[i];
-                    let mut v2 = vertices[(i + 1) % 3];
-
-                    shader::vertex_rhw_init(&mut v1);
-                    shader::vertex_rhw_init(&mut v2);
-
-                    rasterize_line(
-                        &mut Line::new(v1, v2),
-                        &self.shader.pixel_shading,
-                        &self.uniforms,
-                        texture_storage,
-                        &mut self.color_attachment,
-                        &mut self.depth_attachment,
-                    );
-                }
-            } else {
-                // walk through all pixel in AABB and set color
-                for x in aabb_min.x as u32..=aabb_max.x as u32 {
-                    for y in aabb_min.y as u32..=aabb_max.y as u32 {
-                        let berycentric = math::Berycentric::new(
-                            &math::Vec2::new(x as f32, y as f32),
-                            &vertices.map(|v| math::Vec2::new(v.position.x, v.position.y)),
-                        );
-                        if berycentric.is_valid() {
-                            // attributes interpolation and perspective correct
-                            let inv_z = berycentric.alpha() / vertices[0].position.z
-                                + berycentric.beta() / vertices[1].position.z
-                                + berycentric.gamma() / vertices[2].position.z;
-                            let z = 1.0 / inv_z;
-                            // depth test and near plane
-                            if z < self.camera.get_frustum().near()
-                                && self.depth_attachment.get(x, y) <= z
-                            {
-                                let attr = get_corrected_attribute(z, &vertices, &berycentric);
-                                //  call pixel shading function to get pixel color
-                                let color = self.shader.call_pixel_shading(
-                                    &attr,
-                                    &self.uniforms,
-                                    texture_storage,
-                                );
-                                self.color_attachment.set(x, y, &color);
-                                self.depth_attachment.set(x, y, z);
-                            }
-                        }
-                    }
-                }
-            }
+    fn draw_triangle_indices(
+        &mut self,
+        model: &math::Mat4,
+        vertices: &[Vertex],
+        triangles: &[[u32; 3]],
+        texture_storage: &TextureStorage,
+    ) {
+        self.resolved.take();
+        // premultiply once per draw instead of per vertex
+        let model_view = *self.camera.view_mat() * *model;
+        // shared vertices are changed only once
+        let changed: Vec<Vertex> = vertices
+            .iter()
+            .map(|v| change_vertex(&self.shader, &self.uniforms, model, v, texture_storage))
+            .collect();
+        for (i, triangle) in triangles.iter().enumerate() {
+            let vertices = triangle.map(|index| changed[index as usize]);
+            self.draw_changed_triangle(i, &model_view, vertices, texture_storage);
         }
+        self.next_triangle_id = self.next_triangle_id.wrapping_add(triangles.len() as u32);
+    }
+
+    fn draw_line_3d(
+        &mut self,
+        model: &math::Mat4,
+        start: Vertex,
+        end: Vertex,
+        texture_storage: &TextureStorage,
+    ) {
+        self.resolved.take();
+        let viewport = self.raster_viewport();
+        crate::renderer::draw_line_3d(
+            model,
+            [start, end],
+            &self.shader,
+            &self.uniforms,
+            texture_storage,
+            &mut self.target,
+            &self.camera,
+            &viewport,
+            self.depth_range,
+            self.blend_mode,
+        );
     }
 
     fn get_shader(&mut self) -> &mut Shader {
@@ -210,7 +289,16 @@ impl RendererInterface for Renderer {
     }
 
     fn clear_depth(&mut self) {
-        self.depth_attachment.clear(f32::MIN);
+        if let Some(depth_attachment) = &mut self.target.depth {
+            depth_attachment.clear(f32::MAX);
+        }
+        self.hi_z = self.target.depth.as_ref().map(|depth_attachment| {
+            HiZBuffer::new(
+                depth_attachment.width(),
+                depth_attachment.height(),
+                f32::MAX,
+            )
+        });
     }
 
     fn get_camera(&mut self) -> &mut camera::Camera {
@@ -237,6 +325,54 @@ impl RendererInterface for Renderer {
         self.cull
     }
 
+    fn set_cull_method(&mut self, method: CullMethod) {
+        self.cull_method = method;
+    }
+
+    fn get_cull_method(&self) -> CullMethod {
+        self.cull_method
+    }
+
+    fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    fn get_blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    fn set_depth_range(&mut self, near: f32, far: f32) {
+        self.depth_range = DepthRange { near, far };
+    }
+
+    fn get_depth_range(&self) -> DepthRange {
+        self.depth_range
+    }
+
+    fn set_triangle_callback(&mut self, callback: TriangleCallback) {
+        self.triangle_callback = Some(callback);
+    }
+
+    fn reset_triangle_callback(&mut self) {
+        self.triangle_callback = None;
+    }
+
+    fn set_subpixel_bits(&mut self, bits: Option<u32>) {
+        self.subpixel_bits = bits;
+    }
+
+    fn get_subpixel_bits(&self) -> Option<u32> {
+        self.subpixel_bits
+    }
+
+    fn set_back_face_framework_color(&mut self, color: Option<math::Vec4>) {
+        self.back_face_framework_color = color;
+    }
+
+    fn get_back_face_framework_color(&self) -> Option<math::Vec4> {
+        self.back_face_framework_color
+    }
+
     fn enable_framework(&mut self) {
         self.enable_framework = true;
     }
@@ -245,43 +381,779 @@ impl RendererInterface for Renderer {
         self.enable_framework = false;
     }
 
+    fn enable_id_buffer(&mut self) {
+        if self.target.id.is_none() {
+            self.target.id = Some(IdAttachment::new(self.target.width(), self.target.height()));
+        }
+    }
+
+    fn disable_id_buffer(&mut self) {
+        self.target.id = None;
+    }
+
+    fn clear_id(&mut self) {
+        if let Some(id_attachment) = &mut self.target.id {
+            id_attachment.clear();
+        }
+        self.next_triangle_id = 0;
+    }
+
     fn toggle_framework(&mut self) {
         self.enable_framework = !self.enable_framework;
     }
 }
 
-#[rustfmt::skip]
-fn get_corrected_attribute(z: f32, vertices: &[Vertex; 3], berycentric: &Berycentric) -> Attributes {
+fn get_corrected_attribute(
+    z: f32,
+    vertices: &[Vertex; 3],
+    berycentric: &Berycentric,
+    modes: &InterpModes,
+) -> Attributes {
+    let linear = [berycentric.alpha(), berycentric.beta(), berycentric.gamma()];
+    let perspective = [0, 1, 2].map(|k| linear[k] / vertices[k].position.z * z);
+    // flat slots are already the same on three vertices
+    let weights = |mode: InterpMode| {
+        if mode == InterpMode::Perspective {
+            perspective
+        } else {
+            linear
+        }
+    };
+
+    let [a0, a1, a2] = vertices.map(|v| v.attributes);
     let mut attr = Attributes::default();
     for i in 0..attr.float.len() {
-        attr.float[i] = (vertices[0].attributes.float[i] * berycentric.alpha() / vertices[0].position.z +
-                         vertices[1].attributes.float[i] * berycentric.beta() / vertices[1].position.z +
-                         vertices[2].attributes.float[i] * berycentric.gamma() / vertices[2].position.z) * z;
-        attr.vec2[i] = (vertices[0].attributes.vec2[i] * berycentric.alpha() / vertices[0].position.z +
-                        vertices[1].attributes.vec2[i] * berycentric.beta() / vertices[1].position.z +
-                        vertices[2].attributes.vec2[i] * berycentric.gamma() / vertices[2].position.z) * z;
-        attr.vec3[i] = (vertices[0].attributes.vec3[i] * berycentric.alpha() / vertices[0].position.z +
-                        vertices[1].attributes.vec3[i] * berycentric.beta() / vertices[1].position.z +
-                        vertices[2].attributes.vec3[i] * berycentric.gamma() / vertices[2].position.z) * z;
-        attr.vec4[i] = (vertices[0].attributes.vec4[i] * berycentric.alpha() / vertices[0].position.z +
-                        vertices[1].attributes.vec4[i] * berycentric.beta() / vertices[1].position.z +
-                        vertices[2].attributes.vec4[i] * berycentric.gamma() / vertices[2].position.z) * z;
+        let w = weights(modes.float[i]);
+        attr.float[i] = a0.float[i] * w[0] + a1.float[i] * w[1] + a2.float[i] * w[2];
+        let w = weights(modes.vec2[i]);
+        attr.vec2[i] = a0.vec2[i] * w[0] + a1.vec2[i] * w[1] + a2.vec2[i] * w[2];
+        let w = weights(modes.vec3[i]);
+        attr.vec3[i] = a0.vec3[i] * w[0] + a1.vec3[i] * w[1] + a2.vec3[i] * w[2];
+        let w = weights(modes.vec4[i]);
+        attr.vec4[i] = a0.vec4[i] * w[0] + a1.vec4[i] * w[1] + a2.vec4[i] * w[2];
     }
+    let w = weights(modes.world_position);
+    attr.world_position =
+        a0.world_position * w[0] + a1.world_position * w[1] + a2.world_position * w[2];
     attr
 }
 
 impl Renderer {
     pub fn new(w: u32, h: u32, camera: camera::Camera) -> Self {
         Self {
-            color_attachment: ColorAttachment::new(w, h),
-            depth_attachment: DepthAttachment::new(w, h),
+            target: RenderTarget::new(w, h),
+            present_attachment: None,
             camera,
             viewport: Viewport { x: 0, y: 0, w, h },
             shader: Default::default(),
             uniforms: Default::default(),
             front_face: FrontFace::CCW,
             cull: FaceCull::None,
+            cull_method: CullMethod::ViewDir,
+            depth_range: DepthRange::default(),
+            blend_mode: BlendMode::None,
             enable_framework: false,
+            back_face_framework_color: None,
+            subpixel_bits: Some(DEFAULT_SUBPIXEL_BITS),
+            triangle_callback: None,
+            next_triangle_id: 0,
+            supersample_scale: 1,
+            resolved: OnceCell::new(),
+            coverage_fn: None,
+            hi_z: None,
+            stats: RasterStats::default(),
+        }
+    }
+
+    // viewport in internal target, which is scaled when supersampling
+    fn raster_viewport(&self) -> Viewport {
+        self.viewport.scaled(self.supersample_scale)
+    }
+
+    pub fn stats(&self) -> RasterStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = RasterStats::default();
+    }
+
+    /// cull, clip and rasterize triangle `index` of a draw call, `vertices` are after vertex changing
+    fn draw_changed_triangle(
+        &mut self,
+        index: usize,
+        model_view: &math::Mat4,
+        mut vertices: [Vertex; 3],
+        texture_storage: &TextureStorage,
+    ) {
+        self.shader.interp_modes.flatten(&mut vertices);
+
+        // Model View transform
+        for v in &mut vertices {
+            v.position = *model_view * v.position;
+        }
+
+        // Face Cull
+        if self.cull_method == CullMethod::ViewDir
+            && should_cull(
+                &vertices.map(|v| v.position.truncated_to_vec3()),
+                &-*math::Vec3::z_axis(),
+                self.front_face,
+                self.cull,
+            )
+        {
+            return;
+        }
+
+        // near plane clip
+        let near = self.camera.get_frustum().near();
+        if vertices.iter().all(|v| v.position.z > -near) {
+            return;
+        }
+        if vertices.iter().any(|v| v.position.z > -near) {
+            let (face1, face2) = crate::scanline::near_plane_clip(&vertices, near);
+            self.far_plane_clip_and_rasterize(index, face1, true, texture_storage);
+            if let Some(face) = face2 {
+                self.far_plane_clip_and_rasterize(index, face, true, texture_storage);
+            }
+        } else {
+            self.far_plane_clip_and_rasterize(index, vertices, false, texture_storage);
         }
     }
+
+    /// clip triangle in view space against far plane, then rasterize remaining faces
+    fn far_plane_clip_and_rasterize(
+        &mut self,
+        index: usize,
+        vertices: [Vertex; 3],
+        clipped: bool,
+        texture_storage: &TextureStorage,
+    ) {
+        let far = self.camera.get_frustum().far();
+        // totally beyond far plane, nothing is left after clipping
+        if vertices.iter().all(|v| v.position.z < -far) {
+            return;
+        }
+        if vertices.iter().any(|v| v.position.z < -far) {
+            let (face1, face2) = crate::scanline::far_plane_clip(&vertices, far);
+            self.rasterize_triangle(index, face1, true, texture_storage);
+            if let Some(face) = face2 {
+                self.rasterize_triangle(index, face, true, texture_storage);
+            }
+        } else {
+            self.rasterize_triangle(index, vertices, clipped, texture_storage);
+        }
+    }
+
+    /// project, viewport transform and rasterize a triangle in view space
+    fn rasterize_triangle(
+        &mut self,
+        index: usize,
+        mut vertices: [Vertex; 3],
+        clipped: bool,
+        texture_storage: &TextureStorage,
+    ) {
+        // project transform, keep view space z(`-w` only equals it for perspective projection)
+        let view_z = vertices.map(|v| v.position.z);
+        for v in &mut vertices {
+            v.position = *self.camera.get_frustum().get_mat() * v.position;
+        }
+
+        // set truely z
+        /* NOTIC: in OpenGL, after MVP & Perspective divide, z in [-1, 1], then OpenGL do `z = (z + 1) / 2` to make z in [0, 1],
+            then, use `1 / z` to test depth.
+            But here we replace transformed z to it's original z which transformed after MVP.
+            Traditionally we will save `-1.0 / v.position.w` into v.rhw and use it interpolate attributes.
+            But here I don't do it(because I'm lazy :D, maybe do it later).
+        */
+        for (v, z) in vertices.iter_mut().zip(view_z) {
+            v.position.z = z;
+        }
+
+        // clip against `w > CLIP_W_EPSILON` and x, y in guard band before perspective divide,
+        // near plane is already clipped in view space
+        let planes: [fn(&math::Vec4) -> f32; 5] = [
+            |p| p.w - CLIP_W_EPSILON,
+            |p| GUARD_BAND * p.w - p.x,
+            |p| GUARD_BAND * p.w + p.x,
+            |p| GUARD_BAND * p.w - p.y,
+            |p| GUARD_BAND * p.w + p.y,
+        ];
+        if vertices
+            .iter()
+            .all(|v| planes.iter().all(|distance| distance(&v.position) >= 0.0))
+        {
+            self.rasterize_clip_space_triangle(index, vertices, clipped, texture_storage);
+            return;
+        }
+        let polygon = planes.iter().fold(vertices.to_vec(), |polygon, distance| {
+            clip_polygon(&polygon, distance)
+        });
+        for face in fan_triangles(&polygon) {
+            self.rasterize_clip_space_triangle(index, face, true, texture_storage);
+        }
+    }
+
+    /// perspective divide, viewport transform and rasterize a triangle whose vertices are all inside clip space
+    fn rasterize_clip_space_triangle(
+        &mut self,
+        index: usize,
+        mut vertices: [Vertex; 3],
+        clipped: bool,
+        texture_storage: &TextureStorage,
+    ) {
+        // perspective divide
+        for v in &mut vertices {
+            v.position.x /= v.position.w;
+            v.position.y /= v.position.w;
+            v.position.w = 1.0;
+        }
+
+        // Viewport transform
+        for v in &mut vertices {
+            let screen = self
+                .raster_viewport()
+                .transform(&v.position.truncated_to_vec2());
+            v.position.x = screen.x;
+            v.position.y = screen.y;
+        }
+
+        // snap to subpixel grid so coverage is stable when vertices move slowly
+        if let Some(bits) = self.subpixel_bits {
+            for v in &mut vertices {
+                v.position.x = snap_to_subpixel(v.position.x, bits);
+                v.position.y = snap_to_subpixel(v.position.y, bits);
+            }
+        }
+
+        // degenerate projection(like `0 / 0`) can't be rasterized
+        if vertices.iter().any(|v| {
+            !(v.position.x.is_finite() && v.position.y.is_finite() && v.position.z.is_finite())
+        }) {
+            return;
+        }
+
+        // Face Cull in screen space
+        if self.cull_method == CullMethod::ScreenArea
+            && should_cull_screen(
+                &vertices.map(|v| v.position.truncated_to_vec2()),
+                self.front_face,
+                self.cull,
+            )
+        {
+            return;
+        }
+
+        let id = self.next_triangle_id.wrapping_add(index as u32);
+
+        if let Some(callback) = &mut self.triangle_callback {
+            callback(&TriangleInfo::new(
+                index,
+                vertices.map(|v| v.position.truncated_to_vec2()),
+                clipped,
+            ));
+        }
+
+        // find AABB for triangle, clamped into viewport
+        let (min, max) = self
+            .raster_viewport()
+            .pixel_bounds(self.target.width(), self.target.height());
+        let aabb_min_x = vertices
+            .iter()
+            .fold(std::f32::MAX, |min, v| {
+                if v.position.x < min {
+                    v.position.x
+                } else {
+                    min
+                }
+            })
+            .ceil()
+            .max(min.x);
+        let aabb_min_y = vertices
+            .iter()
+            .fold(std::f32::MAX, |min, v| {
+                if v.position.y < min {
+                    v.position.y
+                } else {
+                    min
+                }
+            })
+            .ceil()
+            .max(min.y);
+        let aabb_max_x = vertices
+            .iter()
+            .fold(std::f32::MIN, |max, v| {
+                if v.position.x > max {
+                    v.position.x
+                } else {
+                    max
+                }
+            })
+            .floor()
+            .min(max.x);
+        let aabb_max_y = vertices
+            .iter()
+            .fold(std::f32::MIN, |max, v| {
+                if v.position.y > max {
+                    v.position.y
+                } else {
+                    max
+                }
+            })
+            .floor()
+            .min(max.y);
+        let aabb_min = math::Vec2::new(aabb_min_x, aabb_min_y);
+        let aabb_max = math::Vec2::new(aabb_max_x, aabb_max_y);
+
+        if self.enable_framework {
+            let back_face_color = self.back_face_framework_color.filter(|_| {
+                !is_front_face_screen(
+                    &vertices.map(|v| v.position.truncated_to_vec2()),
+                    self.front_face,
+                )
+            });
+            let shader = &self.shader;
+            let shading =
+                |attr: &Attributes,
+                 uniforms: &Uniforms,
+                 texture_storage: &TextureStorage,
+                 context: &FragmentContext| match back_face_color {
+                    Some(color) => Some(color),
+                    None => shader.call_pixel_shading(attr, uniforms, texture_storage, context),
+                };
+
+            // draw line framework
+            for i in 0..3 {
+                let mut v1 = vertices[i];
+                let mut v2 = vertices[(i + 1) % 3];
+
+                shader::vertex_rhw_init(&mut v1);
+                shader::vertex_rhw_init(&mut v2);
+
+                let depth_range = self.depth_range;
+                let frustum = self.camera.get_frustum();
+                let (near, far) = (frustum.near(), frustum.far());
+                let bounds = self
+                    .raster_viewport()
+                    .pixel_bounds(self.target.width(), self.target.height());
+                rasterize_line(
+                    &mut Line::new(v1, v2),
+                    &shading,
+                    &self.uniforms,
+                    texture_storage,
+                    &mut self.target,
+                    |z| depth_range.map(z, near, far),
+                    PixelWrite {
+                        id: Some(id),
+                        blend_mode: self.blend_mode,
+                        bounds,
+                        may_discard: shader.may_discard(),
+                    },
+                );
+            }
+        } else {
+            if aabb_min.x > aabb_max.x || aabb_min.y > aabb_max.y {
+                return;
+            }
+            let frustum = self.camera.get_frustum();
+            let (near, far) = (frustum.near(), frustum.far());
+            // depth is monotonic with z, so the nearest vertex has the min depth of triangle
+            let triangle_depth = vertices
+                .iter()
+                .map(|v| self.depth_range.map(v.position.z, near, far))
+                .fold(f32::MAX, f32::min);
+
+            // walk through tiles in AABB, skip tiles occluded by Hi-Z
+            let (min_x, min_y) = (aabb_min.x as u32, aabb_min.y as u32);
+            let (max_x, max_y) = (aabb_max.x as u32, aabb_max.y as u32);
+            for tile_y in min_y / HI_Z_TILE_SIZE..=max_y / HI_Z_TILE_SIZE {
+                for tile_x in min_x / HI_Z_TILE_SIZE..=max_x / HI_Z_TILE_SIZE {
+                    if let Some(hi_z) = &self.hi_z {
+                        if triangle_depth > hi_z.get(tile_x, tile_y) {
+                            self.stats.tiles_rejected += 1;
+                            continue;
+                        }
+                    }
+
+                    // walk through all pixel in tile and set color
+                    let mut depth_written = false;
+                    for x in (tile_x * HI_Z_TILE_SIZE).max(min_x)
+                        ..=((tile_x + 1) * HI_Z_TILE_SIZE - 1).min(max_x)
+                    {
+                        for y in (tile_y * HI_Z_TILE_SIZE).max(min_y)
+                            ..=((tile_y + 1) * HI_Z_TILE_SIZE - 1).min(max_y)
+                        {
+                            self.stats.pixels_tested += 1;
+                            depth_written |=
+                                self.rasterize_pixel(x, y, &vertices, id, texture_storage);
+                        }
+                    }
+
+                    if let (true, Some(hi_z), Some(depth_attachment)) =
+                        (depth_written, &mut self.hi_z, &self.target.depth)
+                    {
+                        hi_z.update(depth_attachment, tile_x, tile_y);
+                    }
+                }
+            }
+        }
+    }
+
+    /// return whether depth is written
+    fn rasterize_pixel(
+        &mut self,
+        x: u32,
+        y: u32,
+        vertices: &[Vertex; 3],
+        id: u32,
+        texture_storage: &TextureStorage,
+    ) -> bool {
+        let pt = math::Vec2::new(x as f32, y as f32);
+        let triangle = vertices.map(|v| math::Vec2::new(v.position.x, v.position.y));
+        let berycentric = math::Berycentric::new(&pt, &triangle);
+        let coverage = match &self.coverage_fn {
+            Some(coverage_fn) => coverage_fn(&pt, &triangle).clamp(0.0, 1.0),
+            None => {
+                // top-left rule, so shared edges are only drawn once
+                if berycentric.is_inside_top_left(&triangle) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        if coverage <= 0.0 {
+            return false;
+        }
+
+        // attributes interpolation and perspective correct,
+        // orthographic projection keeps z and attributes linear in screen space
+        let frustum = self.camera.get_frustum();
+        let ortho = frustum.is_ortho();
+        let weights = [berycentric.alpha(), berycentric.beta(), berycentric.gamma()];
+        let (z, rhw) = if ortho {
+            let z = (0..3).map(|k| weights[k] * vertices[k].position.z).sum();
+            (z, 1.0)
+        } else {
+            let inv_z: f32 = (0..3).map(|k| weights[k] / vertices[k].position.z).sum();
+            (1.0 / inv_z, inv_z)
+        };
+        let depth = self.depth_range.map(z, frustum.near(), frustum.far());
+        // depth test and near plane
+        if z >= frustum.near() || !self.target.depth_test(x, y, depth) {
+            return false;
+        }
+
+        // no need to shade for depth only target, unless fragment may be discarded
+        if self.target.has_color() || self.shader.may_discard() {
+            let modes = if ortho {
+                self.shader.interp_modes.without_perspective()
+            } else {
+                self.shader.interp_modes
+            };
+            let attr = get_corrected_attribute(z, vertices, &berycentric, &modes);
+            //  call pixel shading function to get pixel color
+            let context = FragmentContext::new(x, y, depth, z, rhw, self.target.get_depth(x, y))
+                .with_world_position(&attr);
+            let Some(color) =
+                self.shader
+                    .call_pixel_shading(&attr, &self.uniforms, texture_storage, &context)
+            else {
+                // discarded fragment writes nothing
+                return false;
+            };
+            self.write_color(x, y, &color, coverage);
+        }
+        self.target.set_depth(x, y, depth);
+        self.target.set_id(x, y, id);
+        true
+    }
+
+    /// blend `color` over the existing color, then lerp by coverage
+    fn write_color(&mut self, x: u32, y: u32, color: &math::Vec4, coverage: f32) {
+        if let Some(dst) = self.target.get_color(x, y) {
+            let color = *color;
+            let color = self.blend_mode.blend(&color, &dst);
+            let color = if coverage < 1.0 {
+                math::lerp(dst, color, coverage)
+            } else {
+                color
+            };
+            self.target.set_color(x, y, &color);
+        }
+    }
+
+    /// replace the default coverage(1 if pixel center in triangle, otherwise 0),
+    /// fragment color is blended with the existing color by coverage
+    pub fn set_coverage_fn(&mut self, coverage_fn: CoverageFn) {
+        self.coverage_fn = Some(coverage_fn);
+    }
+
+    pub fn reset_coverage_fn(&mut self) {
+        self.coverage_fn = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::renderer::test::{quad, test_triangle};
+
+    #[test]
+    fn custom_coverage() {
+        let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let mut renderer = Renderer::new(64, 64, camera);
+        renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+        renderer.clear_depth();
+        renderer.shader.pixel_shading = Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+        renderer.set_coverage_fn(Box::new(|_, _| 0.5));
+
+        let vertices = test_triangle();
+        renderer.draw_triangle(
+            &math::Mat4::identity(),
+            &vertices,
+            &TextureStorage::default(),
+        );
+
+        let color = renderer.target.color.as_ref().unwrap().get(32, 32);
+        assert!((color.x - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn depth_test_near_wins() {
+        let colored_triangle = |color: math::Vec4, z: f32| {
+            let mut attr = Attributes::default();
+            attr.set_vec4(0, color);
+            [
+                math::Vec3::new(-1.0, -1.0, z),
+                math::Vec3::new(1.0, -1.0, z),
+                math::Vec3::new(0.0, 1.0, z),
+            ]
+            .map(|p| Vertex::new(p, attr))
+        };
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let near = colored_triangle(red, -3.0);
+        let far = colored_triangle(math::Vec4::new(0.0, 0.0, 1.0, 1.0), -4.0);
+
+        for order in [[far, near], [near, far]] {
+            let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+            let mut renderer = Renderer::new(64, 64, camera);
+            renderer.shader.pixel_shading = Box::new(|attr, _, _, _| attr.vec4[0]);
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            for triangle in order {
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &triangle,
+                    &TextureStorage::default(),
+                );
+            }
+            assert_eq!(renderer.target.color.as_ref().unwrap().get(32, 32), red);
+        }
+    }
+
+    #[test]
+    fn cull_back_face() {
+        // clockwise seen from camera
+        let back_face = [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, 1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let black = math::Vec4::new(0.0, 0.0, 0.0, 1.0);
+
+        for method in [CullMethod::ViewDir, CullMethod::ScreenArea] {
+            for (cull, drawn) in [(FaceCull::Back, false), (FaceCull::Front, true)] {
+                let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+                let mut renderer = Renderer::new(64, 64, camera);
+                renderer.shader.pixel_shading =
+                    Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+                renderer.clear(&black);
+                renderer.clear_depth();
+                renderer.set_front_face(FrontFace::CCW);
+                renderer.set_face_cull(cull);
+                renderer.set_cull_method(method);
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &back_face,
+                    &TextureStorage::default(),
+                );
+
+                let color = renderer.target.color.as_ref().unwrap();
+                let empty = (0..64).all(|y| (0..64).all(|x| color.get(x, y) == black));
+                assert_eq!(empty, !drawn);
+            }
+        }
+    }
+
+    #[test]
+    fn near_plane_clip_triangle_behind_camera() {
+        // top vertex is behind camera, only the part in front of near plane(z = -1) is visible
+        let vertices = [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, 1.0, 2.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let black = math::Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+        let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let mut renderer = Renderer::new(64, 64, camera);
+        renderer.shader.pixel_shading = Box::new(move |_, _, _, _| white);
+        let infos = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let recorder = infos.clone();
+        renderer.set_triangle_callback(Box::new(move |info| recorder.borrow_mut().push(*info)));
+        renderer.clear(&black);
+        renderer.clear_depth();
+        renderer.draw_triangle(
+            &math::Mat4::identity(),
+            &vertices,
+            &TextureStorage::default(),
+        );
+
+        assert!(!infos.borrow().is_empty());
+        assert!(infos.borrow().iter().all(|info| info.clipped));
+
+        // clipped edge at near plane is at y = -0.2 in NDC, upper half of screen stays empty
+        let color = renderer.target.color.as_ref().unwrap();
+        assert!((0..30).all(|y| (0..64).all(|x| color.get(x, y) == black)));
+        assert_eq!(color.get(32, 40), white);
+        assert_eq!(color.get(2, 40), black);
+    }
+
+    #[test]
+    fn clip_triangle_straddling_eye_plane() {
+        // one vertex behind the eye, far wider than the view, so both near plane and guard band clip it
+        let vertices = [
+            math::Vec3::new(-30.0, -2.0, -3.0),
+            math::Vec3::new(30.0, -2.0, -3.0),
+            math::Vec3::new(0.0, 1.0, 3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let render = |renderer: &mut dyn RendererInterface| {
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, _| white);
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+            renderer.get_rendered_image().to_vec()
+        };
+
+        let camera = || camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let gpu_image = render(&mut Renderer::new(64, 64, camera()));
+        let cpu_image = render(&mut crate::cpu_renderer::Renderer::new(64, 64, camera()));
+
+        // lower part is covered, upper part is empty
+        let pixel = |x: usize, y: usize| gpu_image[(x + y * 64) * 3];
+        assert_eq!(pixel(32, 60), 255);
+        assert_eq!(pixel(2, 60), 255);
+        assert_eq!(pixel(32, 2), 0);
+        // matches CPU renderer except pixels on edges
+        let diff = gpu_image
+            .chunks(3)
+            .zip(cpu_image.chunks(3))
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(diff < 64, "{diff} pixels differ");
+    }
+
+    #[test]
+    fn hi_z_rejects_occluded_tiles() {
+        let occluder = quad(-10.0, 10.0, -10.0, 10.0, -2.0);
+        let occluded = quad(-3.0, 3.0, -3.0, 3.0, -8.0);
+        let texture_storage = TextureStorage::default();
+        let model = math::Mat4::identity();
+
+        let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let mut renderer = Renderer::new(64, 64, camera);
+        renderer.clear_depth();
+        renderer.draw_triangle(&model, &occluded, &texture_storage);
+        let visible_stats = renderer.stats();
+
+        renderer.clear_depth();
+        renderer.draw_triangle(&model, &occluder, &texture_storage);
+        renderer.reset_stats();
+        renderer.draw_triangle(&model, &occluded, &texture_storage);
+        let occluded_stats = renderer.stats();
+
+        assert!(visible_stats.pixels_tested > 0);
+        assert!(occluded_stats.tiles_rejected > 0);
+        assert!(occluded_stats.pixels_tested * 4 < visible_stats.pixels_tested);
+
+        // occluder over left half only, right half of the farther quad is still written
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let half_occluder = quad(-10.0, 0.0, -10.0, 10.0, -2.0);
+        renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+        renderer.clear_depth();
+        renderer.draw_triangle(&model, &half_occluder, &texture_storage);
+        renderer.reset_stats();
+        renderer.shader.pixel_shading = Box::new(move |_, _, _, _| red);
+        renderer.draw_triangle(
+            &model,
+            &quad(-30.0, 30.0, -30.0, 30.0, -8.0),
+            &texture_storage,
+        );
+
+        assert!(renderer.stats().tiles_rejected > 0);
+        let color = renderer.target.color.as_ref().unwrap();
+        assert_ne!(color.get(10, 32), red);
+        assert_eq!(color.get(50, 32), red);
+    }
+
+    #[test]
+    fn hi_z_keeps_visible_tiles_of_partly_occluded_triangle() {
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        // occluder covers left half of the screen
+        let occluder = quad(-10.0, 0.0, -10.0, 10.0, -2.0);
+        // one farther triangle covers whole screen
+        let triangle = [
+            math::Vec3::new(-30.0, -30.0, -8.0),
+            math::Vec3::new(30.0, -30.0, -8.0),
+            math::Vec3::new(0.0, 30.0, -8.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let texture_storage = TextureStorage::default();
+        let model = math::Mat4::identity();
+
+        let camera = camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians());
+        let mut renderer = Renderer::new(64, 64, camera);
+        renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+        renderer.clear_depth();
+        renderer.shader.pixel_shading = Box::new(move |_, _, _, _| white);
+        renderer.draw_triangle(&model, &occluder, &texture_storage);
+        renderer.reset_stats();
+        renderer.shader.pixel_shading = Box::new(move |_, _, _, _| red);
+        renderer.draw_triangle(&model, &triangle, &texture_storage);
+
+        assert!(renderer.stats().tiles_rejected > 0);
+        let color = renderer.target.color.as_ref().unwrap();
+        assert_eq!(color.get(10, 32), white);
+        assert_eq!(color.get(50, 32), red);
+    }
+
+    #[test]
+    fn toggle_framework() {
+        let mut renderer = Renderer::new(
+            64,
+            64,
+            camera::Camera::new(1.0, 10.0, 1.0, 45f32.to_radians()),
+        );
+        let origin = renderer.enable_framework;
+        renderer.toggle_framework();
+        assert_ne!(renderer.enable_framework, origin);
+        renderer.toggle_framework();
+        assert_eq!(renderer.enable_framework, origin);
+
+        renderer.enable_framework();
+        assert!(renderer.enable_framework);
+        renderer.disable_framework();
+        assert!(!renderer.enable_framework);
+    }
 }
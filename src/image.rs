@@ -1,5 +1,9 @@
 use crate::math;
+use crate::renderer::DepthRange;
 
+/// image with tightly packed elements. Sizes and indices are computed in `usize`,
+/// so the max dimensions are only limited by `u32` width/height and the memory(`w * h * channels` elements)
+#[derive(Clone)]
 pub struct PureElemImage<T> {
     data: Vec<T>,
     w: u32,
@@ -22,53 +26,710 @@ impl<T> PureElemImage<T> {
     pub fn data(&self) -> &Vec<T> {
         &self.data
     }
+
+    pub(crate) fn data_mut(&mut self) -> &mut Vec<T> {
+        &mut self.data
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        pixel_index(x, y, self.w)
+    }
+
+    /// like `in_box` for unsigned coordinates
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x < self.w && y < self.h
+    }
 }
 
+/// number of elements for `w * h` pixels with `channels` elements per pixel, computed in `usize` so it can't overflow `u32`
+fn elem_count(w: u32, h: u32, channels: usize) -> usize {
+    w as usize * h as usize * channels
+}
+
+/// index of pixel `(x, y)` in an image of width `w`, in `usize` as `elem_count`
+fn pixel_index(x: u32, y: u32, w: u32) -> usize {
+    x as usize + y as usize * w as usize
+}
+
+/// pixels cleared by one task when clear in parallel
+#[cfg(feature = "rayon")]
+const CLEAR_TILE_PIXELS: usize = 64 * 64;
+
 impl PureElemImage<u8> {
     pub fn new(w: u32, h: u32) -> Self {
         Self {
-            data: vec![0; (w * 3 * h) as usize],
+            data: vec![0; elem_count(w, h, 3)],
             w,
             h,
         }
     }
 
     pub fn clear(&mut self, color: &math::Vec4) {
-        for x in 0..self.w {
-            for y in 0..self.h {
-                self.set(x, y, color);
+        // quantize once, then copy bytes
+        let rgb = rgb_bytes(color);
+        let fill_rgb = |data: &mut [u8]| {
+            data.chunks_exact_mut(3)
+                .for_each(|pixel| pixel.copy_from_slice(&rgb))
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            self.data
+                .par_chunks_mut(CLEAR_TILE_PIXELS * 3)
+                .for_each(fill_rgb);
+        }
+        #[cfg(not(feature = "rayon"))]
+        fill_rgb(&mut self.data);
+    }
+
+    /// write out of image is ignored
+    pub fn set(&mut self, x: u32, y: u32, color: &math::Vec4) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let index = self.index(x, y) * 3;
+        self.data[index..index + 3].copy_from_slice(&rgb_bytes(color));
+    }
+
+    /// set every pixel to `f(x, y)`, for gradient or checkerboard backgrounds
+    pub fn clear_with<F>(&mut self, f: F)
+    where
+        F: Fn(u32, u32) -> math::Vec4,
+    {
+        let w = self.w as usize;
+        for (i, pixel) in self.data.chunks_exact_mut(3).enumerate() {
+            pixel.copy_from_slice(&rgb_bytes(&f((i % w) as u32, (i / w) as u32)));
+        }
+    }
+
+    /// normalized color of pixel, alpha is always 1, panic if `(x, y)` is out of image
+    pub fn get(&self, x: u32, y: u32) -> math::Vec4 {
+        assert!(
+            x < self.w && y < self.h,
+            "pixel ({}, {}) out of {}x{} color attachment",
+            x,
+            y,
+            self.w,
+            self.h
+        );
+        let index = self.index(x, y) * 3;
+        math::Vec4::new(
+            self.data[index] as f32 / 255.0,
+            self.data[index + 1] as f32 / 255.0,
+            self.data[index + 2] as f32 / 255.0,
+            1.0,
+        )
+    }
+
+    /// copy into an `image::RgbImage`, so it can be saved in any format `image` crate supports
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_raw(self.w, self.h, self.data.clone())
+            .expect("color attachment size mismatch")
+    }
+
+    /// write a PNG file, row 0 is the top of the image so no flip is needed
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> image::ImageResult<()> {
+        self.to_image()
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// write a binary PPM(P6) file without going through `image` crate
+    pub fn save_ppm<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.w, self.h)?;
+        file.write_all(&self.data)?;
+        file.flush()
+    }
+
+    /// set every pixel to `f(x, y, origin)`, `origin` is a copy before this pass, so neighbor reads are not affected by written pixels
+    pub fn process<F>(&mut self, f: F)
+    where
+        F: Fn(u32, u32, &ColorAttachment) -> math::Vec4,
+    {
+        let origin = self.clone();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                self.set(x, y, &f(x, y, &origin));
+            }
+        }
+    }
+
+    /// average every `scale` x `scale` block into one pixel, size is rounded down
+    pub fn downsample(&self, scale: u32) -> ColorAttachment {
+        let mut result = ColorAttachment::new(self.w / scale, self.h / scale);
+        let count = (scale * scale) as f32;
+        for y in 0..result.h {
+            for x in 0..result.w {
+                let mut sum = [0.0f32; 3];
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let index = self.index(x * scale + sx, y * scale + sy) * 3;
+                        for (channel, sum) in sum.iter_mut().enumerate() {
+                            *sum += self.data[index + channel] as f32;
+                        }
+                    }
+                }
+                let index = result.index(x, y) * 3;
+                for (channel, sum) in sum.iter().enumerate() {
+                    result.data[index + channel] = (sum / count).round() as u8;
+                }
             }
         }
+        result
     }
 
+    /// unsharp mask: `color + amount * (color - blur)`, blur is a 3x3 box filter
+    pub fn sharpen(&mut self, amount: f32) {
+        let w = self.w as i32;
+        let h = self.h as i32;
+        let mut result = self.data.clone();
+        for y in 0..h {
+            for x in 0..w {
+                for channel in 0..3 {
+                    let mut sum = 0.0;
+                    let mut count = 0.0;
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            if self.in_box(x + dx, y + dy) {
+                                sum += self.data[((x + dx + (y + dy) * w) * 3 + channel) as usize]
+                                    as f32;
+                                count += 1.0;
+                            }
+                        }
+                    }
+                    let index = ((x + y * w) * 3 + channel) as usize;
+                    let color = self.data[index] as f32;
+                    let blur = sum / count;
+                    result[index] = (color + amount * (color - blur)).clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+        self.data = result;
+    }
+}
+
+/// 4 channels color, unlike `ColorAttachment` alpha is stored
+impl PureElemImage<[u8; 4]> {
+    pub fn new(w: u32, h: u32) -> Self {
+        Self {
+            data: vec![[0; 4]; elem_count(w, h, 1)],
+            w,
+            h,
+        }
+    }
+
+    pub fn clear(&mut self, color: &math::Vec4) {
+        self.data.fill(rgba_bytes(color));
+    }
+
+    /// like `ColorAttachment::clear_with`, alpha of `f` is kept
+    pub fn clear_with<F>(&mut self, f: F)
+    where
+        F: Fn(u32, u32) -> math::Vec4,
+    {
+        let w = self.w as usize;
+        for (i, pixel) in self.data.iter_mut().enumerate() {
+            *pixel = rgba_bytes(&f((i % w) as u32, (i / w) as u32));
+        }
+    }
+
+    /// write out of image is ignored
     pub fn set(&mut self, x: u32, y: u32, color: &math::Vec4) {
-        self.data[(x + y * self.w) as usize * 3] = (color.x * 255.0) as u8;
-        self.data[(x + y * self.w) as usize * 3 + 1] = (color.y * 255.0) as u8;
-        self.data[(x + y * self.w) as usize * 3 + 2] = (color.z * 255.0) as u8;
+        if !self.contains(x, y) {
+            return;
+        }
+        let index = self.index(x, y);
+        self.data[index] = rgba_bytes(color);
+    }
+
+    /// normalized color of pixel, panic if `(x, y)` is out of image
+    pub fn get(&self, x: u32, y: u32) -> math::Vec4 {
+        assert!(
+            x < self.w && y < self.h,
+            "pixel ({}, {}) out of {}x{} RGBA color attachment",
+            x,
+            y,
+            self.w,
+            self.h
+        );
+        let [r, g, b, a] = self.data[self.index(x, y)].map(|c| c as f32 / 255.0);
+        math::Vec4::new(r, g, b, a)
+    }
+
+    /// tightly packed RGBA8 bytes
+    pub fn bytes(&self) -> &[u8] {
+        self.data.as_flattened()
+    }
+
+    /// copy into an `image::RgbaImage`, so it can be saved in any format `image` crate supports
+    pub fn to_image(&self) -> image::RgbaImage {
+        image::RgbaImage::from_raw(self.w, self.h, self.bytes().to_vec())
+            .expect("color attachment size mismatch")
     }
 }
 
+fn rgb_bytes(color: &math::Vec4) -> [u8; 3] {
+    [
+        (color.x * 255.0) as u8,
+        (color.y * 255.0) as u8,
+        (color.z * 255.0) as u8,
+    ]
+}
+
+fn rgba_bytes(color: &math::Vec4) -> [u8; 4] {
+    [
+        (color.x * 255.0) as u8,
+        (color.y * 255.0) as u8,
+        (color.z * 255.0) as u8,
+        (color.w * 255.0) as u8,
+    ]
+}
+
 impl PureElemImage<f32> {
     pub fn new(w: u32, h: u32) -> Self {
         Self {
-            data: vec![0.0; (w * h) as usize],
+            data: vec![0.0; elem_count(w, h, 1)],
             w,
             h,
         }
     }
 
     pub fn clear(&mut self, value: f32) {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            self.data
+                .par_chunks_mut(CLEAR_TILE_PIXELS)
+                .for_each(|tile| tile.fill(value));
+        }
+        #[cfg(not(feature = "rayon"))]
         self.data.fill(value);
     }
 
+    /// write out of image is ignored
     pub fn set(&mut self, x: u32, y: u32, value: f32) {
-        self.data[(x + y * self.w) as usize] = value;
+        if !self.contains(x, y) {
+            return;
+        }
+        let index = self.index(x, y);
+        self.data[index] = value;
     }
 
     pub fn get(&self, x: u32, y: u32) -> f32 {
-        self.data[(x + y * self.w) as usize]
+        self.data[self.index(x, y)]
+    }
+
+    /// like `ColorAttachment::process`
+    pub fn process<F>(&mut self, f: F)
+    where
+        F: Fn(u32, u32, &DepthAttachment) -> f32,
+    {
+        let origin = self.clone();
+        for y in 0..self.h {
+            for x in 0..self.w {
+                self.set(x, y, f(x, y, &origin));
+            }
+        }
+    }
+
+    /// encode depth into 24 bits, `(depth - near) / (far - near)` is clamped into `[0, 1]`,
+    /// then scaled to `[0, 2^24 - 1]` and stored big-endian in R, G, B
+    pub fn pack_to_color(&self, near: f32, far: f32) -> ColorAttachment {
+        let mut color = ColorAttachment::new(self.w, self.h);
+        for (depth, rgb) in self.data.iter().zip(color.data.chunks_exact_mut(3)) {
+            let t = ((depth - near) / (far - near)).clamp(0.0, 1.0);
+            let value = (t * DEPTH_PACK_MAX as f32).round() as u32;
+            rgb.copy_from_slice(&value.to_be_bytes()[1..]);
+        }
+        color
+    }
+
+    /// decode depth encoded by `pack_to_color`
+    pub fn unpack_from_color(color: &ColorAttachment, near: f32, far: f32) -> Self {
+        let mut depth = DepthAttachment::new(color.w, color.h);
+        for (value, rgb) in depth.data.iter_mut().zip(color.data.chunks_exact(3)) {
+            let packed = u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]]);
+            *value = near + (far - near) * packed as f32 / DEPTH_PACK_MAX as f32;
+        }
+        depth
+    }
+
+    /// write a 16-bit grayscale PNG, depth in `range` is scaled to `[0, 65535]`(out of range depth is clamped),
+    /// cleared pixels(`f32::MAX`) are written as 65535
+    pub fn save_png16<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        range: DepthRange,
+    ) -> image::ImageResult<()> {
+        let data = self
+            .data
+            .iter()
+            .map(|&depth| {
+                if depth == f32::MAX {
+                    return u16::MAX;
+                }
+                let t = (depth - range.near) / (range.far - range.near);
+                (t.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+            })
+            .collect();
+        image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(self.w, self.h, data)
+            .expect("depth attachment size mismatch")
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// load depth saved by `save_png16`(or any image, converted to 16-bit grayscale),
+    /// 65535 is loaded as cleared depth(`f32::MAX`), which passes depth test as `range.far` does
+    pub fn load_png16<P: AsRef<std::path::Path>>(
+        path: P,
+        range: DepthRange,
+    ) -> image::ImageResult<Self> {
+        let image = image::open(path)?.into_luma16();
+        Ok(Self {
+            w: image.width(),
+            h: image.height(),
+            data: image
+                .into_raw()
+                .into_iter()
+                .map(|value| {
+                    if value == u16::MAX {
+                        f32::MAX
+                    } else {
+                        range.near + (range.far - range.near) * value as f32 / u16::MAX as f32
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+const DEPTH_PACK_MAX: u32 = (1 << 24) - 1;
+
+// stored for pixels no triangle covers
+const NO_ID: u32 = u32::MAX;
+
+impl PureElemImage<math::Vec3> {
+    pub fn new(w: u32, h: u32) -> Self {
+        Self {
+            data: vec![math::Vec3::zero(); elem_count(w, h, 1)],
+            w,
+            h,
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> math::Vec3 {
+        self.data[self.index(x, y)]
+    }
+
+    /// add `weight` times of packed RGB8 pixels(as `ColorAttachment::data`), which must have the same size
+    pub fn accumulate_rgb(&mut self, rgb: &[u8], weight: f32) {
+        assert_eq!(rgb.len(), self.data.len() * 3);
+        for (value, pixel) in self.data.iter_mut().zip(rgb.chunks_exact(3)) {
+            *value += math::Vec3::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32)
+                * (weight / 255.0);
+        }
+    }
+
+    /// clamp into `[0, 1]` and quantize to 8 bits
+    pub fn to_color(&self) -> ColorAttachment {
+        let mut color = ColorAttachment::new(self.w, self.h);
+        for (i, value) in self.data.iter().enumerate() {
+            let (x, y) = (i as u32 % self.w, i as u32 / self.w);
+            let value = math::Vec3::new(
+                value.x.clamp(0.0, 1.0),
+                value.y.clamp(0.0, 1.0),
+                value.z.clamp(0.0, 1.0),
+            );
+            color.set(x, y, &math::Vec4::from_vec3(&value, 1.0));
+        }
+        color
+    }
+}
+
+impl PureElemImage<u32> {
+    pub fn new(w: u32, h: u32) -> Self {
+        Self {
+            data: vec![NO_ID; elem_count(w, h, 1)],
+            w,
+            h,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.fill(NO_ID);
+    }
+
+    /// write out of image is ignored
+    pub fn set(&mut self, x: u32, y: u32, id: u32) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let index = self.index(x, y);
+        self.data[index] = id;
+    }
+
+    /// `None` if no triangle covers the pixel or `(x, y)` is out of image
+    pub fn get(&self, x: u32, y: u32) -> Option<u32> {
+        if !self.contains(x, y) {
+            return None;
+        }
+        Some(self.data[self.index(x, y)]).filter(|id| *id != NO_ID)
     }
 }
 
 pub type ColorAttachment = PureElemImage<u8>;
+/// linear float color, for accumulating frames before quantization
+pub type HdrAttachment = PureElemImage<math::Vec3>;
+/// opt-in RGBA color, for compositing over a background
+pub type RgbaColorAttachment = PureElemImage<[u8; 4]>;
 pub type DepthAttachment = PureElemImage<f32>;
+/// draw-order index of the triangle which wrote each pixel, for picking
+pub type IdAttachment = PureElemImage<u32>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn color_attachment_to_image() {
+        let mut color_attachment = ColorAttachment::new(4, 3);
+        color_attachment.clear(&math::Vec4::new(1.0, 0.0, 1.0, 1.0));
+
+        let image = color_attachment.to_image();
+        assert_eq!(image.width(), 4);
+        assert_eq!(image.height(), 3);
+        assert_eq!(image.get_pixel(2, 1).0, [255, 0, 255]);
+    }
+
+    #[test]
+    fn rgba_attachment_keep_alpha() {
+        let mut color_attachment = RgbaColorAttachment::new(4, 3);
+        color_attachment.clear(&math::Vec4::new(0.0, 0.0, 0.0, 0.0));
+        let color = math::Vec4::new(1.0, 0.0, 1.0, 128.0 / 255.0);
+        color_attachment.set(2, 1, &color);
+
+        assert_eq!(color_attachment.get(2, 1), color);
+        assert_eq!(color_attachment.get(0, 0).w, 0.0);
+        assert_eq!(color_attachment.bytes().len(), 4 * 3 * 4);
+        assert_eq!(&color_attachment.bytes()[24..28], &[255, 0, 255, 128]);
+        assert_eq!(
+            color_attachment.to_image().get_pixel(2, 1).0,
+            [255, 0, 255, 128]
+        );
+    }
+
+    #[test]
+    fn large_attachment_size() {
+        // `w * h * 3` exceeds `u32::MAX`, checked without allocating
+        let (w, h) = (65536, 32768);
+        assert!(elem_count(w, h, 3) > u32::MAX as usize);
+        assert_eq!(elem_count(w, h, 3), 65536usize * 32768 * 3);
+        assert_eq!(elem_count(w, h, 1), 65536usize * 32768);
+        assert_eq!(pixel_index(w - 1, h - 1, w), 65536usize * 32768 - 1);
+
+        let color_attachment = ColorAttachment::new(123, 77);
+        assert_eq!(color_attachment.data().len(), elem_count(123, 77, 3));
+        let depth_attachment = DepthAttachment::new(123, 77);
+        assert_eq!(depth_attachment.data().len(), elem_count(123, 77, 1));
+    }
+
+    #[test]
+    fn clear_all_pixels() {
+        let mut color_attachment = ColorAttachment::new(123, 77);
+        color_attachment.clear(&math::Vec4::new(0.2, 0.4, 1.0, 1.0));
+        assert!(color_attachment
+            .data()
+            .chunks_exact(3)
+            .all(|pixel| pixel == [51, 102, 255]));
+
+        let mut depth_attachment = DepthAttachment::new(123, 77);
+        depth_attachment.clear(0.75);
+        assert!(depth_attachment.data().iter().all(|depth| *depth == 0.75));
+    }
+
+    #[test]
+    fn pack_depth_to_color() {
+        let mut depth_attachment = DepthAttachment::new(2, 2);
+        depth_attachment.clear(3.7);
+        depth_attachment.set(1, 1, 100.0);
+
+        let color = depth_attachment.pack_to_color(1.0, 10.0);
+        let unpacked = DepthAttachment::unpack_from_color(&color, 1.0, 10.0);
+
+        assert!((unpacked.get(0, 0) - 3.7).abs() < 9.0 / DEPTH_PACK_MAX as f32);
+        // out of range depth is clamped
+        assert_eq!(unpacked.get(1, 1), 10.0);
+    }
+
+    #[test]
+    fn depth_png16_round_trip() {
+        let mut depth_attachment = DepthAttachment::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                depth_attachment.set(x, y, (x + y * 3) as f32 * 0.17);
+            }
+        }
+        depth_attachment.set(2, 1, f32::MAX);
+        let path = std::env::temp_dir().join("rs_cpurenderer_depth_png16.png");
+        depth_attachment
+            .save_png16(&path, DepthRange::default())
+            .unwrap();
+        let loaded = DepthAttachment::load_png16(&path, DepthRange::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width(), loaded.height()), (3, 2));
+        assert_eq!(loaded.get(2, 1), f32::MAX);
+        for (expect, value) in depth_attachment.data().iter().zip(loaded.data()).take(5) {
+            assert!((expect - value).abs() <= 1.0 / u16::MAX as f32);
+        }
+    }
+
+    #[test]
+    fn color_png_and_ppm_round_trip() {
+        let mut color_attachment = ColorAttachment::new(3, 2);
+        color_attachment.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+        color_attachment.set(0, 0, &math::Vec4::new(1.0, 0.0, 0.0, 1.0));
+        color_attachment.set(2, 0, &math::Vec4::new(0.0, 1.0, 0.0, 1.0));
+        color_attachment.set(1, 1, &math::Vec4::new(0.0, 0.0, 1.0, 1.0));
+
+        let png_path = std::env::temp_dir().join("rs_cpurenderer_color.png");
+        let ppm_path = std::env::temp_dir().join("rs_cpurenderer_color.ppm");
+        color_attachment.save_png(&png_path).unwrap();
+        color_attachment.save_ppm(&ppm_path).unwrap();
+        let png = image::open(&png_path).unwrap().into_rgb8();
+        let ppm = image::open(&ppm_path).unwrap().into_rgb8();
+        std::fs::remove_file(&png_path).unwrap();
+        std::fs::remove_file(&ppm_path).unwrap();
+
+        for loaded in [png, ppm] {
+            assert_eq!(loaded.dimensions(), (3, 2));
+            assert_eq!(loaded.get_pixel(0, 0).0, [255, 0, 0]);
+            assert_eq!(loaded.get_pixel(2, 0).0, [0, 255, 0]);
+            assert_eq!(loaded.get_pixel(1, 1).0, [0, 0, 255]);
+            assert_eq!(loaded.get_pixel(0, 1).0, [0, 0, 0]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "out of 4x4 color attachment")]
+    fn color_get_out_of_bounds() {
+        ColorAttachment::new(4, 4).get(4, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of 4x4 RGBA color attachment")]
+    fn rgba_get_out_of_bounds() {
+        RgbaColorAttachment::new(4, 4).get(4, 0);
+    }
+
+    #[test]
+    fn id_get_out_of_bounds() {
+        let mut id_attachment = IdAttachment::new(4, 4);
+        id_attachment.set(0, 1, 7);
+        assert_eq!(id_attachment.get(0, 1), Some(7));
+        // would wrap to (0, 1) without bounds check
+        assert_eq!(id_attachment.get(4, 0), None);
+        assert_eq!(id_attachment.get(0, 4), None);
+    }
+
+    #[test]
+    fn process_box_blur() {
+        let mut color_attachment = ColorAttachment::new(5, 5);
+        for y in 0..5 {
+            for x in 0..5 {
+                let v = ((x * 7 + y * 13) % 10) as f32 / 10.0;
+                color_attachment.set(x, y, &math::Vec4::new(v, 1.0 - v, 0.5, 1.0));
+            }
+        }
+        let origin = color_attachment.clone();
+
+        let box_blur = |x: u32, y: u32, image: &ColorAttachment| {
+            let mut sum = math::Vec4::zero();
+            let mut count = 0.0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                    if image.in_box(nx, ny) {
+                        sum += image.get(nx as u32, ny as u32);
+                        count += 1.0;
+                    }
+                }
+            }
+            sum / count
+        };
+        color_attachment.process(box_blur);
+
+        let mut expected = 0.0;
+        for y in 1..=3 {
+            for x in 1..=3 {
+                expected += origin.get(x, y).x;
+            }
+        }
+        expected /= 9.0;
+        assert!((color_attachment.get(2, 2).x - expected).abs() <= 1.0 / 255.0);
+        // later pixels read the origin, not the blurred ones
+        assert!((color_attachment.get(3, 2).x - box_blur(3, 2, &origin).x).abs() <= 1.0 / 255.0);
+
+        let mut depth_attachment = DepthAttachment::new(3, 1);
+        depth_attachment.set(0, 0, 1.0);
+        depth_attachment.process(|x, y, origin| if x > 0 { origin.get(x - 1, y) } else { 0.0 });
+        assert_eq!(depth_attachment.data(), &vec![0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn sharpen_step_edge() {
+        let mut color_attachment = ColorAttachment::new(6, 3);
+        let dark = math::Vec4::new(0.25, 0.25, 0.25, 1.0);
+        let bright = math::Vec4::new(0.75, 0.75, 0.75, 1.0);
+        for y in 0..3 {
+            for x in 0..6 {
+                color_attachment.set(x, y, if x < 3 { &dark } else { &bright });
+            }
+        }
+        let contrast = |image: &ColorAttachment| {
+            let left = image.data()[(2 + 6) * 3] as i32;
+            let right = image.data()[(3 + 6) * 3] as i32;
+            right - left
+        };
+        let old_contrast = contrast(&color_attachment);
+
+        color_attachment.sharpen(1.0);
+
+        assert!(contrast(&color_attachment) > old_contrast);
+    }
+
+    #[test]
+    fn set_out_of_bounds_ignored() {
+        let mut color = ColorAttachment::new(4, 3);
+        let mut depth = DepthAttachment::new(4, 3);
+        depth.clear(1.0);
+        // without check, (w, 0) would wrap to (0, 1) and (w, h) would index past data
+        for (x, y) in [(4, 3), (4, 0), (0, 3), (u32::MAX, 0)] {
+            color.set(x, y, &math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+            depth.set(x, y, 0.5);
+        }
+        assert!(color.data().iter().all(|&c| c == 0));
+        assert!(depth.data().iter().all(|&d| d == 1.0));
+        assert_eq!(color.get(0, 0), math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn clear_with_gradient() {
+        let gradient = |x: u32, _: u32| math::Vec4::new(x as f32 / 7.0, 0.5, 1.0, 0.25);
+        let mut color = ColorAttachment::new(8, 3);
+        color.clear_with(gradient);
+        let mut expect = ColorAttachment::new(8, 3);
+        for y in 0..3 {
+            for x in 0..8 {
+                expect.set(x, y, &gradient(x, y));
+            }
+        }
+        assert_eq!(color.data(), expect.data());
+
+        let mut rgba = RgbaColorAttachment::new(8, 3);
+        rgba.clear_with(gradient);
+        assert_eq!(rgba.bytes()[4..8], [36, 127, 255, 63]);
+    }
+}
@@ -6,7 +6,10 @@ mod line;
 pub mod math;
 pub mod model;
 pub mod obj_loader;
+pub mod postprocess;
+pub mod preset;
 pub mod renderer;
 mod scanline;
 pub mod shader;
 pub mod texture;
+pub mod tonemap;
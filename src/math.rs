@@ -72,6 +72,21 @@ macro_rules! declare_vec {
                 *self / self.length()
             }
 
+            /// return zero vector when length is near zero
+            pub fn normalize_or_zero(&self) -> $name {
+                self.normalize_or($name::zero())
+            }
+
+            /// return `fallback` when length is near zero(or NaN)
+            pub fn normalize_or(&self, fallback: $name) -> $name {
+                let length = self.length();
+                if length > f32::EPSILON {
+                    *self / length
+                } else {
+                    fallback
+                }
+            }
+
             pub fn dot(&self, rhs: &$name) -> f32 {
                 $(
                     self.$mem * rhs.$mem +
@@ -515,7 +530,28 @@ pub fn reflect(v: &Vec3, normal: &Vec3) -> Vec3 {
     2.0 * (v.dot(normal)) * *normal - *v
 }
 
+/// orthonormalize three roughly independent vectors, `v0` keeps its direction
+pub fn gram_schmidt(v0: &Vec3, v1: &Vec3, v2: &Vec3) -> (Vec3, Vec3, Vec3) {
+    let e0 = v0.normalize();
+    let e1 = (*v1 - v1.dot(&e0) * e0).normalize();
+    let e2 = (*v2 - v2.dot(&e0) * e0 - v2.dot(&e1) * e1).normalize();
+    (e0, e1, e2)
+}
+
+/// tangent and bitangent completing a right handed orthonormal basis with unit normal `n`,
+/// branchless construction from Duff et al. 2017, "Building an Orthonormal Basis, Revisited"
+pub fn basis_from_normal(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
 // Quaternion
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Quaternion {
     pub s: f32,
     pub v: Vec3,
@@ -587,6 +623,34 @@ impl Quaternion {
         self.length_square().sqrt()
     }
 
+    pub fn dot(&self, rhs: &Quaternion) -> f32 {
+        self.s * rhs.s + self.v.dot(&rhs.v)
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        *self / self.length()
+    }
+
+    /// spherical linear interpolation along the shortest path, inputs are normalized internally
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f32) -> Quaternion {
+        let a = a.normalize();
+        let mut b = b.normalize();
+        let mut cos_theta = a.dot(&b);
+        if cos_theta < 0.0 {
+            b = -b;
+            cos_theta = -cos_theta;
+        }
+
+        // nearly the same orientation, sin(theta) is too small to divide, use nlerp instead
+        if cos_theta > 0.9995 {
+            return (a + (b - a) * t).normalize();
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        a * (((1.0 - t) * theta).sin() / sin_theta) + b * ((t * theta).sin() / sin_theta)
+    }
+
     pub fn conjugate(&self) -> Quaternion {
         Quaternion {
             s: self.s,
@@ -605,6 +669,38 @@ impl Quaternion {
     pub fn inverse(&self) -> Quaternion {
         self.conjugate() / self.length_square()
     }
+
+    /// rotate `angle` radians around `axis`, axis is normalized internally
+    pub fn from_axis_angle(axis: &Vec3, angle: f32) -> Quaternion {
+        let half = angle * 0.5;
+        Quaternion {
+            s: half.cos(),
+            v: axis.normalize() * half.sin(),
+        }
+    }
+
+    /// rotation matrix, assume quaternion is normalized
+    #[rustfmt::skip]
+    pub fn to_mat3(&self) -> Mat3 {
+        let (s, x, y, z) = (self.s, self.v.x, self.v.y, self.v.z);
+        Mat3::from_row(&[
+            1.0 - 2.0 * (y * y + z * z),       2.0 * (x * y - s * z),       2.0 * (x * z + s * y),
+                  2.0 * (x * y + s * z), 1.0 - 2.0 * (x * x + z * z),       2.0 * (y * z - s * x),
+                  2.0 * (x * z - s * y),       2.0 * (y * z + s * x), 1.0 - 2.0 * (x * x + y * y),
+        ])
+    }
+
+    /// rotation matrix, assume quaternion is normalized
+    #[rustfmt::skip]
+    pub fn to_mat4(&self) -> Mat4 {
+        let m = self.to_mat3();
+        Mat4::from_row(&[
+            m.get(0, 0), m.get(1, 0), m.get(2, 0), 0.0,
+            m.get(0, 1), m.get(1, 1), m.get(2, 1), 0.0,
+            m.get(0, 2), m.get(1, 2), m.get(2, 2), 0.0,
+                    0.0,         0.0,         0.0, 1.0,
+        ])
+    }
 }
 
 #[rustfmt::skip]
@@ -617,6 +713,18 @@ pub fn create_translate(offset: &Vec3) -> Mat4 {
     ])
 }
 
+/// map box `[left, right] x [bottom, top] x [-far, -near]` in view space(looking at -z) into NDC `[-1, 1]`,
+/// `near` maps to -1 and `far` maps to 1
+#[rustfmt::skip]
+pub fn create_orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Mat4 {
+    Mat4::from_row(&[
+        2.0 / (right - left),                  0.0,                 0.0, -(right + left) / (right - left),
+                         0.0, 2.0 / (top - bottom),                 0.0, -(top + bottom) / (top - bottom),
+                         0.0,                  0.0, -2.0 / (far - near),     -(far + near) / (far - near),
+                         0.0,                  0.0,                 0.0,                              1.0,
+    ])
+}
+
 #[rustfmt::skip]
 pub fn create_scale(scale: &Vec3) -> Mat4 {
     Mat4::from_row(&[
@@ -792,6 +900,200 @@ mod test {
         ]);
         assert_eq!(result, check_result);
     }
+
+    #[test]
+    fn normalize_zero_vector() {
+        let zero = Vec3::zero().normalize_or_zero();
+        assert!(!zero.x.is_nan() && !zero.y.is_nan() && !zero.z.is_nan());
+        assert_eq!(zero, Vec3::zero());
+
+        assert_eq!(Vec2::zero().normalize_or(*Vec2::x_axis()), *Vec2::x_axis());
+        assert_eq!(
+            Vec3::new(0.0, 3.0, 0.0).normalize_or_zero(),
+            *Vec3::y_axis()
+        );
+    }
+
+    #[test]
+    fn gram_schmidt_orthonormal() {
+        let (e0, e1, e2) = gram_schmidt(
+            &Vec3::new(1.0, 0.2, 0.1),
+            &Vec3::new(0.3, 1.0, -0.2),
+            &Vec3::new(0.1, 0.4, 1.0),
+        );
+
+        for e in [e0, e1, e2] {
+            assert!((e.length() - 1.0).abs() < 1e-5);
+        }
+        assert!(e0.dot(&e1).abs() < 1e-5);
+        assert!(e0.dot(&e2).abs() < 1e-5);
+        assert!(e1.dot(&e2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn quaternion_to_matrix() {
+        let v = Vec4::new(1.0, 2.0, 3.0, 1.0);
+        let cases = [
+            (*Vec3::x_axis(), create_eular_rotate_x(PI_DIV_2)),
+            (*Vec3::y_axis(), create_eular_rotate_y(PI_DIV_2)),
+            (*Vec3::z_axis(), create_eular_rotate_z(PI_DIV_2)),
+        ];
+        for (axis, expect) in cases {
+            let q = Quaternion::from_axis_angle(&axis, PI_DIV_2);
+            assert!((q.to_mat4() * v - expect * v).length() < 1e-5);
+            let rotated = q.to_mat3() * v.truncated_to_vec3();
+            assert!((rotated - (expect * v).truncated_to_vec3()).length() < 1e-5);
+        }
+
+        let q = Quaternion::from_axis_angle(&Vec3::new(0.0, 2.0, 0.0), 0.3);
+        assert!((q.length() - 1.0).abs() < 1e-6);
+        assert!(
+            (q.to_mat4() * v - create_eular_rotate_xyz(&Vec3::new(0.0, 0.3, 0.0)) * v).length()
+                < 1e-5
+        );
+    }
+
+    #[test]
+    fn berycentric_both_windings() {
+        let triangle = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        let reversed = [triangle[0], triangle[2], triangle[1]];
+        let values = [1.0, 2.0, 3.0];
+        let pt = Vec2::new(1.0, 2.0);
+
+        let forward = Berycentric::new(&pt, &triangle);
+        let backward = Berycentric::new(&pt, &reversed);
+        assert!(forward.is_valid() && backward.is_valid());
+        let interp =
+            |b: &Berycentric, v: [f32; 3]| b.alpha() * v[0] + b.beta() * v[1] + b.gamma() * v[2];
+        // value is linear: 1 + x / 4 + y / 2
+        assert!((interp(&forward, values) - 2.25).abs() < 1e-5);
+        assert!((interp(&backward, [values[0], values[2], values[1]]) - 2.25).abs() < 1e-5);
+
+        for outside in [
+            Vec2::new(3.0, 3.0),
+            Vec2::new(-1.0, 1.0),
+            Vec2::new(1.0, -0.5),
+        ] {
+            assert!(!Berycentric::new(&outside, &triangle).is_valid());
+            assert!(!Berycentric::new(&outside, &reversed).is_valid());
+        }
+
+        let degenerate = [triangle[0], triangle[1], triangle[1]];
+        assert!(!Berycentric::new(&pt, &degenerate).is_valid());
+    }
+
+    #[test]
+    fn berycentric_top_left_edges() {
+        let triangle = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        let inside = |x: f32, y: f32| Berycentric::new(&Vec2::new(x, y), &triangle);
+        // top and left edges, and the vertex between them
+        assert!(inside(2.0, 0.0).is_inside_top_left(&triangle));
+        assert!(inside(0.0, 2.0).is_inside_top_left(&triangle));
+        assert!(inside(0.0, 0.0).is_inside_top_left(&triangle));
+        // right edge
+        assert!(!inside(2.0, 2.0).is_inside_top_left(&triangle));
+        assert!(inside(2.0, 2.0).is_valid());
+        assert!(inside(1.0, 1.0).is_inside_top_left(&triangle));
+    }
+
+    #[test]
+    fn quaternion_inverse() {
+        let q = Quaternion {
+            s: 2.0,
+            v: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let identity = Quaternion::mul(&q, &q.inverse());
+        assert!((identity.s - 1.0).abs() < 1e-6);
+        assert!(identity.v.length() < 1e-6);
+    }
+
+    #[test]
+    fn quaternion_slerp() {
+        let a = Quaternion::from_axis_angle(Vec3::y_axis(), 0.2);
+        let b = Quaternion::from_axis_angle(&Vec3::new(1.0, 1.0, 0.0), 2.0);
+        let near = |q1: &Quaternion, q2: &Quaternion| (*q1 - *q2).length() < 1e-5;
+
+        assert!(near(&Quaternion::slerp(&a, &b, 0.0), &a));
+        assert!(near(&Quaternion::slerp(&a, &b, 1.0), &b));
+        assert!((Quaternion::slerp(&a, &b, 0.5).length() - 1.0).abs() < 1e-5);
+
+        // same rotation with opposite sign goes the short way
+        let mid = Quaternion::slerp(&a, &-b, 0.5);
+        assert!(near(&mid, &Quaternion::slerp(&a, &b, 0.5)));
+
+        // tiny angle falls back to nlerp
+        let c = Quaternion::from_axis_angle(Vec3::y_axis(), 0.2001);
+        assert!((Quaternion::slerp(&a, &c, 0.5).length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_box_to_ndc() {
+        let (left, right, bottom, top, near, far) = (-3.0, 1.0, -1.0, 2.0, 0.5, 20.0);
+        let mat = create_orthographic(left, right, bottom, top, near, far);
+        for x in [left, right] {
+            for y in [bottom, top] {
+                for z in [-near, -far] {
+                    let ndc = mat * Vec4::new(x, y, z, 1.0);
+                    assert_eq!(ndc.w, 1.0);
+                    let expect = Vec3::new(
+                        if x == left { -1.0 } else { 1.0 },
+                        if y == bottom { -1.0 } else { 1.0 },
+                        if z == -near { -1.0 } else { 1.0 },
+                    );
+                    assert!((ndc.truncated_to_vec3() - expect).length() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dot_cross_consistency() {
+        let a = Vec3::new(1.0, -2.0, 0.5);
+        let b = Vec3::new(0.3, 0.7, -1.5);
+        let c = a.cross(&b);
+        assert!(c.dot(&a).abs() < 1e-5);
+        assert!(c.dot(&b).abs() < 1e-5);
+        assert_eq!(b.cross(&a), -c);
+        assert_eq!(
+            Vec4::from_vec3(&a, 0.0).dot(&Vec4::from_vec3(&b, 0.0)),
+            a.dot(&b)
+        );
+    }
+
+    #[test]
+    fn basis_from_normal_orthonormal() {
+        let mut normals = vec![
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1e-4, 0.0, -1.0).normalize(),
+            Vec3::new(0.0, -1e-4, 1.0).normalize(),
+        ];
+        // spread over the sphere
+        for i in 0..200 {
+            let z = 1.0 - 2.0 * (i as f32 + 0.5) / 200.0;
+            let phi = i as f32 * 2.399_963;
+            let r = (1.0 - z * z).sqrt();
+            normals.push(Vec3::new(r * phi.cos(), r * phi.sin(), z));
+        }
+
+        for n in normals {
+            let (t, b) = basis_from_normal(&n);
+            assert!((t.length() - 1.0).abs() < 1e-4);
+            assert!((b.length() - 1.0).abs() < 1e-4);
+            assert!(t.dot(&n).abs() < 1e-4);
+            assert!(b.dot(&n).abs() < 1e-4);
+            assert!(t.dot(&b).abs() < 1e-4);
+            assert!((t.cross(&b) - n).length() < 1e-4);
+        }
+    }
 }
 
 pub fn lerp<T>(a: T, b: T, t: f32) -> T
@@ -808,17 +1110,41 @@ pub struct Berycentric {
 }
 
 impl Berycentric {
+    /// sub-areas are divided by the signed area of triangle, so coordinates are correct for both windings
     pub fn new(pt: &Vec2, triangle: &[Vec2; 3]) -> Self {
         let area_twice = (triangle[1] - triangle[0]).cross(&(triangle[2] - triangle[0]));
-        let alpha = ((triangle[1] - *pt).cross(&(triangle[2] - *pt)) / area_twice).abs();
-        let beta = ((triangle[0] - *pt).cross(&(triangle[2] - *pt)) / area_twice).abs();
-        let gamma = ((triangle[0] - *pt).cross(&(triangle[1] - *pt)) / area_twice).abs();
+        let alpha = (triangle[1] - *pt).cross(&(triangle[2] - *pt)) / area_twice;
+        let beta = (triangle[2] - *pt).cross(&(triangle[0] - *pt)) / area_twice;
+        let gamma = (triangle[0] - *pt).cross(&(triangle[1] - *pt)) / area_twice;
 
         Self { alpha, beta, gamma }
     }
 
+    /// `pt` is inside triangle(or on its edges), always false for degenerate triangle
     pub fn is_valid(&self) -> bool {
-        self.alpha + self.beta + self.gamma <= 1.000001
+        const EPSILON: f32 = -1e-6;
+        self.alpha >= EPSILON && self.beta >= EPSILON && self.gamma >= EPSILON
+    }
+
+    /// like `is_valid`, but a point on an edge is only inside when it's a top or left edge(top has smaller y),
+    /// so triangles sharing an edge never cover the same point twice
+    pub fn is_inside_top_left(&self, triangle: &[Vec2; 3]) -> bool {
+        const EPSILON: f32 = 1e-6;
+        // interior is at the right of a left edge, or below a horizontal top edge
+        let is_top_left = |a: Vec2, b: Vec2, opposite: Vec2| {
+            let edge = b - a;
+            let mut normal = Vec2::new(-edge.y, edge.x);
+            if normal.dot(&(opposite - a)) < 0.0 {
+                normal = -normal;
+            }
+            normal.x > 0.0 || (normal.x == 0.0 && normal.y > 0.0)
+        };
+        let inside = |weight: f32, a: Vec2, b: Vec2, opposite: Vec2| {
+            weight > EPSILON || (weight.abs() <= EPSILON && is_top_left(a, b, opposite))
+        };
+        inside(self.alpha, triangle[1], triangle[2], triangle[0])
+            && inside(self.beta, triangle[2], triangle[0], triangle[1])
+            && inside(self.gamma, triangle[0], triangle[1], triangle[2])
     }
 
     pub fn alpha(&self) -> f32 {
@@ -831,3 +1157,56 @@ impl Berycentric {
         self.gamma
     }
 }
+
+/// axis aligned bounding box
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// return `None` if there is no point
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = points.first()?;
+        let mut aabb = Aabb::new(*first, *first);
+        for p in points {
+            aabb.min = Vec3::new(
+                aabb.min.x.min(p.x),
+                aabb.min.y.min(p.y),
+                aabb.min.z.min(p.z),
+            );
+            aabb.max = Vec3::new(
+                aabb.max.x.max(p.x),
+                aabb.max.y.max(p.y),
+                aabb.max.z.max(p.z),
+            );
+        }
+        Some(aabb)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [
+            Vec3::new(self.min.x, self.min.y, self.min.z),
+            Vec3::new(self.max.x, self.min.y, self.min.z),
+            Vec3::new(self.min.x, self.max.y, self.min.z),
+            Vec3::new(self.max.x, self.max.y, self.min.z),
+            Vec3::new(self.min.x, self.min.y, self.max.z),
+            Vec3::new(self.max.x, self.min.y, self.max.z),
+            Vec3::new(self.min.x, self.max.y, self.max.z),
+            Vec3::new(self.max.x, self.max.y, self.max.z),
+        ]
+    }
+}
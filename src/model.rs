@@ -1,6 +1,12 @@
 use crate::math;
 use crate::obj_loader;
 use crate::obj_loader::Mtllib;
+use crate::renderer::FrontFace;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::{BitOr, BitOrAssign};
+
+pub mod primitives;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Vertex {
@@ -8,9 +14,13 @@ pub struct Vertex {
     pub normal: math::Vec3,
     pub texcoord: math::Vec2,
     pub color: math::Vec4,
+    /// direction of +U in model space, zero unless generated by `PreOperation::GEN_TANGENTS`
+    pub tangent: math::Vec3,
+    /// direction of +V in model space, zero unless generated by `PreOperation::GEN_TANGENTS`
+    pub bitangent: math::Vec3,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub name: Option<String>,
@@ -18,27 +28,67 @@ pub struct Mesh {
     pub material: Option<String>,
 }
 
-#[derive(PartialEq, Clone, Copy)]
-pub enum PreOperation {
-    None = 0x00,
-    RecalcNormal = 0x01,
+impl Mesh {
+    /// bounding box in model space, `None` if mesh is empty
+    pub fn aabb(&self) -> Option<math::Aabb> {
+        math::Aabb::from_points(
+            &self
+                .vertices
+                .iter()
+                .map(|v| v.position)
+                .collect::<Vec<math::Vec3>>(),
+        )
+    }
 }
 
-pub fn load_from_file(
-    filename: &str,
-    pre_operation: PreOperation,
-) -> Result<(Vec<Mesh>, Vec<Mtllib>), obj_loader::Error> {
-    let mut meshes = vec![];
+/// operations applied after loading, combine them with `|`
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct PreOperation(u8);
 
-    let scene = obj_loader::load_from_file(filename)?;
+impl PreOperation {
+    pub const NONE: PreOperation = PreOperation(0x00);
+    /// flat normal for every face
+    pub const RECALC_NORMAL: PreOperation = PreOperation(0x01);
+    /// average normals of faces sharing a position in models with smoothing group(`s`) on, flat normals for others.
+    /// Applied after `RECALC_NORMAL`
+    pub const SMOOTH_NORMAL: PreOperation = PreOperation(0x02);
+    /// tangent and bitangent of every face from texcoords, applied after normals
+    pub const GEN_TANGENTS: PreOperation = PreOperation(0x04);
+
+    /// whether all operations in `other` are set
+    pub fn contains(&self, other: PreOperation) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for PreOperation {
+    type Output = PreOperation;
 
-    for model in scene.models {
+    fn bitor(self, rhs: PreOperation) -> PreOperation {
+        PreOperation(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PreOperation {
+    fn bitor_assign(&mut self, rhs: PreOperation) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// one mesh for each model in scene, n-gon faces are fan triangulated
+fn meshes_from_scene(scene: &obj_loader::SceneData) -> Vec<Mesh> {
+    let mut meshes = vec![];
+    for model in &scene.models {
         let mut mesh = Mesh {
             name: Some(model.name.clone()),
             ..Default::default()
         };
-        for face in model.faces {
-            for vtx in face.vertices {
+        for face in &model.faces {
+            // fan triangulate n-gon into n - 2 triangles (v0, vi, vi+1)
+            let triangles = (1..face.vertices.len().saturating_sub(1))
+                .flat_map(|i| [0, i, i + 1])
+                .map(|i| &face.vertices[i]);
+            for vtx in triangles {
                 let position = scene.vertices[vtx.vertex as usize];
                 let normal = match vtx.normal {
                     None => math::Vec3::zero(),
@@ -53,32 +103,760 @@ pub fn load_from_file(
                     normal,
                     texcoord,
                     color: math::Vec4::new(1.0, 1.0, 1.0, 1.0),
+                    tangent: math::Vec3::zero(),
+                    bitangent: math::Vec3::zero(),
                 });
             }
         }
 
-        mesh.material = model.material;
+        mesh.material = model.material.clone();
         mesh.mtllib = model.mtllib;
         meshes.push(mesh);
     }
+    meshes
+}
 
-    if pre_operation as u8 & PreOperation::RecalcNormal as u8 != 0 {
-        for mesh in &mut meshes {
-            assert_eq!(mesh.vertices.len() % 3, 0);
-            for i in 0..mesh.vertices.len() / 3 {
-                let v1 = &mesh.vertices[i * 3];
-                let v2 = &mesh.vertices[i * 3 + 1];
-                let v3 = &mesh.vertices[i * 3 + 2];
-                let norm = (v3.position - v2.position)
-                    .cross(&(v2.position - v1.position))
-                    .normalize();
-
-                mesh.vertices[i * 3].normal = norm;
-                mesh.vertices[i * 3 + 1].normal = norm;
-                mesh.vertices[i * 3 + 2].normal = norm;
+pub fn load_from_file(
+    filename: &str,
+    pre_operation: PreOperation,
+) -> Result<(Vec<Mesh>, Vec<Mtllib>), obj_loader::Error> {
+    let scene = obj_loader::load_from_file(filename)?;
+    let mut meshes = meshes_from_scene(&scene);
+    apply_pre_operation(&mut meshes, &scene, pre_operation);
+
+    Ok((meshes, scene.materials))
+}
+
+fn apply_pre_operation(
+    meshes: &mut [Mesh],
+    scene: &obj_loader::SceneData,
+    pre_operation: PreOperation,
+) {
+    if pre_operation.contains(PreOperation::RECALC_NORMAL) {
+        for mesh in meshes.iter_mut() {
+            flat_normals(mesh);
+        }
+    }
+
+    if pre_operation.contains(PreOperation::SMOOTH_NORMAL) {
+        for (mesh, model) in meshes.iter_mut().zip(&scene.models) {
+            if model.smooth_shade != 0 {
+                smooth_normals(mesh);
+            } else {
+                flat_normals(mesh);
             }
         }
     }
 
-    Ok((meshes, scene.materials))
+    if pre_operation.contains(PreOperation::GEN_TANGENTS) {
+        for mesh in meshes.iter_mut() {
+            gen_tangents(mesh);
+        }
+    }
+}
+
+/// set tangent(+U) and bitangent(+V) of every face from edges and texcoord deltas,
+/// faces with zero texcoord area get an arbitrary orthonormal frame around face normal
+pub fn gen_tangents(mesh: &mut Mesh) {
+    assert_eq!(mesh.vertices.len() % 3, 0);
+
+    for triangle in mesh.vertices.chunks_exact_mut(3) {
+        let edge1 = triangle[1].position - triangle[0].position;
+        let edge2 = triangle[2].position - triangle[0].position;
+        let duv1 = triangle[1].texcoord - triangle[0].texcoord;
+        let duv2 = triangle[2].texcoord - triangle[0].texcoord;
+        let det = duv1.cross(&duv2);
+
+        let (tangent, bitangent) = if det.abs() > f32::EPSILON {
+            // solve [edge1, edge2] = [T, B] * [duv1, duv2]
+            let r = 1.0 / det;
+            (
+                ((edge1 * duv2.y - edge2 * duv1.y) * r).normalize_or_zero(),
+                ((edge2 * duv1.x - edge1 * duv2.x) * r).normalize_or_zero(),
+            )
+        } else {
+            math::basis_from_normal(&face_normal(triangle).normalize_or(*math::Vec3::z_axis()))
+        };
+        for v in triangle {
+            v.tangent = tangent;
+            v.bitangent = bitangent;
+        }
+    }
+}
+
+/// merge vertices with identical attributes into an index buffer for `RendererInterface::draw_indexed`,
+/// first appearance order is kept
+pub fn deduplicate(mesh: &Mesh) -> (Vec<Vertex>, Vec<u32>) {
+    let key = |v: &Vertex| {
+        [
+            v.position.x,
+            v.position.y,
+            v.position.z,
+            v.normal.x,
+            v.normal.y,
+            v.normal.z,
+            v.texcoord.x,
+            v.texcoord.y,
+            v.color.x,
+            v.color.y,
+            v.color.z,
+            v.color.w,
+            v.tangent.x,
+            v.tangent.y,
+            v.tangent.z,
+            v.bitangent.x,
+            v.bitangent.y,
+            v.bitangent.z,
+        ]
+        .map(f32::to_bits)
+    };
+
+    let mut unique: HashMap<[u32; 18], u32> = HashMap::new();
+    let mut vertices = vec![];
+    let indices = mesh
+        .vertices
+        .iter()
+        .map(|v| {
+            *unique.entry(key(v)).or_insert_with(|| {
+                vertices.push(*v);
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect();
+    (vertices, indices)
+}
+
+/// counter-clockwise normal of triangle, length is twice of the area
+fn face_normal(triangle: &[Vertex]) -> math::Vec3 {
+    (triangle[1].position - triangle[0].position)
+        .cross(&(triangle[2].position - triangle[0].position))
+}
+
+/// set every vertex normal to counter-clockwise normal of its face
+pub fn flat_normals(mesh: &mut Mesh) {
+    assert_eq!(mesh.vertices.len() % 3, 0);
+
+    for triangle in mesh.vertices.chunks_exact_mut(3) {
+        let normal = face_normal(triangle).normalize_or_zero();
+        for v in triangle {
+            v.normal = normal;
+        }
+    }
+}
+
+/// set every vertex normal to area weighted average of normals of faces sharing its position
+pub fn smooth_normals(mesh: &mut Mesh) {
+    assert_eq!(mesh.vertices.len() % 3, 0);
+
+    // adding 0 turns -0 into 0, so they share the same key
+    let key = |v: &Vertex| [v.position.x, v.position.y, v.position.z].map(|x| (x + 0.0).to_bits());
+    let mut accumulated: HashMap<[u32; 3], math::Vec3> = HashMap::new();
+    for triangle in mesh.vertices.chunks_exact(3) {
+        let normal = face_normal(triangle);
+        for v in triangle {
+            *accumulated.entry(key(v)).or_insert_with(math::Vec3::zero) += normal;
+        }
+    }
+    for v in &mut mesh.vertices {
+        v.normal = accumulated[&key(v)].normalize_or_zero();
+    }
+}
+
+/// guess winding of outward faces by voting every triangle: counter-clockwise normal pointing away from bounding box center votes `CCW`.
+/// Works for closed, roughly convex meshes, ties and empty meshes give `CCW`
+pub fn suggest_front_face(mesh: &Mesh) -> FrontFace {
+    let Some(aabb) = mesh.aabb() else {
+        return FrontFace::CCW;
+    };
+    let center = (aabb.min + aabb.max) * 0.5;
+
+    let mut votes = 0.0;
+    for triangle in mesh.vertices.chunks_exact(3) {
+        let [p1, p2, p3] = [0, 1, 2].map(|i| triangle[i].position);
+        let ccw_normal = (p2 - p1).cross(&(p3 - p1));
+        let outward = (p1 + p2 + p3) * (1.0 / 3.0) - center;
+        // weighted by area, so tiny inner triangles do not outvote the hull
+        votes += ccw_normal.dot(&outward).signum() * ccw_normal.length();
+    }
+
+    if votes >= 0.0 {
+        FrontFace::CCW
+    } else {
+        FrontFace::CW
+    }
+}
+
+/// weight of planes which keep mesh boundary in place while simplifying
+const BOUNDARY_WEIGHT: f64 = 100.0;
+
+/// symmetric 4x4 matrix of [quadric error metrics](https://www.cs.cmu.edu/~garland/Papers/quadrics.pdf), stored as upper triangle
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// quadric of plane `normal.dot(pt) + d = 0`, `normal` must be normalized
+    fn from_plane(normal: &math::Vec3, d: f32, weight: f64) -> Self {
+        let (a, b, c, d) = (normal.x as f64, normal.y as f64, normal.z as f64, d as f64);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+        )
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut result = *self;
+        for (r, o) in result.0.iter_mut().zip(other.0.iter()) {
+            *r += o;
+        }
+        result
+    }
+
+    /// sum of weighted squared distance from `pt` to planes
+    fn error(&self, pt: &math::Vec3) -> f64 {
+        let (x, y, z) = (pt.x as f64, pt.y as f64, pt.z as f64);
+        let q = &self.0;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// candidate of collapsing vertex `from` into vertex `to`
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    // versions of vertices when this candidate is created, outdated candidates are skipped
+    stamps: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, so `BinaryHeap` pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn triangle_normal(positions: &[math::Vec3], triangle: &[usize; 3]) -> math::Vec3 {
+    (positions[triangle[1]] - positions[triangle[0]])
+        .cross(&(positions[triangle[2]] - positions[triangle[0]]))
+}
+
+/// sorted vertices sharing a face with `v`
+fn vertex_neighbors(v: usize, vertex_faces: &[Vec<usize>], triangles: &[[usize; 3]]) -> Vec<usize> {
+    let mut result: Vec<usize> = vertex_faces[v]
+        .iter()
+        .flat_map(|f| triangles[*f])
+        .filter(|n| *n != v)
+        .collect();
+    result.sort_unstable();
+    result.dedup();
+    result
+}
+
+/// reduce triangles of mesh to about `target_ratio`(in `[0, 1]`) of origin by edge collapse with quadric error metrics.
+/// Vertices are welded by position, every collapse moves a vertex onto its neighbor so result stays inside origin bounds.
+/// Attributes of collapsed vertex are replaced by the kept one, so texture seams are not preserved
+pub fn simplify(mesh: &Mesh, target_ratio: f32) -> Mesh {
+    assert_eq!(mesh.vertices.len() % 3, 0);
+
+    // weld vertices by position
+    let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut triangles: Vec<[usize; 3]> = vec![];
+    for face in mesh.vertices.chunks(3) {
+        let triangle = [0, 1, 2].map(|i| {
+            let p = face[i].position;
+            *welded
+                .entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()])
+                .or_insert_with(|| {
+                    vertices.push(face[i]);
+                    vertices.len() - 1
+                })
+        });
+        if triangle[0] != triangle[1] && triangle[1] != triangle[2] && triangle[0] != triangle[2] {
+            triangles.push(triangle);
+        }
+    }
+
+    let positions: Vec<math::Vec3> = vertices.iter().map(|v| v.position).collect();
+    let target = (triangles.len() as f32 * target_ratio.clamp(0.0, 1.0)).ceil() as usize;
+
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    let mut vertex_faces: Vec<Vec<usize>> = vec![vec![]; vertices.len()];
+    let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (i, triangle) in triangles.iter().enumerate() {
+        let normal = triangle_normal(&positions, triangle).normalize_or_zero();
+        let q = Quadric::from_plane(&normal, -normal.dot(&positions[triangle[0]]), 1.0);
+        for j in 0..3 {
+            quadrics[triangle[j]] = quadrics[triangle[j]].add(&q);
+            vertex_faces[triangle[j]].push(i);
+            let (a, b) = (triangle[j], triangle[(j + 1) % 3]);
+            edge_faces.entry((a.min(b), a.max(b))).or_default().push(i);
+        }
+    }
+
+    // planes perpendicular to boundary edges keep the outline of mesh
+    for ((a, b), faces) in &edge_faces {
+        if faces.len() != 1 {
+            continue;
+        }
+        let face_normal = triangle_normal(&positions, &triangles[faces[0]]);
+        let normal = (positions[*b] - positions[*a])
+            .cross(&face_normal)
+            .normalize_or_zero();
+        let q = Quadric::from_plane(&normal, -normal.dot(&positions[*a]), BOUNDARY_WEIGHT);
+        quadrics[*a] = quadrics[*a].add(&q);
+        quadrics[*b] = quadrics[*b].add(&q);
+    }
+
+    let mut stamps = vec![0u32; vertices.len()];
+    let mut removed = vec![false; vertices.len()];
+    let mut alive = vec![true; triangles.len()];
+    let mut alive_count = triangles.len();
+
+    let collapse_of = |a: usize, b: usize, quadrics: &[Quadric], stamps: &[u32]| {
+        let q = quadrics[a].add(&quadrics[b]);
+        let (cost_a, cost_b) = (q.error(&positions[a]), q.error(&positions[b]));
+        let (from, to, cost) = if cost_a < cost_b {
+            (b, a, cost_a)
+        } else {
+            (a, b, cost_b)
+        };
+        Collapse {
+            cost,
+            from,
+            to,
+            stamps: (stamps[from], stamps[to]),
+        }
+    };
+
+    let mut heap: BinaryHeap<Collapse> = edge_faces
+        .keys()
+        .map(|(a, b)| collapse_of(*a, *b, &quadrics, &stamps))
+        .collect();
+
+    while alive_count > target {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        let (from, to) = (collapse.from, collapse.to);
+        if removed[from] || removed[to] || collapse.stamps != (stamps[from], stamps[to]) {
+            continue;
+        }
+
+        vertex_faces[from].retain(|f| alive[*f]);
+        vertex_faces[to].retain(|f| alive[*f]);
+
+        // link condition: vertices adjacent to both must be exactly the opposite corners of shared faces
+        let to_neighbors = vertex_neighbors(to, &vertex_faces, &triangles);
+        let common = vertex_neighbors(from, &vertex_faces, &triangles)
+            .iter()
+            .filter(|n| to_neighbors.binary_search(n).is_ok())
+            .count();
+        let shared = vertex_faces[from]
+            .iter()
+            .filter(|f| triangles[**f].contains(&to))
+            .count();
+        if shared == 0 || common != shared {
+            continue;
+        }
+
+        // reject collapse which flips or degenerates faces around `from`
+        let flipped = vertex_faces[from].iter().any(|f| {
+            let triangle = triangles[*f];
+            if triangle.contains(&to) {
+                return false;
+            }
+            let moved = triangle.map(|v| if v == from { to } else { v });
+            let old_normal = triangle_normal(&positions, &triangle);
+            let new_normal = triangle_normal(&positions, &moved);
+            new_normal.length_square() <= f32::EPSILON * old_normal.length_square()
+                || old_normal.dot(&new_normal) <= 0.0
+        });
+        if flipped {
+            continue;
+        }
+
+        for f in std::mem::take(&mut vertex_faces[from]) {
+            if triangles[f].contains(&to) {
+                alive[f] = false;
+                alive_count -= 1;
+            } else {
+                for v in triangles[f].iter_mut() {
+                    if *v == from {
+                        *v = to;
+                    }
+                }
+                vertex_faces[to].push(f);
+            }
+        }
+        vertex_faces[to].retain(|f| alive[*f]);
+        removed[from] = true;
+        quadrics[to] = quadrics[to].add(&quadrics[from]);
+        stamps[to] += 1;
+
+        for n in vertex_neighbors(to, &vertex_faces, &triangles) {
+            heap.push(collapse_of(to, n, &quadrics, &stamps));
+        }
+    }
+
+    Mesh {
+        vertices: triangles
+            .iter()
+            .zip(alive.iter())
+            .filter(|(_, alive)| **alive)
+            .flat_map(|(triangle, _)| triangle.map(|v| vertices[v]))
+            .collect(),
+        name: mesh.name.clone(),
+        mtllib: mesh.mtllib,
+        material: mesh.material.clone(),
+    }
+}
+
+/// grid of lines on XZ plane centered at origin, spans `[-size/2, size/2]` with `divisions` cells along each axis.
+/// Every two vertices are the endpoints of a line, draw them by `draw_line_3d`.
+/// Center lines(exist when `divisions` is even) are red along X axis and blue along Z axis, others are gray
+pub fn make_grid(size: f32, divisions: u32) -> Mesh {
+    assert!(divisions > 0);
+
+    const GRAY: math::Vec4 = math::Vec4::new(0.5, 0.5, 0.5, 1.0);
+    let half = size * 0.5;
+    let vertex = |position, color| Vertex {
+        position,
+        normal: math::Vec3::new(0.0, 1.0, 0.0),
+        texcoord: math::Vec2::zero(),
+        color,
+        tangent: math::Vec3::zero(),
+        bitangent: math::Vec3::zero(),
+    };
+
+    let mut vertices = Vec::with_capacity((divisions as usize + 1) * 4);
+    for i in 0..=divisions {
+        let offset = -half + size * i as f32 / divisions as f32;
+        let is_center = i * 2 == divisions;
+
+        // line along X axis
+        let color = if is_center {
+            math::Vec4::new(1.0, 0.0, 0.0, 1.0)
+        } else {
+            GRAY
+        };
+        vertices.push(vertex(math::Vec3::new(-half, 0.0, offset), color));
+        vertices.push(vertex(math::Vec3::new(half, 0.0, offset), color));
+
+        // line along Z axis
+        let color = if is_center {
+            math::Vec4::new(0.0, 0.0, 1.0, 1.0)
+        } else {
+            GRAY
+        };
+        vertices.push(vertex(math::Vec3::new(offset, 0.0, -half), color));
+        vertices.push(vertex(math::Vec3::new(offset, 0.0, half), color));
+    }
+
+    Mesh {
+        vertices,
+        name: Some("grid".to_string()),
+        mtllib: None,
+        material: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simplify_subdivided_plane() {
+        const N: usize = 16;
+        let vertex = |x: usize, z: usize| Vertex {
+            position: math::Vec3::new(
+                x as f32 / N as f32 * 2.0 - 1.0,
+                0.0,
+                z as f32 / N as f32 * 2.0 - 1.0,
+            ),
+            normal: math::Vec3::new(0.0, 1.0, 0.0),
+            texcoord: math::Vec2::new(x as f32 / N as f32, z as f32 / N as f32),
+            color: math::Vec4::new(1.0, 1.0, 1.0, 1.0),
+            tangent: math::Vec3::zero(),
+            bitangent: math::Vec3::zero(),
+        };
+        let mut plane = Mesh::default();
+        for x in 0..N {
+            for z in 0..N {
+                plane.vertices.extend([
+                    vertex(x, z),
+                    vertex(x, z + 1),
+                    vertex(x + 1, z + 1),
+                    vertex(x, z),
+                    vertex(x + 1, z + 1),
+                    vertex(x + 1, z),
+                ]);
+            }
+        }
+
+        let origin_count = plane.vertices.len() / 3;
+        let simplified = simplify(&plane, 0.25);
+        let count = simplified.vertices.len() / 3;
+        let target = origin_count / 4;
+        assert!(count <= target && count + 2 >= target);
+
+        let origin_aabb = plane.aabb().unwrap();
+        let aabb = simplified.aabb().unwrap();
+        assert!((aabb.min - origin_aabb.min).length() < 1e-5);
+        assert!((aabb.max - origin_aabb.max).length() < 1e-5);
+
+        // triangles keep facing up and cover the whole plane
+        let mut area = 0.0;
+        for face in simplified.vertices.chunks(3) {
+            let normal =
+                (face[1].position - face[0].position).cross(&(face[2].position - face[0].position));
+            assert!(normal.y > 0.0);
+            area += normal.length() * 0.5;
+        }
+        assert!((area - 4.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn grid_span_and_count() {
+        let grid = make_grid(10.0, 4);
+        assert_eq!(grid.vertices.len(), (4 + 1) * 4);
+
+        let aabb = grid.aabb().unwrap();
+        assert_eq!(aabb.min, math::Vec3::new(-5.0, 0.0, -5.0));
+        assert_eq!(aabb.max, math::Vec3::new(5.0, 0.0, 5.0));
+
+        // center lines have distinct colors
+        let center: Vec<&Vertex> = grid
+            .vertices
+            .iter()
+            .filter(|v| v.color != grid.vertices[0].color)
+            .collect();
+        assert_eq!(center.len(), 4);
+        assert!(center
+            .iter()
+            .all(|v| v.position.x == 0.0 || v.position.z == 0.0));
+    }
+
+    #[test]
+    fn triangulate_quad_faces() {
+        let obj = "\
+g cube
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+vn 0 0 1
+f 5//1 6//1 7//1 8//1
+f 2//1 1//1 4//1 3//1
+f 1//1 5//1 8//1 4//1
+f 6//1 2//1 3//1 7//1
+f 8//1 7//1 3//1 4//1
+f 1//1 2//1 6//1 5//1
+f 1//1 2//1 3//1 4//1 5//1
+";
+        let meshes = meshes_from_scene(&obj_loader::load_from_str(obj, None).unwrap());
+
+        let vertices = &meshes[0].vertices;
+        // 6 quads and a pentagon
+        assert_eq!(vertices.len(), (6 * 2 + 3) * 3);
+        // first quad is (v5, v6, v7), (v5, v7, v8) and keeps its normals
+        let positions: Vec<math::Vec3> = vertices[..6].iter().map(|v| v.position).collect();
+        assert_eq!(
+            positions,
+            [
+                (-1.0, -1.0),
+                (1.0, -1.0),
+                (1.0, 1.0),
+                (-1.0, -1.0),
+                (1.0, 1.0),
+                (-1.0, 1.0)
+            ]
+            .map(|(x, y)| math::Vec3::new(x, y, 1.0))
+        );
+        assert!(vertices[..6]
+            .iter()
+            .all(|v| v.normal == math::Vec3::new(0.0, 0.0, 1.0)));
+        // v//n form leaves texcoord default
+        assert!(vertices.iter().all(|v| v.texcoord == math::Vec2::zero()));
+    }
+
+    #[test]
+    fn suggest_cube_front_face() {
+        let mut cube = primitives::cube(2.0);
+        assert!(matches!(suggest_front_face(&cube), FrontFace::CCW));
+
+        for triangle in cube.vertices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+        assert!(matches!(suggest_front_face(&cube), FrontFace::CW));
+    }
+
+    #[test]
+    fn smooth_sphere_normals() {
+        let mut sphere = primitives::uv_sphere(1.0, 12, 8);
+        for v in &mut sphere.vertices {
+            v.normal = math::Vec3::zero();
+        }
+        smooth_normals(&mut sphere);
+
+        // shared by adjacent triangles, close to the analytic normal instead of faceted
+        let mut shared: HashMap<[u32; 3], math::Vec3> = HashMap::new();
+        for v in &sphere.vertices {
+            let key = [v.position.x, v.position.y, v.position.z].map(|x| (x + 0.0).to_bits());
+            let normal = *shared.entry(key).or_insert(v.normal);
+            assert_eq!(normal, v.normal);
+            assert!((v.normal.length() - 1.0).abs() < 1e-5);
+            assert!(v.normal.dot(&v.position.normalize()) > 0.99);
+        }
+        let first = &sphere.vertices[12 * 3..12 * 3 + 3];
+        assert!(first[0].normal != first[1].normal);
+    }
+
+    #[test]
+    fn combine_pre_operations() {
+        let mut operation = PreOperation::NONE;
+        assert!(!operation.contains(PreOperation::RECALC_NORMAL));
+        operation |= PreOperation::RECALC_NORMAL;
+        let operation = operation | PreOperation::SMOOTH_NORMAL;
+        assert!(operation.contains(PreOperation::RECALC_NORMAL));
+        assert!(operation.contains(PreOperation::SMOOTH_NORMAL));
+        assert!(operation.contains(PreOperation::NONE));
+
+        // smooth normal runs last, so shared vertex of two faces gets the averaged normal
+        let scene = obj_loader::load_from_str(
+            "\
+g fold
+s 1
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 2 3
+f 1 4 2
+",
+            None,
+        )
+        .unwrap();
+        let mut meshes = meshes_from_scene(&scene);
+        apply_pre_operation(&mut meshes, &scene, operation);
+        let shared = meshes[0].vertices[0].normal;
+        let expect = math::Vec3::new(0.0, 1.0, 1.0).normalize();
+        assert!((shared - expect).length() < 1e-5);
+
+        // `s off` loads and keeps flat normals
+        let scene = obj_loader::load_from_str(
+            "\
+g fold
+s off
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 2 3
+f 1 4 2
+",
+            None,
+        )
+        .unwrap();
+        let mut meshes = meshes_from_scene(&scene);
+        apply_pre_operation(&mut meshes, &scene, operation);
+        assert_eq!(meshes[0].vertices[0].normal, math::Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(meshes[0].vertices[3].normal, math::Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn quad_tangent_along_u() {
+        // quad on XY plane, U goes along +X, V along +Y
+        let mut quad = primitives::plane(2.0, 2.0, 1);
+        for v in &mut quad.vertices {
+            v.position = math::Vec3::new(v.position.x, -v.position.z, 0.0);
+            v.normal = math::Vec3::new(0.0, 0.0, 1.0);
+        }
+        gen_tangents(&mut quad);
+        for v in &quad.vertices {
+            assert!((v.tangent - math::Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+            assert!((v.bitangent - math::Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+        }
+
+        // zero texcoord area falls back to a frame orthogonal to the face
+        for v in &mut quad.vertices {
+            v.texcoord = math::Vec2::zero();
+        }
+        gen_tangents(&mut quad);
+        for v in &quad.vertices {
+            assert!((v.tangent.length() - 1.0).abs() < 1e-5);
+            assert!(v.tangent.dot(&v.normal).abs() < 1e-5);
+            assert!(v.bitangent.dot(&v.normal).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn deduplicate_quad() {
+        let quad = primitives::plane(1.0, 1.0, 1);
+        let (vertices, indices) = deduplicate(&quad);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        for (index, v) in indices.iter().zip(&quad.vertices) {
+            assert_eq!(vertices[*index as usize].position, v.position);
+        }
+    }
+
+    #[test]
+    fn recalc_normal_ccw_triangle() {
+        let scene = obj_loader::load_from_str(
+            "\
+g triangle
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vn 0 0 -1
+f 1//1 2//1 3//1
+",
+            None,
+        )
+        .unwrap();
+        let mut meshes = meshes_from_scene(&scene);
+        apply_pre_operation(&mut meshes, &scene, PreOperation::RECALC_NORMAL);
+        for v in &meshes[0].vertices {
+            assert_eq!(v.normal, math::Vec3::new(0.0, 0.0, 1.0));
+        }
+    }
 }
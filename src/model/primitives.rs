@@ -0,0 +1,262 @@
+//! generate simple meshes without loading files, all faces are counter-clockwise when viewed from outside
+
+use super::{Mesh, Vertex};
+use crate::math;
+
+const WHITE: math::Vec4 = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+fn vertex(position: math::Vec3, normal: math::Vec3, texcoord: math::Vec2) -> Vertex {
+    Vertex {
+        position,
+        normal,
+        texcoord,
+        color: WHITE,
+        tangent: math::Vec3::zero(),
+        bitangent: math::Vec3::zero(),
+    }
+}
+
+fn mesh(name: &str, vertices: Vec<Vertex>) -> Mesh {
+    Mesh {
+        vertices,
+        name: Some(name.to_string()),
+        mtllib: None,
+        material: None,
+    }
+}
+
+/// push quad `a, b, c, d`(clockwise from top left when viewed from outside) as two triangles
+fn push_quad(vertices: &mut Vec<Vertex>, a: Vertex, b: Vertex, c: Vertex, d: Vertex) {
+    vertices.extend_from_slice(&[a, d, c, a, c, b]);
+}
+
+/// axis aligned cube centered at origin, 12 triangles
+pub fn cube(size: f32) -> Mesh {
+    let half = size * 0.5;
+    // (normal, right, up) of each face when viewed from outside
+    let faces = [
+        (
+            *math::Vec3::x_axis(),
+            -*math::Vec3::z_axis(),
+            *math::Vec3::y_axis(),
+        ),
+        (
+            -*math::Vec3::x_axis(),
+            *math::Vec3::z_axis(),
+            *math::Vec3::y_axis(),
+        ),
+        (
+            *math::Vec3::y_axis(),
+            *math::Vec3::x_axis(),
+            -*math::Vec3::z_axis(),
+        ),
+        (
+            -*math::Vec3::y_axis(),
+            *math::Vec3::x_axis(),
+            *math::Vec3::z_axis(),
+        ),
+        (
+            *math::Vec3::z_axis(),
+            *math::Vec3::x_axis(),
+            *math::Vec3::y_axis(),
+        ),
+        (
+            -*math::Vec3::z_axis(),
+            -*math::Vec3::x_axis(),
+            *math::Vec3::y_axis(),
+        ),
+    ];
+
+    let mut vertices = Vec::with_capacity(36);
+    for (normal, right, up) in faces {
+        let corner = |x: f32, y: f32| {
+            vertex(
+                (normal + right * x + up * y) * half,
+                normal,
+                math::Vec2::new(x * 0.5 + 0.5, y * 0.5 + 0.5),
+            )
+        };
+        push_quad(
+            &mut vertices,
+            corner(-1.0, 1.0),
+            corner(1.0, 1.0),
+            corner(1.0, -1.0),
+            corner(-1.0, -1.0),
+        );
+    }
+    mesh("cube", vertices)
+}
+
+/// sphere centered at origin with poles on Y axis, `segments` around Y axis and `rings` from top to bottom.
+/// Rings touching poles are single triangles, so there are `segments * (2 * rings - 2)` triangles
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    assert!(segments >= 3 && rings >= 2);
+
+    let point = |segment: u32, ring: u32| {
+        // wrap so seam vertices have exactly the same position
+        let phi = (segment % segments) as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+        let theta = ring as f32 / rings as f32 * std::f32::consts::PI;
+        // sin(PI) isn't exactly 0 in f32, keep bottom pole on Y axis
+        let (sin_theta, cos_theta) = if ring == rings {
+            (0.0, -1.0)
+        } else {
+            theta.sin_cos()
+        };
+        let normal = math::Vec3::new(sin_theta * phi.sin(), cos_theta, sin_theta * phi.cos());
+        vertex(
+            normal * radius,
+            normal,
+            math::Vec2::new(
+                segment as f32 / segments as f32,
+                1.0 - ring as f32 / rings as f32,
+            ),
+        )
+    };
+
+    let mut vertices = Vec::with_capacity((segments * (2 * rings - 2) * 3) as usize);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = point(segment, ring);
+            let b = point(segment + 1, ring);
+            let c = point(segment + 1, ring + 1);
+            let d = point(segment, ring + 1);
+            if ring == 0 {
+                vertices.extend_from_slice(&[a, d, c]);
+            } else if ring == rings - 1 {
+                vertices.extend_from_slice(&[a, c, b]);
+            } else {
+                push_quad(&mut vertices, a, b, c, d);
+            }
+        }
+    }
+    mesh("uv_sphere", vertices)
+}
+
+/// plane on XZ plane centered at origin facing +Y, `w` along X and `h` along Z, split into `subdiv * subdiv` quads
+pub fn plane(w: f32, h: f32, subdiv: u32) -> Mesh {
+    assert!(subdiv > 0);
+
+    let normal = *math::Vec3::y_axis();
+    let point = |i: u32, j: u32| {
+        let u = i as f32 / subdiv as f32;
+        let v = j as f32 / subdiv as f32;
+        vertex(
+            math::Vec3::new((u - 0.5) * w, 0.0, (v - 0.5) * h),
+            normal,
+            math::Vec2::new(u, 1.0 - v),
+        )
+    };
+
+    let mut vertices = Vec::with_capacity((subdiv * subdiv * 6) as usize);
+    for j in 0..subdiv {
+        for i in 0..subdiv {
+            push_quad(
+                &mut vertices,
+                point(i, j),
+                point(i + 1, j),
+                point(i + 1, j + 1),
+                point(i, j + 1),
+            );
+        }
+    }
+    mesh("plane", vertices)
+}
+
+/// capped cylinder centered at origin along Y axis, `segments * 4` triangles
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> Mesh {
+    assert!(segments >= 3);
+
+    let half = height * 0.5;
+    let direction = |segment: u32| {
+        // wrap so seam vertices have exactly the same position
+        let phi = (segment % segments) as f32 / segments as f32 * 2.0 * std::f32::consts::PI;
+        math::Vec3::new(phi.sin(), 0.0, phi.cos())
+    };
+    let side = |segment: u32, y: f32| {
+        let normal = direction(segment);
+        vertex(
+            normal * radius + math::Vec3::new(0.0, y, 0.0),
+            normal,
+            math::Vec2::new(segment as f32 / segments as f32, y / height + 0.5),
+        )
+    };
+    let cap = |segment: u32, normal: math::Vec3| {
+        let dir = direction(segment);
+        vertex(
+            dir * radius + normal * half,
+            normal,
+            math::Vec2::new(dir.x * 0.5 + 0.5, dir.z * 0.5 + 0.5),
+        )
+    };
+
+    let top = *math::Vec3::y_axis();
+    let bottom = -*math::Vec3::y_axis();
+    let top_center = vertex(top * half, top, math::Vec2::new(0.5, 0.5));
+    let bottom_center = vertex(bottom * half, bottom, math::Vec2::new(0.5, 0.5));
+
+    let mut vertices = Vec::with_capacity((segments * 12) as usize);
+    for segment in 0..segments {
+        push_quad(
+            &mut vertices,
+            side(segment, half),
+            side(segment + 1, half),
+            side(segment + 1, -half),
+            side(segment, -half),
+        );
+        vertices.extend_from_slice(&[top_center, cap(segment, top), cap(segment + 1, top)]);
+        vertices.extend_from_slice(&[
+            bottom_center,
+            cap(segment + 1, bottom),
+            cap(segment, bottom),
+        ]);
+    }
+    mesh("cylinder", vertices)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// unit normals, and every triangle is counter-clockwise viewed from the side its normals point to
+    fn check_normals(mesh: &Mesh) {
+        for triangle in mesh.vertices.chunks(3) {
+            let face_normal = (triangle[1].position - triangle[0].position)
+                .cross(&(triangle[2].position - triangle[0].position));
+            for v in triangle {
+                assert!((v.normal.length() - 1.0).abs() < 1e-5);
+                assert!(face_normal.dot(&v.normal) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn primitive_counts_and_normals() {
+        let cube = cube(2.0);
+        assert_eq!(cube.vertices.len(), 12 * 3);
+        let aabb = cube.aabb().unwrap();
+        assert_eq!(aabb.min, math::Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(aabb.max, math::Vec3::new(1.0, 1.0, 1.0));
+        check_normals(&cube);
+
+        let sphere = uv_sphere(2.0, 8, 6);
+        assert_eq!(sphere.vertices.len(), 8 * (2 * 6 - 2) * 3);
+        assert!(sphere
+            .vertices
+            .iter()
+            .all(|v| (v.position.length() - 2.0).abs() < 1e-5));
+        check_normals(&sphere);
+
+        let plane = plane(4.0, 2.0, 3);
+        assert_eq!(plane.vertices.len(), 3 * 3 * 2 * 3);
+        let aabb = plane.aabb().unwrap();
+        assert_eq!(aabb.min, math::Vec3::new(-2.0, 0.0, -1.0));
+        assert_eq!(aabb.max, math::Vec3::new(2.0, 0.0, 1.0));
+        check_normals(&plane);
+
+        let cylinder = cylinder(1.0, 3.0, 10);
+        assert_eq!(cylinder.vertices.len(), 10 * 4 * 3);
+        let aabb = cylinder.aabb().unwrap();
+        assert!((aabb.min.y + 1.5).abs() < 1e-6 && (aabb.max.y - 1.5).abs() < 1e-6);
+        check_normals(&cylinder);
+    }
+}
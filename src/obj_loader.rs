@@ -1,7 +1,6 @@
 use crate::math;
 use std::collections::HashMap;
 use std::io::{prelude::*, BufReader};
-use std::ops::Not;
 use std::str::{self, SplitWhitespace};
 
 /// a help struct to read whole file in lines
@@ -12,7 +11,10 @@ struct FileContent {
 impl FileContent {
     fn from_file(filename: &std::path::Path) -> Result<FileContent, std::io::Error> {
         let file = std::fs::File::open(filename)?;
-        let mut reader = BufReader::new(file);
+        Self::from_reader(BufReader::new(file))
+    }
+
+    fn from_reader<R: BufRead>(mut reader: R) -> Result<FileContent, std::io::Error> {
         let mut line = String::new();
         let mut lines: Vec<String> = vec![];
         let mut read_finish = false;
@@ -61,7 +63,11 @@ pub struct MtlTextureMaps {
     pub specular_highlight: Option<String>, // map_Ns
     pub alpha: Option<String>,              // map_d
     pub refl: Option<String>,               // map_refl
-    pub bump: Option<String>,               // map_Bump
+    pub bump: Option<String>,               // map_Bump, bump
+    pub emissive: Option<String>,           // map_Ke
+    pub roughness: Option<String>,          // map_Pr
+    pub metallic: Option<String>,           // map_Pm
+    pub normal: Option<String>,             // norm
 }
 
 pub struct Material {
@@ -76,6 +82,13 @@ pub struct Material {
     pub optical_density: Option<f32>,            // Ni
     pub illum: Option<u8>,                       // illum
 
+    // PBR extension
+    pub roughness: Option<f32>,           // Pr
+    pub metallic: Option<f32>,            // Pm
+    pub sheen: Option<f32>,               // Ps
+    pub clearcoat_thickness: Option<f32>, // Pc
+    pub clearcoat_roughness: Option<f32>, // Pcr
+
     pub texture_maps: MtlTextureMaps,
 }
 
@@ -92,6 +105,11 @@ impl Material {
             transmission_filter: None,
             optical_density: None,
             illum: None,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            clearcoat_thickness: None,
+            clearcoat_roughness: None,
             texture_maps: MtlTextureMaps {
                 ambient: None,
                 diffuse: None,
@@ -100,6 +118,10 @@ impl Material {
                 alpha: None,
                 refl: None,
                 bump: None,
+                emissive: None,
+                roughness: None,
+                metallic: None,
+                normal: None,
             },
         }
     }
@@ -107,6 +129,8 @@ impl Material {
 
 pub struct Mtllib {
     pub materials: HashMap<String, Material>,
+    /// lines skipped because of unknown statements
+    pub warnings: Vec<String>,
 }
 
 pub struct SceneData {
@@ -115,6 +139,8 @@ pub struct SceneData {
     pub texcoords: Vec<math::Vec2>,
     pub materials: Vec<Mtllib>,
     pub models: Vec<Model>,
+    /// lines skipped because of unknown statements
+    pub warnings: Vec<String>,
 }
 
 impl SceneData {
@@ -125,7 +151,31 @@ impl SceneData {
             texcoords: vec![],
             materials: vec![],
             models: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn new_model(&self, name: String) -> Model {
+        Model {
+            faces: vec![],
+            name,
+            mtllib: self
+                .materials
+                .len()
+                .checked_sub(1)
+                .map(|index| index as u32),
+            material: None,
+            smooth_shade: 0,
+        }
+    }
+
+    // faces before any `g`/`o` go to an unnamed model
+    fn current_model(&mut self) -> &mut Model {
+        if self.models.is_empty() {
+            let model = self.new_model(String::new());
+            self.models.push(model);
         }
+        self.models.last_mut().unwrap()
     }
 }
 
@@ -175,7 +225,11 @@ impl<'a> TokenRequester<'a> {
         }
     }
 
-    fn request(&mut self) -> TokenType {
+    fn unknown_token_warning(&self, token: &str) -> String {
+        format!("line {}: skip unknown token `{}`", self.line + 1, token)
+    }
+
+    fn request(&mut self) -> TokenType<'a> {
         match self.tokens.next() {
             Some(token) => TokenType::Token(token),
             None => {
@@ -195,7 +249,8 @@ pub type ParseResult = Result<(), Error>;
 
 struct ObjParser<'a, 'b> {
     scene: SceneData,
-    dirpath: &'a std::path::Path,
+    // directory to find mtllib, `None` means relative to the working directory
+    dirpath: Option<&'a std::path::Path>,
     token_requester: &'b mut TokenRequester<'b>,
 }
 
@@ -252,11 +307,28 @@ macro_rules! parse_as {
     };
 }
 
+/// convert 1-based index into 0-based, negative index is relative to `count` elements defined so far(-1 is the last one)
+fn resolve_index(index: &str, count: usize) -> Result<u32, Error> {
+    let index = index.parse::<i64>().map_err(|_| Error::CantCvt2Num)?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || resolved < 0 {
+        return Err(Error::InvalidSyntax);
+    }
+    Ok(resolved as u32)
+}
+
 impl<'a, 'b> ObjParser<'a, 'b> {
-    fn new(path: &'a std::path::Path, token_requester: &'b mut TokenRequester<'b>) -> Self {
+    fn new(
+        dirpath: Option<&'a std::path::Path>,
+        token_requester: &'b mut TokenRequester<'b>,
+    ) -> Self {
         Self {
             scene: SceneData::new(),
-            dirpath: path,
+            dirpath,
             token_requester,
         }
     }
@@ -270,18 +342,11 @@ impl<'a, 'b> ObjParser<'a, 'b> {
                 TokenType::Token(token_str) => match token_str {
                     "#" => ignore_until![token = self.token_requester.request();
                                               TokenType::Nextline, TokenType::Eof],
-                    "g" | "o" => self.scene.models.push(Model {
-                        faces: vec![],
-                        name: parse_as![token = self.token_requester.request(); String]?,
-                        mtllib: self
-                            .scene
-                            .materials
-                            .is_empty()
-                            .not()
-                            .then_some((self.scene.materials.len() - 1) as u32),
-                        material: None,
-                        smooth_shade: 0,
-                    }),
+                    "g" | "o" => {
+                        let name = parse_as![token = self.token_requester.request(); String]?;
+                        let model = self.scene.new_model(name);
+                        self.scene.models.push(model);
+                    }
                     "v" => {
                         self.scene
                             .vertices
@@ -306,33 +371,23 @@ impl<'a, 'b> ObjParser<'a, 'b> {
                         let mut finish = false;
                         while !finish {
                             if let TokenType::Token(token_str) = token {
+                                // v, v/t, v/t/n or v//n
                                 let indices: Vec<&str> = token_str.split('/').collect();
-                                if indices.len() != 3 {
+                                if indices.len() > 3 {
                                     return Err(Error::InvalidSyntax);
                                 }
-                                let vertex =
-                                    indices[0].parse::<u32>().map_err(|_| Error::CantCvt2Num)? - 1;
-
-                                let texcoord = if indices[1].is_empty() {
-                                    None
-                                } else {
-                                    Some(
-                                        indices[1]
-                                            .parse::<u32>()
-                                            .map_err(|_| Error::CantCvt2Num)?
-                                            - 1,
-                                    )
-                                };
-                                let normal = if indices[2].is_empty() {
-                                    None
-                                } else {
-                                    Some(
-                                        indices[2]
-                                            .parse::<u32>()
-                                            .map_err(|_| Error::CantCvt2Num)?
-                                            - 1,
-                                    )
+                                let counts = [
+                                    self.scene.vertices.len(),
+                                    self.scene.texcoords.len(),
+                                    self.scene.normals.len(),
+                                ];
+                                let optional_index = |i: usize| match indices.get(i) {
+                                    None | Some(&"") => Ok(None),
+                                    Some(index) => resolve_index(index, counts[i]).map(Some),
                                 };
+                                let vertex = resolve_index(indices[0], counts[0])?;
+                                let texcoord = optional_index(1)?;
+                                let normal = optional_index(2)?;
                                 vertices.push(Vertex {
                                     vertex,
                                     normal,
@@ -344,45 +399,40 @@ impl<'a, 'b> ObjParser<'a, 'b> {
                             token = self.token_requester.request();
                         }
 
-                        self.scene
-                            .models
-                            .last_mut()
-                            .ok_or(Error::ParseIncomplete)?
-                            .faces
-                            .push(Face { vertices });
+                        self.scene.current_model().faces.push(Face { vertices });
                     }
                     "mtllib" => {
                         token = self.token_requester.request();
                         if let TokenType::Token(mtllib_filename) = token {
-                            let mut pathbuf = std::path::PathBuf::from(
-                                self.dirpath.parent().ok_or(Error::PathNotFount)?,
-                            );
-                            pathbuf.push(mtllib_filename);
-                            let filecontent = FileContent::from_file(pathbuf.as_path())?;
-                            let mut mtllib_token_requester = TokenRequester::new(&filecontent)?;
-                            let mut mtllib_parser = MtllibParser::new(&mut mtllib_token_requester);
-
-                            self.scene.materials.push(mtllib_parser.parse()?);
+                            let pathbuf = match self.dirpath {
+                                Some(dirpath) => dirpath.join(mtllib_filename),
+                                None => std::path::PathBuf::from(mtllib_filename),
+                            };
+                            self.scene.materials.push(parse_mtllib(pathbuf.as_path())?);
 
                             token = self.token_requester.request();
                         }
                     }
                     "usemtl" => {
-                        self.scene
-                            .models
-                            .last_mut()
-                            .ok_or(Error::ParseIncomplete)?
-                            .material =
+                        self.scene.current_model().material =
                             Some(parse_as![token = self.token_requester.request(); String]?)
                     }
                     "s" => {
+                        let group = parse_as![token = self.token_requester.request(); String]?;
+                        // `s off` is the same as `s 0`
+                        self.scene.current_model().smooth_shade = if group == "off" {
+                            0
+                        } else {
+                            group.parse::<u8>().map_err(|_| Error::CantCvt2Num)?
+                        };
+                    }
+                    _ => {
                         self.scene
-                            .models
-                            .last_mut()
-                            .ok_or(Error::ParseIncomplete)?
-                            .smooth_shade = parse_as![token = self.token_requester.request(); u8]?
+                            .warnings
+                            .push(self.token_requester.unknown_token_warning(token_str));
+                        ignore_until![token = self.token_requester.request();
+                                           TokenType::Nextline, TokenType::Eof]
                     }
-                    _ => return Err(Error::UnknownToken(token_str.to_string())),
                 },
                 TokenType::Eof => parse_finish = true,
                 TokenType::Nextline => token = self.token_requester.request(),
@@ -411,6 +461,7 @@ impl<'a> MtllibParser<'a> {
     fn parse(&mut self) -> Result<Mtllib, Error> {
         let mut mtllib = Mtllib {
             materials: HashMap::new(),
+            warnings: vec![],
         };
 
         let mut mtl: Option<Material> = None;
@@ -499,11 +550,52 @@ impl<'a> MtllibParser<'a> {
                         mtl.texture_maps.refl =
                             Some(parse_as![token = self.token_requester.request(); String]?)
                     ],
-                    "map_Bump" => parse_material_field![
+                    "map_Bump" | "bump" => parse_material_field![
                         mtl.texture_maps.bump =
                             Some(parse_as![token = self.token_requester.request(); String]?)
                     ],
-                    _ => return Err(Error::UnknownToken(token_str.to_string())),
+                    "Pr" => parse_material_field![
+                        mtl.roughness =
+                            Some(parse_as![token = self.token_requester.request(); f32]?)
+                    ],
+                    "Pm" => parse_material_field![
+                        mtl.metallic =
+                            Some(parse_as![token = self.token_requester.request(); f32]?)
+                    ],
+                    "Ps" => parse_material_field![
+                        mtl.sheen = Some(parse_as![token = self.token_requester.request(); f32]?)
+                    ],
+                    "Pc" => parse_material_field![
+                        mtl.clearcoat_thickness =
+                            Some(parse_as![token = self.token_requester.request(); f32]?)
+                    ],
+                    "Pcr" => parse_material_field![
+                        mtl.clearcoat_roughness =
+                            Some(parse_as![token = self.token_requester.request(); f32]?)
+                    ],
+                    "map_Ke" => parse_material_field![
+                        mtl.texture_maps.emissive =
+                            Some(parse_as![token = self.token_requester.request(); String]?)
+                    ],
+                    "map_Pr" => parse_material_field![
+                        mtl.texture_maps.roughness =
+                            Some(parse_as![token = self.token_requester.request(); String]?)
+                    ],
+                    "map_Pm" => parse_material_field![
+                        mtl.texture_maps.metallic =
+                            Some(parse_as![token = self.token_requester.request(); String]?)
+                    ],
+                    "norm" => parse_material_field![
+                        mtl.texture_maps.normal =
+                            Some(parse_as![token = self.token_requester.request(); String]?)
+                    ],
+                    _ => {
+                        mtllib
+                            .warnings
+                            .push(self.token_requester.unknown_token_warning(token_str));
+                        ignore_until![token = self.token_requester.request();
+                                           TokenType::Nextline, TokenType::Eof]
+                    }
                 },
                 TokenType::Nextline => token = self.token_requester.request(),
                 TokenType::Eof => {
@@ -522,13 +614,302 @@ impl<'a> MtllibParser<'a> {
 
 /// load scene from file
 pub fn load_from_file(filename: &str) -> Result<SceneData, Error> {
-    match FileContent::from_file(std::path::Path::new(filename)) {
-        Ok(content) => {
-            let mut token_requester = TokenRequester::new(&content)?;
-            let mut parser = ObjParser::new(std::path::Path::new(filename), &mut token_requester);
-            parser.parse()?;
-            Ok(parser.scene)
-        }
-        Err(err) => Err(Error::IoError(err)),
+    let path = std::path::Path::new(filename);
+    let content = FileContent::from_file(path)?;
+    parse_content(&content, Some(path.parent().ok_or(Error::PathNotFount)?))
+}
+
+/// load a standalone MTL file
+pub fn load_mtl(filename: &str) -> Result<Mtllib, Error> {
+    parse_mtllib(std::path::Path::new(filename))
+}
+
+/// load scene from OBJ content in memory, mtllib is found in `base_dir`(or working directory if `None`)
+pub fn load_from_str(
+    content: &str,
+    base_dir: Option<&std::path::Path>,
+) -> Result<SceneData, Error> {
+    load_from_reader(content.as_bytes(), base_dir)
+}
+
+/// load scene from any reader, mtllib is found in `base_dir`(or working directory if `None`)
+pub fn load_from_reader<R: BufRead>(
+    reader: R,
+    base_dir: Option<&std::path::Path>,
+) -> Result<SceneData, Error> {
+    let content = FileContent::from_reader(reader)?;
+    parse_content(&content, base_dir)
+}
+
+fn parse_mtllib(path: &std::path::Path) -> Result<Mtllib, Error> {
+    let content = FileContent::from_file(path)?;
+    let mut token_requester = TokenRequester::new(&content)?;
+    let mut parser = MtllibParser::new(&mut token_requester);
+    parser.parse()
+}
+
+fn parse_content(
+    content: &FileContent,
+    base_dir: Option<&std::path::Path>,
+) -> Result<SceneData, Error> {
+    let mut token_requester = TokenRequester::new(content)?;
+    let mut parser = ObjParser::new(base_dir, &mut token_requester);
+    parser.parse()?;
+    Ok(parser.scene)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CUBE_OBJ: &str = "\
+# unit cube
+g cube
+v -0.5 -0.5 0.5
+v 0.5 -0.5 0.5
+v 0.5 0.5 0.5
+v -0.5 0.5 0.5
+v -0.5 -0.5 -0.5
+v 0.5 -0.5 -0.5
+v 0.5 0.5 -0.5
+v -0.5 0.5 -0.5
+vn 0 0 1
+vn 0 0 -1
+vn 1 0 0
+vn -1 0 0
+vn 0 1 0
+vn 0 -1 0
+f 1//1 2//1 3//1
+f 1//1 3//1 4//1
+f 6//2 5//2 8//2
+f 6//2 8//2 7//2
+f 2//3 6//3 7//3
+f 2//3 7//3 3//3
+f 5//4 1//4 4//4
+f 5//4 4//4 8//4
+f 4//5 3//5 7//5
+f 4//5 7//5 8//5
+f 5//6 6//6 2//6
+f 5//6 2//6 1//6
+";
+
+    #[test]
+    fn load_cube_from_str() {
+        let scene = load_from_str(CUBE_OBJ, None).unwrap();
+
+        assert_eq!(scene.vertices.len(), 8);
+        assert_eq!(scene.normals.len(), 6);
+        assert_eq!(scene.models.len(), 1);
+        assert_eq!(scene.models[0].name, "cube");
+        assert_eq!(scene.models[0].faces.len(), 12);
+        assert!(scene.models[0]
+            .faces
+            .iter()
+            .all(|face| face.vertices.len() == 3));
+    }
+
+    #[test]
+    fn face_index_forms() {
+        let scene = load_from_str(
+            "\
+g forms
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0 0
+vn 0 0 1
+f 1 2 3
+f 1/1 2/1 3/1
+f 1//1 2//1 3//1
+f 1/1/1 2/1/1 3/1/1
+",
+            None,
+        )
+        .unwrap();
+
+        let faces = &scene.models[0].faces;
+        let forms: Vec<(u32, Option<u32>, Option<u32>)> = faces
+            .iter()
+            .map(|face| {
+                let v = &face.vertices[1];
+                (v.vertex, v.texcoord, v.normal)
+            })
+            .collect();
+        assert_eq!(
+            forms,
+            [
+                (1, None, None),
+                (1, Some(0), None),
+                (1, None, Some(0)),
+                (1, Some(0), Some(0))
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_face_indices() {
+        let scene = load_from_str(
+            "\
+g relative
+v 0 0 0
+v 1 0 0
+vt 0 0
+vn 0 0 1
+v 0 1 0
+vt 1 1
+vn 0 1 0
+f -3/-2/-2 -2/-2/-2 -1/-1/-1
+f 1/1/1 2/1/1 -1/-1/-1
+",
+            None,
+        )
+        .unwrap();
+
+        let faces = &scene.models[0].faces;
+        let indices = |face: &Face| -> Vec<(u32, Option<u32>, Option<u32>)> {
+            face.vertices
+                .iter()
+                .map(|v| (v.vertex, v.texcoord, v.normal))
+                .collect()
+        };
+        assert_eq!(
+            indices(&faces[0]),
+            [
+                (0, Some(0), Some(0)),
+                (1, Some(0), Some(0)),
+                (2, Some(1), Some(1))
+            ]
+        );
+        assert_eq!(
+            indices(&faces[1]),
+            [
+                (0, Some(0), Some(0)),
+                (1, Some(0), Some(0)),
+                (2, Some(1), Some(1))
+            ]
+        );
+
+        // before the first vertex
+        assert!(matches!(
+            load_from_str("g bad\nv 0 0 0\nf -2 -1 -1\n", None),
+            Err(Error::InvalidSyntax)
+        ));
+    }
+
+    #[test]
+    fn groupless_faces() {
+        let scene = load_from_str(
+            "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+s 1
+usemtl red
+f 1 2 3
+",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(scene.models.len(), 1);
+        let model = &scene.models[0];
+        assert_eq!(model.name, "");
+        assert_eq!(model.faces.len(), 1);
+        assert_eq!(model.material.as_deref(), Some("red"));
+        assert_eq!(model.smooth_shade, 1);
+    }
+
+    #[test]
+    fn skip_unknown_tokens() {
+        let scene = load_from_str(
+            "\
+g extension
+v 0 0 0
+v 1 0 0
+vp 0 0
+v 0 1 0
+l 1 2
+f 1 2 3
+",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(scene.vertices.len(), 3);
+        assert_eq!(scene.models[0].faces.len(), 1);
+        assert_eq!(
+            scene.warnings,
+            [
+                "line 4: skip unknown token `vp`",
+                "line 6: skip unknown token `l`"
+            ]
+        );
+    }
+
+    #[test]
+    fn load_pbr_mtl() {
+        let path = std::env::temp_dir().join("rs_cpurenderer_load_pbr.mtl");
+        std::fs::write(
+            &path,
+            "\
+newmtl metal
+Kd 0.8 0.8 0.8
+Pr 0.4
+Pm 1.0
+Pc 0.5
+norm bump.png
+map_Pr roughness.png
+",
+        )
+        .unwrap();
+
+        let mtllib = load_mtl(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let metal = &mtllib.materials["metal"];
+        assert_eq!(metal.roughness, Some(0.4));
+        assert_eq!(metal.metallic, Some(1.0));
+        assert_eq!(metal.clearcoat_thickness, Some(0.5));
+        assert_eq!(metal.sheen, None);
+        assert_eq!(metal.texture_maps.normal.as_deref(), Some("bump.png"));
+        assert_eq!(
+            metal.texture_maps.roughness.as_deref(),
+            Some("roughness.png")
+        );
+        assert!(mtllib.warnings.is_empty());
+    }
+
+    #[test]
+    fn load_standalone_mtl() {
+        let path = std::env::temp_dir().join("rs_cpurenderer_load_standalone.mtl");
+        std::fs::write(
+            &path,
+            "\
+# two materials
+newmtl red
+Kd 1 0 0
+Ns 10
+illum 1
+
+newmtl shiny
+Kd 0.5 0.5 0.5
+Ks 1 1 1
+illum 2
+",
+        )
+        .unwrap();
+
+        let mtllib = load_mtl(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mtllib.materials.len(), 2);
+        let red = &mtllib.materials["red"];
+        assert_eq!(red.diffuse, Some(math::Vec3::new(1.0, 0.0, 0.0)));
+        assert_eq!(red.specular_exponent, Some(10.0));
+        assert_eq!(red.illum, Some(1));
+        let shiny = &mtllib.materials["shiny"];
+        assert_eq!(shiny.diffuse, Some(math::Vec3::new(0.5, 0.5, 0.5)));
+        assert_eq!(shiny.specular, Some(math::Vec3::new(1.0, 1.0, 1.0)));
+        assert_eq!(shiny.illum, Some(2));
     }
 }
@@ -0,0 +1,153 @@
+//! full screen post process steps over `ColorAttachment`
+
+use crate::{image::ColorAttachment, math};
+
+pub trait PostProcess {
+    fn apply(&self, input: &ColorAttachment) -> ColorAttachment;
+}
+
+/// luminance with Rec.709 weights
+pub struct Grayscale;
+
+impl PostProcess for Grayscale {
+    fn apply(&self, input: &ColorAttachment) -> ColorAttachment {
+        let mut output = ColorAttachment::new(input.width(), input.height());
+        for y in 0..input.height() {
+            for x in 0..input.width() {
+                let color = input.get(x, y);
+                let luminance = 0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z;
+                output.set(x, y, &math::Vec4::new(luminance, luminance, luminance, 1.0));
+            }
+        }
+        output
+    }
+}
+
+/// `1 - color`, done on bytes so applying twice is lossless
+pub struct Invert;
+
+impl PostProcess for Invert {
+    fn apply(&self, input: &ColorAttachment) -> ColorAttachment {
+        let mut output = input.clone();
+        output
+            .data_mut()
+            .iter_mut()
+            .for_each(|value| *value = 255 - *value);
+        output
+    }
+}
+
+/// average of `(2 * radius + 1)^2` box, pixels out of image are ignored
+pub struct BoxBlur {
+    pub radius: u32,
+}
+
+impl PostProcess for BoxBlur {
+    fn apply(&self, input: &ColorAttachment) -> ColorAttachment {
+        let mut output = input.clone();
+        let radius = self.radius as i32;
+        let w = input.width() as i32;
+        let data = input.data();
+        for y in 0..input.height() as i32 {
+            for x in 0..w {
+                let mut sum = [0u32; 3];
+                let mut count = 0;
+                for dy in -radius..=radius {
+                    for dx in -radius..=radius {
+                        if input.in_box(x + dx, y + dy) {
+                            let index = ((x + dx + (y + dy) * w) * 3) as usize;
+                            for (channel, sum) in sum.iter_mut().enumerate() {
+                                *sum += data[index + channel] as u32;
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+                let index = ((x + y * w) * 3) as usize;
+                for (channel, sum) in sum.iter().enumerate() {
+                    output.data_mut()[index + channel] = (sum / count) as u8;
+                }
+            }
+        }
+        output
+    }
+}
+
+/// run post process steps in order, output of one step is input of the next
+#[derive(Default)]
+pub struct PostChain {
+    steps: Vec<Box<dyn PostProcess>>,
+}
+
+impl PostChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, step: Box<dyn PostProcess>) {
+        self.steps.push(step);
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl PostProcess for PostChain {
+    fn apply(&self, input: &ColorAttachment) -> ColorAttachment {
+        let mut output = input.clone();
+        for step in &self.steps {
+            output = step.apply(&output);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gradient_attachment() -> ColorAttachment {
+        let mut attachment = ColorAttachment::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                attachment.set(
+                    x,
+                    y,
+                    &math::Vec4::new(x as f32 / 7.0, y as f32 / 3.0, 0.3, 1.0),
+                );
+            }
+        }
+        attachment
+    }
+
+    #[test]
+    fn invert_twice_unchanged() {
+        let input = gradient_attachment();
+        let mut chain = PostChain::new();
+        chain.push(Box::new(Invert));
+        chain.push(Box::new(Invert));
+        assert_eq!(chain.len(), 2);
+
+        assert_eq!(chain.apply(&input).data(), input.data());
+        assert_ne!(Invert.apply(&input).data(), input.data());
+    }
+
+    #[test]
+    fn grayscale_and_blur() {
+        let input = gradient_attachment();
+        let gray = Grayscale.apply(&input);
+        let color = gray.get(5, 2);
+        assert!(color.x == color.y && color.y == color.z);
+
+        let mut single = ColorAttachment::new(3, 3);
+        single.set(1, 1, &math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+        let blurred = BoxBlur { radius: 1 }.apply(&single);
+        assert_eq!(blurred.data()[(1 + 3) * 3], 255 / 9);
+        assert_eq!(blurred.data()[0], 255 / 4);
+    }
+}
@@ -0,0 +1,252 @@
+//! ready-made shaders, each preset documents the attribute and uniform locations it uses
+
+use crate::{math, obj_loader::Material, shader::*};
+
+// attribute location
+pub const ATTR_COLOR: usize = 0; // vec4
+pub const ATTR_FOG: usize = 0; // float
+pub const ATTR_NORMAL: usize = 0; // vec3
+pub const ATTR_WORLD_POSITION: usize = 1; // vec3
+
+// uniform location
+pub const UNIFORM_MODEL_VIEW: u32 = 0; // mat4
+pub const UNIFORM_FOG_COLOR: u32 = 0; // vec4
+pub const UNIFORM_FOG_START: u32 = 0; // float
+pub const UNIFORM_FOG_END: u32 = 1; // float
+pub const UNIFORM_MODEL: u32 = 1; // mat4
+pub const UNIFORM_LIGHT_DIR: u32 = 0; // vec3, direction to light
+pub const UNIFORM_LIGHT_COLOR: u32 = 1; // vec3
+pub const UNIFORM_EYE_POSITION: u32 = 2; // vec3
+pub const UNIFORM_AMBIENT: u32 = 3; // vec3, Ka
+pub const UNIFORM_DIFFUSE: u32 = 4; // vec3, Kd
+pub const UNIFORM_SPECULAR: u32 = 5; // vec3, Ks
+pub const UNIFORM_SHININESS: u32 = 2; // float, Ns
+pub const UNIFORM_ILLUM: u32 = 0; // int
+
+/// 0 before `start`, 1 after `end`, linear between them
+pub fn linear_fog_factor(distance: f32, start: f32, end: f32) -> f32 {
+    ((distance - start) / (end - start)).clamp(0.0, 1.0)
+}
+
+/// Gouraud fog: fog factor is computed per vertex(from view space distance) and interpolated,
+/// then vertex color(`ATTR_COLOR`) is blended to fog color per pixel.
+/// `UNIFORM_MODEL_VIEW` must be set to `view * model` since vertex changing runs before model view transform
+pub fn gouraud_fog_shader() -> Shader {
+    Shader {
+        vertex_changing: Box::new(|vertex, uniforms, _| {
+            let model_view = uniforms
+                .mat4
+                .get(&UNIFORM_MODEL_VIEW)
+                .copied()
+                .unwrap_or_else(math::Mat4::identity);
+            let start = *uniforms.float.get(&UNIFORM_FOG_START).unwrap_or(&0.0);
+            let end = *uniforms.float.get(&UNIFORM_FOG_END).unwrap_or(&1.0);
+
+            let distance = -(model_view * vertex.position).z;
+            let mut vertex = *vertex;
+            vertex
+                .attributes
+                .set_float(ATTR_FOG, linear_fog_factor(distance, start, end));
+            vertex
+        }),
+        pixel_shading: Box::new(|attr, uniforms, _, _| {
+            let fog_color = *uniforms
+                .vec4
+                .get(&UNIFORM_FOG_COLOR)
+                .unwrap_or(&math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+            math::lerp(
+                attr.vec4[ATTR_COLOR],
+                fog_color,
+                attr.float[ATTR_FOG].clamp(0.0, 1.0),
+            )
+        }),
+        pixel_shading_discard: None,
+        interp_modes: Default::default(),
+        world_position: false,
+        uniforms: Default::default(),
+    }
+}
+
+/// which lighting terms the phong preset evaluates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightingTerms {
+    pub ambient: bool,
+    pub diffuse: bool,
+    pub specular: bool,
+}
+
+impl LightingTerms {
+    /// map MTL `illum` to lighting terms:
+    /// 0 = color only(Kd), 1 = ambient + diffuse, 2 and above = ambient + diffuse + specular.
+    /// Models above 2(reflection, refraction) fall back to 2
+    pub fn from_illum(illum: u8) -> Self {
+        Self {
+            ambient: illum >= 1,
+            diffuse: illum >= 1,
+            specular: illum >= 2,
+        }
+    }
+}
+
+/// write material coefficients and illum model into uniforms used by `phong_shader`,
+/// missing `illum` is treated as 2
+pub fn bind_material(uniforms: &mut Uniforms, material: &Material) {
+    let zero = math::Vec3::zero();
+    uniforms.set_vec3(UNIFORM_AMBIENT, material.ambient.unwrap_or(zero));
+    uniforms.set_vec3(
+        UNIFORM_DIFFUSE,
+        material.diffuse.unwrap_or(math::Vec3::new(1.0, 1.0, 1.0)),
+    );
+    uniforms.set_vec3(UNIFORM_SPECULAR, material.specular.unwrap_or(zero));
+    uniforms.set_float(UNIFORM_SHININESS, material.specular_exponent.unwrap_or(1.0));
+    uniforms.set_int(UNIFORM_ILLUM, material.illum.unwrap_or(2) as i32);
+}
+
+/// Blinn-Phong with one directional light, terms are selected by `UNIFORM_ILLUM`(see `LightingTerms::from_illum`).
+/// `UNIFORM_MODEL` transforms position and normal(`ATTR_NORMAL`) to world space
+pub fn phong_shader() -> Shader {
+    Shader {
+        vertex_changing: Box::new(|vertex, uniforms, _| {
+            let model = uniforms
+                .mat4
+                .get(&UNIFORM_MODEL)
+                .copied()
+                .unwrap_or_else(math::Mat4::identity);
+            let mut vertex = *vertex;
+            let world_position = (model * vertex.position).truncated_to_vec3();
+            let normal = (model * math::Vec4::from_vec3(&vertex.attributes.vec3[ATTR_NORMAL], 0.0))
+                .truncated_to_vec3()
+                .normalize_or_zero();
+            vertex
+                .attributes
+                .set_vec3(ATTR_WORLD_POSITION, world_position);
+            vertex.attributes.set_vec3(ATTR_NORMAL, normal);
+            vertex
+        }),
+        pixel_shading: Box::new(|attr, uniforms, _, _| {
+            let zero = math::Vec3::zero();
+            let one = math::Vec3::new(1.0, 1.0, 1.0);
+            let terms =
+                LightingTerms::from_illum(*uniforms.int.get(&UNIFORM_ILLUM).unwrap_or(&2) as u8);
+            let diffuse = *uniforms.vec3.get(&UNIFORM_DIFFUSE).unwrap_or(&one);
+
+            if !terms.diffuse {
+                return math::Vec4::from_vec3(&diffuse, 1.0);
+            }
+
+            let ambient = *uniforms.vec3.get(&UNIFORM_AMBIENT).unwrap_or(&zero);
+            let light_color = *uniforms.vec3.get(&UNIFORM_LIGHT_COLOR).unwrap_or(&one);
+            let light_dir = uniforms
+                .vec3
+                .get(&UNIFORM_LIGHT_DIR)
+                .unwrap_or(&math::Vec3::new(0.0, 0.0, 1.0))
+                .normalize_or_zero();
+            let normal = attr.vec3[ATTR_NORMAL].normalize_or_zero();
+
+            let mut color = zero;
+            if terms.ambient {
+                color += ambient;
+            }
+            color += diffuse * light_color * normal.dot(&light_dir).max(0.0);
+            if terms.specular {
+                let specular = *uniforms.vec3.get(&UNIFORM_SPECULAR).unwrap_or(&zero);
+                let shininess = *uniforms.float.get(&UNIFORM_SHININESS).unwrap_or(&1.0);
+                let eye = *uniforms.vec3.get(&UNIFORM_EYE_POSITION).unwrap_or(&zero);
+                let view_dir = (eye - attr.vec3[ATTR_WORLD_POSITION]).normalize_or_zero();
+                let half = (view_dir + light_dir).normalize_or_zero();
+                color += specular * light_color * normal.dot(&half).max(0.0).powf(shininess);
+            }
+
+            math::Vec4::from_vec3(&color, 1.0)
+        }),
+        pixel_shading_discard: None,
+        interp_modes: Default::default(),
+        world_position: false,
+        uniforms: Default::default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::texture::TextureStorage;
+
+    #[test]
+    fn gouraud_fog_increase_with_distance() {
+        let shader = gouraud_fog_shader();
+        let mut uniforms = Uniforms::default();
+        uniforms.float.insert(UNIFORM_FOG_START, 2.0);
+        uniforms.float.insert(UNIFORM_FOG_END, 20.0);
+        let texture_storage = TextureStorage::default();
+
+        let near = shader.call_vertex_changing(
+            &Vertex::new(math::Vec3::new(0.0, 0.0, -3.0), Default::default()),
+            &uniforms,
+            &texture_storage,
+        );
+        let far = shader.call_vertex_changing(
+            &Vertex::new(math::Vec3::new(0.0, 0.0, -15.0), Default::default()),
+            &uniforms,
+            &texture_storage,
+        );
+        assert!(far.attributes.float[ATTR_FOG] > near.attributes.float[ATTR_FOG]);
+
+        let near_side = lerp_vertex(&near, &far, 0.25);
+        let far_side = lerp_vertex(&near, &far, 0.75);
+        assert!(far_side.attributes.float[ATTR_FOG] > near_side.attributes.float[ATTR_FOG]);
+    }
+
+    #[test]
+    fn phong_follow_illum() {
+        assert_eq!(
+            LightingTerms::from_illum(0),
+            LightingTerms {
+                ambient: false,
+                diffuse: false,
+                specular: false
+            }
+        );
+        assert!(LightingTerms::from_illum(1).diffuse && !LightingTerms::from_illum(1).specular);
+        assert!(LightingTerms::from_illum(7).specular);
+
+        let shader = phong_shader();
+        let texture_storage = TextureStorage::default();
+        let mut uniforms = Uniforms::default();
+        uniforms
+            .vec3
+            .insert(UNIFORM_EYE_POSITION, math::Vec3::new(0.0, 0.0, 5.0));
+        uniforms
+            .vec3
+            .insert(UNIFORM_LIGHT_DIR, math::Vec3::new(0.0, 0.0, 1.0));
+        uniforms
+            .vec3
+            .insert(UNIFORM_DIFFUSE, math::Vec3::new(0.5, 0.2, 0.1));
+        uniforms
+            .vec3
+            .insert(UNIFORM_SPECULAR, math::Vec3::new(0.3, 0.3, 0.3));
+        uniforms.float.insert(UNIFORM_SHININESS, 16.0);
+
+        // surface facing both light and eye, so diffuse and specular are maximal
+        let mut attr = Attributes::default();
+        attr.set_vec3(ATTR_NORMAL, math::Vec3::new(0.0, 0.0, 1.0));
+        attr.set_vec3(ATTR_WORLD_POSITION, math::Vec3::zero());
+        let context = FragmentContext::new(0, 0, 0.5, -1.0, -1.0, f32::MAX);
+
+        uniforms.int.insert(UNIFORM_ILLUM, 0);
+        let unlit = shader
+            .call_pixel_shading(&attr, &uniforms, &texture_storage, &context)
+            .unwrap();
+        assert_eq!(unlit, math::Vec4::new(0.5, 0.2, 0.1, 1.0));
+
+        uniforms.int.insert(UNIFORM_ILLUM, 1);
+        let diffuse = shader
+            .call_pixel_shading(&attr, &uniforms, &texture_storage, &context)
+            .unwrap();
+        uniforms.int.insert(UNIFORM_ILLUM, 2);
+        let specular = shader
+            .call_pixel_shading(&attr, &uniforms, &texture_storage, &context)
+            .unwrap();
+        assert!((specular.x - diffuse.x - 0.3).abs() < 1e-4);
+        assert!((specular.z - diffuse.z - 0.3).abs() < 1e-4);
+    }
+}
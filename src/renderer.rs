@@ -2,12 +2,15 @@ use crate::camera::Camera;
 use crate::image::*;
 use crate::line::Line;
 use crate::math;
+use crate::model::Mesh;
 use crate::shader;
 use crate::shader::Uniforms;
 use crate::shader::{Shader, Vertex};
 use crate::texture::Texture;
 use crate::texture::TextureStorage;
 
+/// `(x, y)` is the top left pixel of viewport in screen space(y axis points down)
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Viewport {
     pub x: i32,
     pub y: i32,
@@ -15,6 +18,250 @@ pub struct Viewport {
     pub h: u32,
 }
 
+impl Viewport {
+    /// viewport of a target `scale` times larger in both axes
+    pub fn scaled(&self, scale: u32) -> Viewport {
+        Viewport {
+            x: self.x * scale as i32,
+            y: self.y * scale as i32,
+            w: self.w * scale,
+            h: self.h * scale,
+        }
+    }
+
+    /// pixels in `[min, max]`(inclusive) inside both viewport and a `w` x `h` target, draws never write outside them
+    pub fn pixel_bounds(&self, w: u32, h: u32) -> (math::Vec2, math::Vec2) {
+        let min = math::Vec2::new(self.x.max(0) as f32, self.y.max(0) as f32);
+        let max = math::Vec2::new(
+            ((self.x + self.w as i32).min(w as i32) - 1) as f32,
+            ((self.y + self.h as i32).min(h as i32) - 1) as f32,
+        );
+        (min, max)
+    }
+
+    /// transform NDC x, y into screen space(y axis points down).
+    /// Viewport with a single pixel(or none, see `pixel_bounds`) along an axis puts NDC 0 on its first pixel
+    pub fn transform(&self, ndc: &math::Vec2) -> math::Vec2 {
+        // distance from -1 in NDC, in pixels
+        let offset = |ndc: f32, size: u32| {
+            if size > 1 {
+                (ndc + 1.0) * 0.5 * (size as f32 - 1.0)
+            } else {
+                ndc * 0.5
+            }
+        };
+        let bottom = if self.h > 1 { self.h as f32 } else { 0.0 };
+        math::Vec2::new(
+            offset(ndc.x, self.w) + self.x as f32,
+            bottom - offset(ndc.y, self.h) + self.y as f32,
+        )
+    }
+}
+
+/// attachments which draws write into, color or depth can be absent(e.g. depth only target for shadow pass).
+/// Color is written into both `color` and `rgba` if both exist, `rgba` keeps the alpha channel for compositing
+pub struct RenderTarget {
+    pub color: Option<ColorAttachment>,
+    pub depth: Option<DepthAttachment>,
+    pub id: Option<IdAttachment>,
+    pub rgba: Option<RgbaColorAttachment>,
+}
+
+impl RenderTarget {
+    pub fn new(w: u32, h: u32) -> Self {
+        Self {
+            color: Some(ColorAttachment::new(w, h)),
+            depth: Some(DepthAttachment::new(w, h)),
+            id: None,
+            rgba: None,
+        }
+    }
+
+    pub fn color_only(w: u32, h: u32) -> Self {
+        Self {
+            color: Some(ColorAttachment::new(w, h)),
+            depth: None,
+            id: None,
+            rgba: None,
+        }
+    }
+
+    pub fn depth_only(w: u32, h: u32) -> Self {
+        Self {
+            color: None,
+            depth: Some(DepthAttachment::new(w, h)),
+            id: None,
+            rgba: None,
+        }
+    }
+
+    /// RGBA color with depth, for rendering over a transparent background
+    pub fn rgba(w: u32, h: u32) -> Self {
+        Self {
+            color: None,
+            depth: Some(DepthAttachment::new(w, h)),
+            id: None,
+            rgba: Some(RgbaColorAttachment::new(w, h)),
+        }
+    }
+
+    /// 0 if there is no attachment
+    pub fn width(&self) -> u32 {
+        match (&self.color, &self.rgba, &self.depth) {
+            (Some(color), _, _) => color.width(),
+            (None, Some(rgba), _) => rgba.width(),
+            (None, None, Some(depth)) => depth.width(),
+            (None, None, None) => 0,
+        }
+    }
+
+    /// 0 if there is no attachment
+    pub fn height(&self) -> u32 {
+        match (&self.color, &self.rgba, &self.depth) {
+            (Some(color), _, _) => color.height(),
+            (None, Some(rgba), _) => rgba.height(),
+            (None, None, Some(depth)) => depth.height(),
+            (None, None, None) => 0,
+        }
+    }
+
+    /// has RGB or RGBA color attachment
+    pub fn has_color(&self) -> bool {
+        self.color.is_some() || self.rgba.is_some()
+    }
+
+    pub fn clear_color(&mut self, color: &math::Vec4) {
+        if let Some(color_attachment) = &mut self.color {
+            color_attachment.clear(color);
+        }
+        if let Some(rgba_attachment) = &mut self.rgba {
+            rgba_attachment.clear(color);
+        }
+    }
+
+    /// read from RGBA attachment first since it keeps alpha, `None` without color attachment
+    pub(crate) fn get_color(&self, x: u32, y: u32) -> Option<math::Vec4> {
+        match (&self.rgba, &self.color) {
+            (Some(rgba_attachment), _) => Some(rgba_attachment.get(x, y)),
+            (None, Some(color_attachment)) => Some(color_attachment.get(x, y)),
+            (None, None) => None,
+        }
+    }
+
+    /// always pass without depth attachment
+    pub(crate) fn depth_test(&self, x: u32, y: u32, depth: f32) -> bool {
+        match &self.depth {
+            Some(depth_attachment) => depth_attachment.get(x, y) >= depth,
+            None => true,
+        }
+    }
+
+    /// stored depth, `f32::MAX`(as cleared) without depth attachment
+    pub(crate) fn get_depth(&self, x: u32, y: u32) -> f32 {
+        match &self.depth {
+            Some(depth_attachment) => depth_attachment.get(x, y),
+            None => f32::MAX,
+        }
+    }
+
+    pub(crate) fn set_depth(&mut self, x: u32, y: u32, depth: f32) {
+        if let Some(depth_attachment) = &mut self.depth {
+            depth_attachment.set(x, y, depth);
+        }
+    }
+
+    pub(crate) fn set_color(&mut self, x: u32, y: u32, color: &math::Vec4) {
+        if let Some(color_attachment) = &mut self.color {
+            color_attachment.set(x, y, color);
+        }
+        if let Some(rgba_attachment) = &mut self.rgba {
+            rgba_attachment.set(x, y, color);
+        }
+    }
+
+    /// destination is only read back when blending
+    pub(crate) fn blend_color(
+        &mut self,
+        x: u32,
+        y: u32,
+        color: &math::Vec4,
+        blend_mode: BlendMode,
+    ) {
+        if let Some(dst) = self.get_color(x, y) {
+            let color = match blend_mode {
+                BlendMode::None => *color,
+                _ => blend_mode.blend(color, &dst),
+            };
+            self.set_color(x, y, &color);
+        }
+    }
+
+    pub(crate) fn set_id(&mut self, x: u32, y: u32, id: u32) {
+        if let Some(id_attachment) = &mut self.id {
+            id_attachment.set(x, y, id);
+        }
+    }
+}
+
+/// like `glDepthRange`, linear depth between near plane and far plane is remapped into `[near, far]`,
+/// the depth buffer stores the remapped depth(less is closer)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthRange {
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for DepthRange {
+    fn default() -> Self {
+        Self {
+            near: 0.0,
+            far: 1.0,
+        }
+    }
+}
+
+impl DepthRange {
+    /// map view space z(negative) into depth range, depth out of frustum is clamped
+    pub fn map(&self, z: f32, frustum_near: f32, frustum_far: f32) -> f32 {
+        let t = ((-z - frustum_near) / (frustum_far - frustum_near)).clamp(0.0, 1.0);
+        self.near + (self.far - self.near) * t
+    }
+}
+
+/// how a shaded color is written over the existing color
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    /// overwrite destination
+    None,
+    /// source-over: `src.a * src + (1 - src.a) * dst`
+    AlphaBlend,
+}
+
+impl BlendMode {
+    pub fn blend(&self, src: &math::Vec4, dst: &math::Vec4) -> math::Vec4 {
+        match self {
+            BlendMode::None => *src,
+            BlendMode::AlphaBlend => {
+                let mut color = math::lerp(*dst, *src, src.w);
+                color.w = src.w + dst.w * (1.0 - src.w);
+                color
+            }
+        }
+    }
+}
+
+/// per pixel states of a draw besides color and depth
+#[derive(Clone, Copy)]
+pub(crate) struct PixelWrite {
+    /// written into ID attachment when `Some`
+    pub id: Option<u32>,
+    pub blend_mode: BlendMode,
+    /// inclusive pixel bounds, see `Viewport::pixel_bounds`
+    pub bounds: (math::Vec2, math::Vec2),
+    /// pixel shading may discard fragments, so it runs even without color attachment
+    pub may_discard: bool,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum FaceCull {
     Front,
@@ -28,18 +275,132 @@ pub enum FrontFace {
     CCW,
 }
 
+/// how to decide a triangle is front face or back face
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CullMethod {
+    /// dot face normal with camera's view direction, exact only when the triangle is near the view center
+    ViewDir,
+    /// signed area of the triangle after viewport transform, always matches the rasterized winding
+    ScreenArea,
+}
+
+/// a triangle ready for rasterization, passed to `TriangleCallback`
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleInfo {
+    /// index of triangle in the vertices of this draw call
+    pub index: usize,
+    /// vertices in screen space
+    pub vertices: [math::Vec2; 3],
+    /// unsigned area in pixels
+    pub area: f32,
+    /// generated by near plane clipping, one submitted triangle may generate two
+    pub clipped: bool,
+}
+
+impl TriangleInfo {
+    pub(crate) fn new(index: usize, vertices: [math::Vec2; 3], clipped: bool) -> Self {
+        Self {
+            index,
+            vertices,
+            area: ((vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0])) * 0.5).abs(),
+            clipped,
+        }
+    }
+}
+
+/// invoked for every triangle after setup, culled triangles are not reported
+pub type TriangleCallback = Box<dyn FnMut(&TriangleInfo)>;
+
 pub trait RendererInterface {
     fn clear(&mut self, color: &math::Vec4);
     fn clear_depth(&mut self);
     fn get_canva_width(&self) -> u32;
     fn get_canva_height(&self) -> u32;
+    /// Output is deterministic: triangles are rasterized one by one in submission order,
+    /// and when depths are equal the later one wins, so same inputs always produce the same image
     fn draw_triangle(
         &mut self,
         model: &math::Mat4,
         vertices: &[Vertex],
         texture_storage: &TextureStorage,
     );
+    /// draw triangles of `indices` into `vertices`(every 3 indices is a triangle, remaining indices are ignored),
+    /// vertex changing runs once per vertex. Panics if an index is out of `vertices`
+    fn draw_indexed(
+        &mut self,
+        model: &math::Mat4,
+        vertices: &[Vertex],
+        indices: &[u32],
+        texture_storage: &TextureStorage,
+    ) {
+        if let Some(index) = indices
+            .iter()
+            .find(|&&index| index as usize >= vertices.len())
+        {
+            panic!(
+                "draw_indexed: index {} is out of {} vertices",
+                index,
+                vertices.len()
+            );
+        }
+        let triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        self.draw_triangle_indices(model, vertices, &triangles, texture_storage);
+    }
+    /// hook of `draw_indexed`: change every vertex once, then draw `triangles` in order, indices are already validated
+    fn draw_triangle_indices(
+        &mut self,
+        model: &math::Mat4,
+        vertices: &[Vertex],
+        triangles: &[[u32; 3]],
+        texture_storage: &TextureStorage,
+    );
+    /// draw a 3D line through vertex changing, model view projection and viewport transform with depth test,
+    /// the part behind near plane is clipped
+    fn draw_line_3d(
+        &mut self,
+        model: &math::Mat4,
+        start: Vertex,
+        end: Vertex,
+        texture_storage: &TextureStorage,
+    );
+    /// draw with `front_face` and `cull` for this call only, global states are restored after drawing
+    fn draw_triangle_with_state(
+        &mut self,
+        model: &math::Mat4,
+        vertices: &[Vertex],
+        texture_storage: &TextureStorage,
+        front_face: FrontFace,
+        cull: FaceCull,
+    ) {
+        let old_front_face = self.get_front_face();
+        let old_cull = self.get_face_cull();
+        self.set_front_face(front_face);
+        self.set_face_cull(cull);
+        self.draw_triangle(model, vertices, texture_storage);
+        self.set_front_face(old_front_face);
+        self.set_face_cull(old_cull);
+    }
+
+    /// NDC is mapped into viewport and pixels outside it are never written, e.g. for split screen
+    fn set_viewport(&mut self, viewport: Viewport);
+    fn get_viewport(&self) -> &Viewport;
+    /// render with `scale` x `scale` samples per pixel into an internal target, `get_rendered_image` returns the box filtered result.
+    /// Attachments of current target are recreated(not cleared) at the new size, `get_target` returns the internal target
+    fn set_supersample_scale(&mut self, scale: u32);
+    fn get_supersample_scale(&self) -> u32;
+    /// redirect subsequent draws into `target`, viewport is reset to the whole target and supersample scale is reset to 1, return the previous target
+    fn bind_target(&mut self, target: RenderTarget) -> RenderTarget;
+    fn get_target(&self) -> &RenderTarget;
+    /// the presented image(empty if there is no color attachment), which is the back buffer of last `swap_buffers` when double buffer enabled
     fn get_rendered_image(&self) -> &[u8];
+    /// render into back buffer and present the front buffer, both are swapped by `swap_buffers`
+    fn enable_double_buffer(&mut self);
+    fn disable_double_buffer(&mut self);
+    /// do nothing if double buffer is disabled
+    fn swap_buffers(&mut self);
     fn get_shader(&mut self) -> &mut Shader;
     fn get_uniforms(&mut self) -> &mut Uniforms;
     fn get_camera(&mut self) -> &mut Camera;
@@ -48,17 +409,211 @@ pub trait RendererInterface {
     fn get_front_face(&self) -> FrontFace;
     fn set_face_cull(&mut self, cull: FaceCull);
     fn get_face_cull(&self) -> FaceCull;
+    fn set_cull_method(&mut self, method: CullMethod);
+    fn get_cull_method(&self) -> CullMethod;
+    fn set_depth_range(&mut self, near: f32, far: f32);
+    fn get_depth_range(&self) -> DepthRange;
+    fn set_blend_mode(&mut self, mode: BlendMode);
+    fn get_blend_mode(&self) -> BlendMode;
+    fn set_triangle_callback(&mut self, callback: TriangleCallback);
+    fn reset_triangle_callback(&mut self);
+    /// fractional bits of the fixed point grid screen space vertices snap to before rasterization, `None` disables snapping
+    fn set_subpixel_bits(&mut self, bits: Option<u32>);
+    fn get_subpixel_bits(&self) -> Option<u32>;
+    /// when `Some`, edges of back face triangles are drawn in this color instead of pixel shading in framework mode
+    fn set_back_face_framework_color(&mut self, color: Option<math::Vec4>);
+    fn get_back_face_framework_color(&self) -> Option<math::Vec4>;
     fn enable_framework(&mut self);
     fn disable_framework(&mut self);
     fn toggle_framework(&mut self);
+    /// add an ID attachment to current target, see `get_id_at`
+    fn enable_id_buffer(&mut self);
+    fn disable_id_buffer(&mut self);
+    /// clear ID attachment and count triangles from 0 again
+    fn clear_id(&mut self);
+    /// draw-order index of the triangle which last wrote the pixel, counted over all draw calls since last `clear_id`.
+    /// `None` if `(x, y)` is out of canvas, no triangle covers the pixel or there is no ID attachment
+    fn get_id_at(&self, x: u32, y: u32) -> Option<u32> {
+        // check before scaling, so that `x * scale` can't overflow
+        if x >= self.get_canva_width() || y >= self.get_canva_height() {
+            return None;
+        }
+        let scale = self.get_supersample_scale();
+        self.get_target()
+            .id
+            .as_ref()
+            .and_then(|id| id.get(x * scale, y * scale))
+    }
+    /// value in depth attachment, in depth range(`f32::MAX` if nothing drawn since last clear).
+    /// `None` if `(x, y)` is out of canvas or there is no depth attachment
+    fn get_depth_at(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.get_canva_width() || y >= self.get_canva_height() {
+            return None;
+        }
+        let scale = self.get_supersample_scale();
+        self.get_target()
+            .depth
+            .as_ref()
+            .map(|depth| depth.get(x * scale, y * scale))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// vertex count is not multiple of 3
+    BadCount(usize),
+    /// triangle(index) has zero area
+    Degenerate(usize),
+    /// triangle(index) has NaN or infinite position
+    NaN(usize),
+}
+
+fn validate_triangle(vertices: &[Vertex], index: usize) -> Result<(), ValidationError> {
+    let positions = [
+        vertices[index * 3].position,
+        vertices[index * 3 + 1].position,
+        vertices[index * 3 + 2].position,
+    ];
+    if positions
+        .iter()
+        .any(|p| !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite() && p.w.is_finite()))
+    {
+        return Err(ValidationError::NaN(index));
+    }
+
+    let [p1, p2, p3] = positions.map(|p| p.truncated_to_vec3());
+    if (p2 - p1).cross(&(p3 - p1)).length_square() <= f32::EPSILON * f32::EPSILON {
+        return Err(ValidationError::Degenerate(index));
+    }
+
+    Ok(())
+}
+
+/// check triangle list before drawing, report the first problem
+pub fn validate_triangles(vertices: &[Vertex]) -> Result<(), ValidationError> {
+    if !vertices.len().is_multiple_of(3) {
+        return Err(ValidationError::BadCount(vertices.len()));
+    }
+    for i in 0..vertices.len() / 3 {
+        validate_triangle(vertices, i)?;
+    }
+    Ok(())
 }
 
+/// drop the incomplete tail and all invalid triangles
+pub fn repair_triangles(vertices: &[Vertex]) -> Vec<Vertex> {
+    let mut result = Vec::with_capacity(vertices.len() - vertices.len() % 3);
+    for i in 0..vertices.len() / 3 {
+        if validate_triangle(vertices, i).is_ok() {
+            result.extend_from_slice(&vertices[i * 3..i * 3 + 3]);
+        }
+    }
+    result
+}
+
+/// nearest sample, texcoord out of `[0, 1]` is clamped to edge.
+/// Use `texture::Sampler` for other wrap modes and mipmaps, `texture_sample_bilinear` for filtering
 pub fn texture_sample(texture: &Texture, texcoord: &math::Vec2) -> math::Vec4 {
-    let x = (texcoord.x * (texture.width() - 1) as f32) as u32;
-    let y = (texcoord.y * ((texture.height() - 1) as f32)) as u32;
+    let x = (texcoord.x.clamp(0.0, 1.0) * (texture.width() - 1) as f32) as u32;
+    let y = (texcoord.y.clamp(0.0, 1.0) * ((texture.height() - 1) as f32)) as u32;
     texture.get(x, y)
 }
 
+/// interpolate the four texels around `texcoord`, texcoord is mapped the same way as `texture_sample`
+pub fn texture_sample_bilinear(texture: &Texture, texcoord: &math::Vec2) -> math::Vec4 {
+    let max_x = (texture.width() - 1) as f32;
+    let max_y = (texture.height() - 1) as f32;
+    let x = (texcoord.x * max_x).clamp(0.0, max_x);
+    let y = (texcoord.y * max_y).clamp(0.0, max_y);
+
+    // `+1` texel is clamped so texcoord 1.0 doesn't read out of bounds
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let x1 = (x0 + 1).min(texture.width() - 1);
+    let y1 = (y0 + 1).min(texture.height() - 1);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let top = math::lerp(texture.get(x0, y0), texture.get(x1, y0), fx);
+    let bottom = math::lerp(texture.get(x0, y1), texture.get(x1, y1), fx);
+    math::lerp(top, bottom, fy)
+}
+
+/// frustum culling for a batch of meshes, `models[i]` is the model matrix of `meshes[i]`.
+/// Return visibility flags, empty meshes are treated as invisible
+pub fn cull_meshes(meshes: &[Mesh], models: &[math::Mat4], camera: &Camera) -> Vec<bool> {
+    meshes
+        .iter()
+        .zip(models.iter())
+        .map(|(mesh, model)| match mesh.aabb() {
+            Some(aabb) => {
+                let model_view = *camera.view_mat() * *model;
+                let corners = aabb.corners().map(|corner| {
+                    (model_view * math::Vec4::from_vec3(&corner, 1.0)).truncated_to_vec3()
+                });
+                camera.get_frustum().intersect_box(&corners)
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// `index`-th element of Halton low discrepancy sequence in `[0, 1)`, `index` starts from 1
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// call `draw` `samples` times with camera jittered inside one pixel by Halton(2, 3) sequence, and average rendered images.
+/// `draw` should clear and render the whole frame, camera jitter is restored after all samples
+pub fn render_jittered<F>(
+    renderer: &mut dyn RendererInterface,
+    samples: u32,
+    mut draw: F,
+) -> HdrAttachment
+where
+    F: FnMut(&mut dyn RendererInterface),
+{
+    let (w, h) = (renderer.get_canva_width(), renderer.get_canva_height());
+    let origin_jitter = renderer.get_camera().get_frustum().jitter();
+    let mut accumulated = HdrAttachment::new(w, h);
+    for i in 1..=samples {
+        // pixel offset in [-0.5, 0.5), converted into NDC
+        let offset = math::Vec2::new(halton(i, 2) - 0.5, halton(i, 3) - 0.5);
+        renderer.get_camera().set_jitter(
+            origin_jitter + math::Vec2::new(offset.x * 2.0 / w as f32, offset.y * 2.0 / h as f32),
+        );
+        draw(renderer);
+        accumulated.accumulate_rgb(renderer.get_rendered_image(), 1.0 / samples as f32);
+    }
+    renderer.get_camera().set_jitter(origin_jitter);
+    accumulated
+}
+
+/// two CCW triangles facing the camera, `size` is the full width and height of quad
+pub fn billboard(center: math::Vec3, size: math::Vec2, camera: &Camera) -> [Vertex; 6] {
+    let (right, up, _) = camera.basis();
+    let right = right * (size.x * 0.5);
+    let up = up * (size.y * 0.5);
+
+    let left_bottom = Vertex::new(center - right - up, Default::default());
+    let right_bottom = Vertex::new(center + right - up, Default::default());
+    let right_top = Vertex::new(center + right + up, Default::default());
+    let left_top = Vertex::new(center - right + up, Default::default());
+    [
+        left_bottom,
+        right_bottom,
+        right_top,
+        left_bottom,
+        right_top,
+        left_top,
+    ]
+}
+
 pub(crate) fn should_cull(
     positions: &[math::Vec3; 3],
     view_dir: &math::Vec3,
@@ -71,6 +626,29 @@ pub(crate) fn should_cull(
         FrontFace::CCW => norm.dot(view_dir) <= 0.0,
     };
 
+    cull_by_face(is_front_face, cull)
+}
+
+/// positions are in screen space(after viewport transform, y axis points down)
+pub(crate) fn should_cull_screen(
+    positions: &[math::Vec2; 3],
+    face: FrontFace,
+    cull: FaceCull,
+) -> bool {
+    cull_by_face(is_front_face_screen(positions, face), cull)
+}
+
+/// positions are in screen space(after viewport transform, y axis points down)
+pub(crate) fn is_front_face_screen(positions: &[math::Vec2; 3], face: FrontFace) -> bool {
+    // y axis is flipped by viewport transform, so CCW triangle has negative area on screen
+    let area = (positions[1] - positions[0]).cross(&(positions[2] - positions[0]));
+    match face {
+        FrontFace::CW => area > 0.0,
+        FrontFace::CCW => area <= 0.0,
+    }
+}
+
+fn cull_by_face(is_front_face: bool, cull: FaceCull) -> bool {
     match cull {
         FaceCull::Front => is_front_face,
         FaceCull::Back => !is_front_face,
@@ -78,22 +656,128 @@ pub(crate) fn should_cull(
     }
 }
 
+/// vertex changing, transform, near clip and rasterize a 3D line, the shared part of `draw_line_3d`
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn draw_line_3d(
+    model: &math::Mat4,
+    line: [Vertex; 2],
+    shader: &Shader,
+    uniforms: &Uniforms,
+    texture_storage: &TextureStorage,
+    target: &mut RenderTarget,
+    camera: &Camera,
+    viewport: &Viewport,
+    depth_range: DepthRange,
+    blend_mode: BlendMode,
+) {
+    let line = line.map(|v| change_vertex(shader, uniforms, model, &v, texture_storage));
+    let Some([mut v1, mut v2]) = transform_line(model, line, camera, viewport) else {
+        return;
+    };
+    shader::vertex_rhw_init(&mut v1);
+    shader::vertex_rhw_init(&mut v2);
+
+    let frustum = camera.get_frustum();
+    let (near, far) = (frustum.near(), frustum.far());
+    let bounds = viewport.pixel_bounds(target.width(), target.height());
+    rasterize_line(
+        &mut Line::new(v1, v2),
+        &|attr, uniforms, texture_storage, context| {
+            shader.call_pixel_shading(attr, uniforms, texture_storage, context)
+        },
+        uniforms,
+        texture_storage,
+        target,
+        |z| depth_range.map(z, near, far),
+        PixelWrite {
+            id: None,
+            blend_mode,
+            bounds,
+            may_discard: shader.may_discard(),
+        },
+    );
+}
+
+/// transform a line(after vertex changing) into screen space and clip it against near plane,
+/// view space z is kept in `position.z`. `None` if the whole line is behind near plane
+pub(crate) fn transform_line(
+    model: &math::Mat4,
+    mut line: [Vertex; 2],
+    camera: &Camera,
+    viewport: &Viewport,
+) -> Option<[Vertex; 2]> {
+    let model_view = *camera.view_mat() * *model;
+    for v in &mut line {
+        v.position = model_view * v.position;
+    }
+
+    // near plane clip, inside is `z <= -near`
+    let near = camera.get_frustum().near();
+    let near_plane = math::Vec4::new(0.0, 0.0, 1.0, near);
+    match (line[0].position.z > -near, line[1].position.z > -near) {
+        (true, true) => return None,
+        (true, false) => {
+            line[0] = crate::scanline::clip_vertex_against_plane(&line[0], &line[1], &near_plane)
+        }
+        (false, true) => {
+            line[1] = crate::scanline::clip_vertex_against_plane(&line[0], &line[1], &near_plane)
+        }
+        (false, false) => {}
+    }
+
+    for v in &mut line {
+        let z = v.position.z;
+        v.position = *camera.get_frustum().get_mat() * v.position;
+        let screen = viewport.transform(&math::Vec2::new(
+            v.position.x / v.position.w,
+            v.position.y / v.position.w,
+        ));
+        v.position = math::Vec4::new(screen.x, screen.y, z, 1.0);
+    }
+
+    Some(line)
+}
+
+pub const DEFAULT_SUBPIXEL_BITS: u32 = 4;
+
+/// call vertex changing, then write world position if `shader` requires it
+pub(crate) fn change_vertex(
+    shader: &Shader,
+    uniforms: &Uniforms,
+    model: &math::Mat4,
+    vertex: &Vertex,
+    texture_storage: &TextureStorage,
+) -> Vertex {
+    let mut vertex = shader.call_vertex_changing(vertex, uniforms, texture_storage);
+    shader.write_world_position(model, &mut vertex);
+    vertex
+}
+
+/// snap screen space coordinate to a fixed point grid with `bits` fractional bits
+pub fn snap_to_subpixel(value: f32, bits: u32) -> f32 {
+    let scale = (1u32 << bits) as f32;
+    (value * scale).round() / scale
+}
+
 pub(crate) fn rasterize_line(
     line: &mut Line,
-    shading: &shader::PixelShading,
+    shading: &dyn Fn(
+        &shader::Attributes,
+        &Uniforms,
+        &TextureStorage,
+        &shader::FragmentContext,
+    ) -> Option<math::Vec4>,
     uniforms: &shader::Uniforms,
     texture_storage: &TextureStorage,
-    color_attachment: &mut ColorAttachment,
-    depth_attachment: &mut DepthAttachment,
+    target: &mut RenderTarget,
+    depth_map: impl Fn(f32) -> f32,
+    write: PixelWrite,
 ) {
     let mut bresenham = Bresenham::new(
         &line.start.position.truncated_to_vec2(),
         &line.end.position.truncated_to_vec2(),
-        &math::Vec2::zero(),
-        &math::Vec2::new(
-            color_attachment.width() as f32 - 1.0,
-            color_attachment.height() as f32 - 1.0,
-        ),
+        &write.bounds.0,
+        &write.bounds.1,
     );
 
     if let Some(iter) = &mut bresenham {
@@ -107,22 +791,32 @@ pub(crate) fn rasterize_line(
 
             let x = x as u32;
             let y = y as u32;
-            if depth_attachment.get(x, y) <= z {
-                let mut attr = vertex.attributes;
-                shader::attributes_foreach(&mut attr, |value| value / rhw);
-                // call pixel shading function to get shading color
-                let color = shading(&attr, uniforms, texture_storage);
-                color_attachment.set(x, y, &color);
-                depth_attachment.set(x, y, z);
+            let depth = depth_map(z);
+            if target.depth_test(x, y, depth) {
+                // no need to shade for depth only target unless shading may discard
+                let color = if target.has_color() || write.may_discard {
+                    let mut attr = vertex.attributes;
+                    shader::attributes_foreach(&mut attr, |value| value / rhw);
+                    // call pixel shading function to get shading color
+                    let context =
+                        shader::FragmentContext::new(x, y, depth, z, rhw, target.get_depth(x, y))
+                            .with_world_position(&attr);
+                    shading(&attr, uniforms, texture_storage, &context)
+                } else {
+                    Some(math::Vec4::zero())
+                };
+                // discarded fragment writes nothing
+                if let Some(color) = color {
+                    target.blend_color(x, y, &color, write.blend_mode);
+                    target.set_depth(x, y, depth);
+                    if let Some(id) = write.id {
+                        target.set_id(x, y, id);
+                    }
+                }
             }
 
             vertex.position += line.step.position;
-            vertex.attributes = shader::interp_attributes(
-                &vertex.attributes,
-                &line.step.attributes,
-                |value1, value2, _| value1 + value2,
-                0.0,
-            );
+            vertex.attributes = shader::attributes_add(&vertex.attributes, &line.step.attributes);
             position = iter.next();
         }
     }
@@ -295,3 +989,1247 @@ impl Iterator for Bresenham {
         Some(result)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::model;
+
+    fn test_camera() -> Camera {
+        Camera::new(1.0, 10.0, 1.0, 45f32.to_radians())
+    }
+
+    fn create_renderer(cpu: bool, w: u32, h: u32, camera: Camera) -> Box<dyn RendererInterface> {
+        if cpu {
+            Box::new(crate::cpu_renderer::Renderer::new(w, h, camera))
+        } else {
+            Box::new(crate::gpu_renderer::Renderer::new(w, h, camera))
+        }
+    }
+
+    /// cpu and gpu renderers, both with camera from `camera`
+    fn renderers_with_camera(
+        w: u32,
+        h: u32,
+        camera: impl Fn() -> Camera,
+    ) -> [Box<dyn RendererInterface>; 2] {
+        [
+            create_renderer(true, w, h, camera()),
+            create_renderer(false, w, h, camera()),
+        ]
+    }
+
+    /// with 65x65 target, maps x, y in [-32, 32] to whole pixels(x + 32)
+    fn pixel_ortho_camera() -> Camera {
+        Camera::new_ortho(-32.0, 32.0, -32.0, 32.0, 1.0, 10.0)
+    }
+
+    /// cpu and gpu renderers with `test_camera`
+    fn renderers(w: u32, h: u32) -> [Box<dyn RendererInterface>; 2] {
+        renderers_with_camera(w, h, test_camera)
+    }
+
+    /// triangle at z = -3 around screen center, about 20 pixels wide with `test_camera` on 64x64 target
+    pub(crate) fn test_triangle() -> [Vertex; 3] {
+        [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, 1.0, -3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()))
+    }
+
+    /// two CCW triangles covering `[x0, x1] x [y0, y1]` at `z`
+    pub(crate) fn quad(x0: f32, x1: f32, y0: f32, y1: f32, z: f32) -> [Vertex; 6] {
+        [
+            math::Vec3::new(x0, y0, z),
+            math::Vec3::new(x1, y0, z),
+            math::Vec3::new(x1, y1, z),
+            math::Vec3::new(x0, y0, z),
+            math::Vec3::new(x1, y1, z),
+            math::Vec3::new(x0, y1, z),
+        ]
+        .map(|p| Vertex::new(p, Default::default()))
+    }
+
+    fn cube_mesh() -> Mesh {
+        let aabb = math::Aabb::new(
+            math::Vec3::new(-0.5, -0.5, -0.5),
+            math::Vec3::new(0.5, 0.5, 0.5),
+        );
+        Mesh {
+            vertices: aabb
+                .corners()
+                .iter()
+                .map(|corner| model::Vertex {
+                    position: *corner,
+                    normal: math::Vec3::zero(),
+                    texcoord: math::Vec2::zero(),
+                    color: math::Vec4::new(1.0, 1.0, 1.0, 1.0),
+                    tangent: math::Vec3::zero(),
+                    bitangent: math::Vec3::zero(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cull_meshes_behind_camera() {
+        let camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+
+        let mut meshes = vec![];
+        let mut models = vec![];
+        for x in -2..=2 {
+            for z in [-10.0, 10.0] {
+                meshes.push(cube_mesh());
+                models.push(math::create_translate(&math::Vec3::new(x as f32, 0.0, z)));
+            }
+        }
+
+        let visibility = cull_meshes(&meshes, &models, &camera);
+        assert_eq!(visibility.len(), meshes.len());
+        for (visible, model) in visibility.iter().zip(models.iter()) {
+            // cubes in front of camera(z < 0) are visible, others are behind camera
+            assert_eq!(*visible, model.get(3, 2) < 0.0);
+        }
+        assert_eq!(visibility.iter().filter(|v| **v).count(), 5);
+    }
+
+    #[test]
+    fn billboard_faces_camera() {
+        let mut camera = Camera::new(1.0, 100.0, 1.0, 30f32.to_radians());
+        camera.move_to(math::Vec3::new(1.0, 2.0, 3.0));
+        camera.set_rotation(math::Vec3::new(0.3, -0.7, 0.0));
+        let center = math::Vec3::new(-2.0, 1.0, -5.0);
+
+        let quad = billboard(center, math::Vec2::new(2.0, 1.0), &camera);
+
+        let to_camera = (*camera.position() - center).normalize();
+        for triangle in quad.chunks(3) {
+            let [p0, p1, p2] = [0, 1, 2].map(|i| triangle[i].position.truncated_to_vec3());
+            let normal = (p1 - p0).cross(&(p2 - p1)).normalize();
+            assert!(normal.dot(&to_camera) > 0.0);
+        }
+    }
+
+    #[test]
+    fn deterministic_output() {
+        let colored_triangle = |color: math::Vec4, offset: f32| {
+            let mut attr = shader::Attributes::default();
+            attr.set_vec4(0, color);
+            [
+                math::Vec3::new(-1.0 + offset, -1.0, -3.0),
+                math::Vec3::new(1.0 + offset, -1.0, -3.0),
+                math::Vec3::new(offset, 1.0, -3.0),
+            ]
+            .map(|p| Vertex::new(p, attr))
+        };
+        let mut vertices = colored_triangle(math::Vec4::new(1.0, 0.0, 0.0, 1.0), -0.3).to_vec();
+        vertices.extend(colored_triangle(math::Vec4::new(0.0, 0.0, 1.0, 1.0), 0.3));
+
+        for cpu in [true, false] {
+            let render = || {
+                let mut renderer = create_renderer(cpu, 64, 64, test_camera());
+                renderer.get_shader().pixel_shading = Box::new(|attr, _, _, _| attr.vec4[0]);
+                renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+                renderer.clear_depth();
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &vertices,
+                    &TextureStorage::default(),
+                );
+                renderer.get_rendered_image().to_vec()
+            };
+
+            let first = render();
+            assert!(first.chunks_exact(3).any(|pixel| pixel == [255, 0, 0]));
+            assert!(first.chunks_exact(3).any(|pixel| pixel == [0, 0, 255]));
+            for _ in 0..5 {
+                assert!(render() == first);
+            }
+        }
+    }
+
+    #[test]
+    fn premultiplied_model_view() {
+        let mut attr = shader::Attributes::default();
+        attr.set_vec4(0, math::Vec4::new(0.2, 0.8, 0.4, 1.0));
+        // the second triangle crosses near plane
+        let vertices = [
+            math::Vec3::new(-1.5, -1.0, 0.0),
+            math::Vec3::new(0.5, -1.0, 0.0),
+            math::Vec3::new(-0.5, 1.0, 0.0),
+            math::Vec3::new(-2.0, -0.5, 1.0),
+            math::Vec3::new(0.0, -0.5, 4.5),
+            math::Vec3::new(-1.0, 0.5, -1.0),
+        ]
+        .map(|p| Vertex::new(p, attr));
+        let model = math::create_translate(&math::Vec3::new(1.0, 0.0, -4.0));
+        let transformed = vertices.map(|v| Vertex {
+            position: model * v.position,
+            ..v
+        });
+
+        for cpu in [true, false] {
+            let render = |model: &math::Mat4, vertices: &[Vertex]| {
+                let mut renderer = create_renderer(cpu, 64, 64, test_camera());
+                renderer.get_shader().pixel_shading = Box::new(|attr, _, _, _| attr.vec4[0]);
+                renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+                renderer.clear_depth();
+                renderer.draw_triangle(model, vertices, &TextureStorage::default());
+                renderer.get_rendered_image().to_vec()
+            };
+
+            let premultiplied = render(&model, &vertices);
+            assert!(premultiplied
+                .chunks_exact(3)
+                .any(|pixel| pixel != [0, 0, 0]));
+            assert!(premultiplied == render(&math::Mat4::identity(), &transformed));
+        }
+    }
+
+    #[test]
+    fn alpha_blend_over_background() {
+        let renderers = renderers(64, 64);
+        let quad = quad(-2.0, 2.0, -2.0, 2.0, -3.0);
+
+        for mut renderer in renderers {
+            assert_eq!(renderer.get_blend_mode(), BlendMode::None);
+            renderer.set_blend_mode(BlendMode::AlphaBlend);
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 0.0, 0.0, 0.5));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 1.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(&math::Mat4::identity(), &quad, &TextureStorage::default());
+
+            // away from the shared edge of two triangles
+            let color = renderer.get_target().color.as_ref().unwrap().get(20, 20);
+            assert!((color.x - 0.5).abs() < 0.01);
+            assert!(color.y.abs() < 0.01);
+            assert!((color.z - 0.5).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn interpolated_world_position() {
+        let renderers = renderers(64, 64);
+        // world positions are on plane z = -3 + 0.5x after model transform
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -1.0));
+        let vertices = [
+            math::Vec3::new(-1.0, -1.0, -2.5),
+            math::Vec3::new(1.0, -1.0, -1.5),
+            math::Vec3::new(0.0, 1.0, -2.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            let positions = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let captured = positions.clone();
+            let shader = renderer.get_shader();
+            shader.world_position = true;
+            shader.pixel_shading = Box::new(move |attr, _, _, context| {
+                // no attribute slot is taken
+                assert_eq!(attr.vec3, [math::Vec3::zero(); 4]);
+                captured.borrow_mut().push(context.world_position);
+                math::Vec4::new(1.0, 1.0, 1.0, 1.0)
+            });
+            renderer.clear_depth();
+            renderer.draw_triangle(&model, &vertices, &TextureStorage::default());
+
+            let positions = positions.borrow();
+            assert!(positions.len() > 100);
+            for p in positions.iter() {
+                assert!((p.z - (-3.0 + 0.5 * p.x)).abs() < 1e-3);
+                assert!(p.x.abs() <= 1.0 + 1e-3 && p.y.abs() <= 1.0 + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn jittered_edge_average() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 3), 2.0 / 3.0);
+
+        let renderers = renderers(64, 64);
+        // left part of the screen, steep edge goes through the center
+        let vertices = [
+            math::Vec3::new(-8.0, -8.0, -3.0),
+            math::Vec3::new(3.0, -8.0, -3.0),
+            math::Vec3::new(-3.0, 8.0, -3.0),
+            math::Vec3::new(-8.0, -8.0, -3.0),
+            math::Vec3::new(-3.0, 8.0, -3.0),
+            math::Vec3::new(-8.0, 8.0, -3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+            let accumulated = render_jittered(renderer.as_mut(), 4, |renderer| {
+                renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+                renderer.clear_depth();
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &vertices,
+                    &TextureStorage::default(),
+                );
+            });
+            assert_eq!(
+                renderer.get_camera().get_frustum().jitter(),
+                math::Vec2::zero()
+            );
+
+            assert_eq!(accumulated.get(4, 32).x, 1.0);
+            assert_eq!(accumulated.get(60, 32).x, 0.0);
+            let partial = (0..64)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .filter(|&(x, y)| {
+                    let value = accumulated.get(x, y).x;
+                    value > 0.1 && value < 0.9
+                })
+                .count();
+            assert!(partial > 10);
+        }
+    }
+
+    #[test]
+    fn one_pixel_target() {
+        let renderers = renderers(1, 1);
+        let vertices = test_triangle();
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 0.0, 0.0, 1.0));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            assert_eq!(color.get(0, 0), math::Vec4::new(1.0, 0.0, 0.0, 1.0));
+            let depth = renderer.get_depth_at(0, 0).unwrap();
+            assert!(depth.is_finite() && depth < f32::MAX);
+        }
+
+        // empty viewport draws nothing
+        let bounds = Viewport {
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+        }
+        .pixel_bounds(1, 1);
+        assert!(bounds.0.x > bounds.1.x && bounds.0.y > bounds.1.y);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 3 is out of 3 vertices")]
+    fn draw_indexed_out_of_range() {
+        let vertices = test_triangle();
+        let mut renderer = create_renderer(true, 64, 64, test_camera());
+        renderer.draw_indexed(
+            &math::Mat4::identity(),
+            &vertices,
+            &[0, 1, 3],
+            &TextureStorage::default(),
+        );
+    }
+
+    #[test]
+    fn draw_indexed_same_as_triangles() {
+        let (vertices, indices) = model::deduplicate(&model::primitives::plane(2.0, 2.0, 2));
+        let vertices: Vec<Vertex> = vertices
+            .iter()
+            .map(|v| {
+                let mut attr = shader::Attributes::default();
+                attr.set_vec2(0, v.texcoord);
+                Vertex::new(v.position, attr)
+            })
+            .collect();
+        let expanded: Vec<Vertex> = indices.iter().map(|i| vertices[*i as usize]).collect();
+        // plane faces +Y, tilt it to face the camera
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -3.0))
+            * math::create_eular_rotate_x(90f32.to_radians());
+
+        for (mut indexed, mut expanded_renderer) in
+            renderers(64, 64).into_iter().zip(renderers(64, 64))
+        {
+            for renderer in [&mut indexed, &mut expanded_renderer] {
+                renderer.get_shader().pixel_shading = Box::new(|attr, _, _, _| {
+                    math::Vec4::new(attr.vec2[0].x, attr.vec2[0].y, 1.0, 1.0)
+                });
+                renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+                renderer.clear_depth();
+            }
+            indexed.draw_indexed(&model, &vertices, &indices, &TextureStorage::default());
+            expanded_renderer.draw_triangle(&model, &expanded, &TextureStorage::default());
+
+            let image = indexed.get_rendered_image();
+            assert_eq!(image, expanded_renderer.get_rendered_image());
+            assert!(image.chunks(3).filter(|c| c[2] == 255).count() > 100);
+        }
+    }
+
+    #[test]
+    fn flat_attribute_constant() {
+        let renderers = renderers(64, 64);
+        // slot 0 is flat, slot 1 is perspective correct
+        let vertices = [
+            (math::Vec3::new(-1.0, -1.0, -2.0), 0.5, 0.0),
+            (math::Vec3::new(1.0, -1.0, -4.0), 0.0, 0.5),
+            (math::Vec3::new(0.0, 1.0, -3.0), 1.0, 1.0),
+        ]
+        .map(|(p, flat, smooth)| {
+            let mut attr = shader::Attributes::default();
+            attr.set_float(0, flat);
+            attr.set_float(1, smooth);
+            Vertex::new(p, attr)
+        });
+
+        for mut renderer in renderers {
+            let shader = renderer.get_shader();
+            shader.interp_modes.float[0] = shader::InterpMode::Flat;
+            shader.pixel_shading =
+                Box::new(|attr, _, _, _| math::Vec4::new(attr.float[0], attr.float[1], 1.0, 1.0));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            let covered: Vec<math::Vec4> = (0..64)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .map(|(x, y)| color.get(x, y))
+                .filter(|c| c.z > 0.5)
+                .collect();
+            assert!(covered.len() > 100);
+            // value of the first vertex everywhere
+            assert!(covered.iter().all(|c| (c.x - 0.5).abs() < 0.01));
+            let (min, max) = covered.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+                (min.min(c.y), max.max(c.y))
+            });
+            assert!(max - min > 0.5);
+        }
+    }
+
+    #[test]
+    fn half_width_viewport() {
+        let renderers = renderers(64, 64);
+        // much larger than the view
+        let vertices = [
+            math::Vec3::new(-20.0, -20.0, -3.0),
+            math::Vec3::new(20.0, -20.0, -3.0),
+            math::Vec3::new(0.0, 20.0, -3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let clear_color = math::Vec4::new(0.0, 0.0, 1.0, 1.0);
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+        for mut renderer in renderers {
+            assert_eq!(
+                *renderer.get_viewport(),
+                Viewport {
+                    x: 0,
+                    y: 0,
+                    w: 64,
+                    h: 64
+                }
+            );
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, _| white);
+
+            for viewport_x in [0, 32] {
+                renderer.set_viewport(Viewport {
+                    x: viewport_x,
+                    y: 0,
+                    w: 32,
+                    h: 64,
+                });
+                renderer.clear(&clear_color);
+                renderer.clear_depth();
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &vertices,
+                    &TextureStorage::default(),
+                );
+
+                let color = renderer.get_target().color.as_ref().unwrap();
+                for y in 0..64 {
+                    for x in 0..64 {
+                        let inside = (x as i32 - viewport_x) / 32 == 0 && x as i32 >= viewport_x;
+                        let expected = if inside { white } else { clear_color };
+                        assert_eq!(color.get(x, y), expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn supersample_resolve_edge() {
+        let renderers = renderers(64, 64);
+        // long edges are diagonal
+        let vertices = test_triangle();
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, _| white);
+            let gray_count = |renderer: &mut Box<dyn RendererInterface>| {
+                renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+                renderer.clear_depth();
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &vertices,
+                    &TextureStorage::default(),
+                );
+                let image = renderer.get_rendered_image();
+                assert_eq!(image.len(), 64 * 64 * 3);
+                image.iter().filter(|c| **c != 0 && **c != 255).count()
+            };
+
+            assert_eq!(gray_count(&mut renderer), 0);
+
+            renderer.set_supersample_scale(2);
+            assert_eq!(renderer.get_supersample_scale(), 2);
+            assert_eq!(renderer.get_canva_width(), 64);
+            assert_eq!(renderer.get_canva_height(), 64);
+            assert_eq!(renderer.get_target().width(), 128);
+            assert!(gray_count(&mut renderer) > 0);
+        }
+    }
+
+    #[test]
+    fn read_back_color_and_depth() {
+        let renderers = renderers(64, 64);
+        let vertices = test_triangle();
+
+        for mut renderer in renderers {
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 0.0, 1.0, 1.0));
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            assert_eq!(color.get(32, 32), math::Vec4::new(1.0, 0.0, 1.0, 1.0));
+            assert_eq!(color.get(2, 2), math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+
+            let frustum = renderer.get_camera().get_frustum();
+            let (near, far) = (frustum.near(), frustum.far());
+            let expect = renderer.get_depth_range().map(-3.0, near, far);
+            assert!((renderer.get_depth_at(32, 32).unwrap() - expect).abs() < 1e-4);
+            assert_eq!(renderer.get_depth_at(2, 2), Some(f32::MAX));
+            assert_eq!(renderer.get_depth_at(64, 0), None);
+        }
+    }
+
+    #[test]
+    fn ndc_z_range_keeps_rendered_depth() {
+        use crate::camera::{Frustum, NdcZRange};
+
+        let vertices = test_triangle();
+
+        for z_range in [NdcZRange::NegOneToOne, NdcZRange::ZeroToOne] {
+            let camera = || {
+                Camera::from_frustum(Frustum::new_with_z_range(
+                    1.0,
+                    10.0,
+                    1.0,
+                    45f32.to_radians(),
+                    z_range,
+                ))
+            };
+            for mut renderer in renderers_with_camera(64, 64, camera) {
+                renderer.clear_depth();
+                renderer.draw_triangle(
+                    &math::Mat4::identity(),
+                    &vertices,
+                    &TextureStorage::default(),
+                );
+
+                let expect = renderer.get_depth_range().map(-3.0, 1.0, 10.0);
+                assert!((renderer.get_depth_at(32, 32).unwrap() - expect).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn rendered_depth_png16_round_trip() {
+        let vertices = test_triangle();
+
+        for (i, mut renderer) in renderers(64, 64).into_iter().enumerate() {
+            renderer.clear_depth();
+            renderer.set_depth_range(0.25, 0.75);
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let range = renderer.get_depth_range();
+            let depth = renderer.get_target().depth.as_ref().unwrap();
+            let path = std::env::temp_dir().join(format!("rs_cpurenderer_rendered_depth{}.png", i));
+            depth.save_png16(&path, range).unwrap();
+            let loaded = DepthAttachment::load_png16(&path, range).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.get(2, 2), f32::MAX);
+            let expect = range.map(-3.0, 1.0, 10.0);
+            assert!((loaded.get(32, 32) - expect).abs() <= 1.0 / u16::MAX as f32);
+            for (expect, value) in depth.data().iter().zip(loaded.data()) {
+                assert!((expect - value).abs() <= 1.0 / u16::MAX as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn render_over_transparent_clear() {
+        let vertices = test_triangle();
+        let half_red = math::Vec4::new(1.0, 0.0, 0.0, 0.5);
+
+        for mut renderer in renderers(64, 64) {
+            renderer.bind_target(RenderTarget::rgba(64, 64));
+            renderer.clear(&math::Vec4::zero());
+            renderer.clear_depth();
+            renderer.set_blend_mode(BlendMode::AlphaBlend);
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, _| half_red);
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let rgba = renderer.get_target().rgba.as_ref().unwrap();
+            assert_eq!(rgba.get(2, 2), math::Vec4::zero());
+            let color = rgba.get(32, 32);
+            let expect = math::Vec4::new(0.5, 0.0, 0.0, 0.5);
+            assert!((color - expect).length() <= 1.0 / 255.0);
+        }
+    }
+
+    #[test]
+    fn read_back_out_of_canvas() {
+        for mut renderer in renderers(64, 64) {
+            renderer.set_supersample_scale(2);
+            renderer.enable_id_buffer();
+            renderer.clear_depth();
+            renderer.clear_id();
+
+            assert_eq!(renderer.get_depth_at(63, 63), Some(f32::MAX));
+            assert_eq!(renderer.get_depth_at(64, 0), None);
+            assert_eq!(renderer.get_depth_at(u32::MAX, u32::MAX), None);
+            assert_eq!(renderer.get_id_at(64, 0), None);
+            assert_eq!(renderer.get_id_at(u32::MAX, u32::MAX), None);
+        }
+    }
+
+    #[test]
+    fn id_buffer_per_triangle() {
+        let renderers = renderers(64, 64);
+        let triangle = |offset_x: f32, z: f32| {
+            [
+                math::Vec3::new(offset_x - 1.0, -1.0, z),
+                math::Vec3::new(offset_x + 1.0, -1.0, z),
+                math::Vec3::new(offset_x, 1.0, z),
+            ]
+            .map(|p| Vertex::new(p, Default::default()))
+        };
+        let left = triangle(-1.0, -3.0);
+        // right one and an occluded one behind the left one
+        let others: Vec<Vertex> = [triangle(1.0, -3.0), triangle(-1.0, -5.0)]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+
+        for mut renderer in renderers {
+            assert_eq!(renderer.get_id_at(16, 40), None);
+            renderer.enable_id_buffer();
+            renderer.clear_depth();
+            renderer.clear_id();
+            let model = math::Mat4::identity();
+            renderer.draw_triangle(&model, &left, &TextureStorage::default());
+            renderer.draw_triangle(&model, &others, &TextureStorage::default());
+
+            assert_eq!(renderer.get_id_at(16, 40), Some(0));
+            assert_eq!(renderer.get_id_at(48, 40), Some(1));
+            assert_eq!(renderer.get_id_at(2, 2), None);
+
+            renderer.clear_id();
+            assert_eq!(renderer.get_id_at(16, 40), None);
+            renderer.disable_id_buffer();
+            assert!(renderer.get_target().id.is_none());
+        }
+    }
+
+    #[test]
+    fn triangle_callback_count() {
+        let renderers = renderers(64, 64);
+        let front = [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, 1.0, -3.0),
+        ];
+        let back = [front[0], front[2], front[1]];
+        let vertices: Vec<Vertex> = [front, back, front, front]
+            .iter()
+            .flatten()
+            .map(|p| Vertex::new(*p, Default::default()))
+            .collect();
+
+        for mut renderer in renderers {
+            let infos = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let recorder = infos.clone();
+            renderer.set_triangle_callback(Box::new(move |info| recorder.borrow_mut().push(*info)));
+            renderer.set_front_face(FrontFace::CCW);
+            renderer.set_face_cull(FaceCull::Back);
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            // one of four triangles is culled
+            let infos = infos.borrow();
+            assert_eq!(infos.len(), 3);
+            assert_eq!(
+                infos.iter().map(|info| info.index).collect::<Vec<_>>(),
+                vec![0, 2, 3]
+            );
+            assert!(infos.iter().all(|info| info.area > 0.0 && !info.clipped));
+
+            renderer.reset_triangle_callback();
+        }
+    }
+
+    #[test]
+    fn triangle_count_from_vertices() {
+        // two triangles and two trailing vertices which don't form a triangle
+        let vertices: Vec<Vertex> = [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, 1.0, -3.0),
+            math::Vec3::new(-1.0, -1.0, -4.0),
+            math::Vec3::new(1.0, -1.0, -4.0),
+            math::Vec3::new(0.0, 1.0, -4.0),
+            math::Vec3::new(-1.0, -1.0, -5.0),
+            math::Vec3::new(1.0, -1.0, -5.0),
+        ]
+        .iter()
+        .map(|p| Vertex::new(*p, Default::default()))
+        .collect();
+
+        for cpu in [true, false] {
+            let mut renderer = create_renderer(cpu, 64, 64, test_camera());
+            let count = std::rc::Rc::new(std::cell::Cell::new(0));
+            let counter = count.clone();
+            renderer.set_triangle_callback(Box::new(move |_| counter.set(counter.get() + 1)));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+            assert_eq!(count.get(), vertices.len() / 3);
+        }
+    }
+
+    #[test]
+    fn subpixel_snapping_stable_coverage() {
+        assert_eq!(snap_to_subpixel(10.03, 4), 10.0);
+        assert_eq!(snap_to_subpixel(10.04, 4), 10.0625);
+
+        let renderers = renderers(64, 64);
+        // right edge is vertical and rotates slowly around screen center
+        let vertices = [
+            math::Vec3::new(0.0, -2.0, 0.0),
+            math::Vec3::new(0.0, 2.0, 0.0),
+            math::Vec3::new(-5.0, 0.0, 0.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+        let rightmost = |renderer: &dyn RendererInterface, y: u32| {
+            let depth = renderer.get_target().depth.as_ref().unwrap();
+            (0..64).filter(|x| depth.get(*x, y) < f32::MAX).max()
+        };
+
+        for mut renderer in renderers {
+            assert_eq!(renderer.get_subpixel_bits(), Some(DEFAULT_SUBPIXEL_BITS));
+            let mut upper = vec![];
+            let mut lower = vec![];
+            for step in 0..60 {
+                let angle = (step as f32 * 0.1).to_radians();
+                let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -3.0))
+                    * math::create_eular_rotate_z(angle);
+                renderer.clear_depth();
+                renderer.draw_triangle(&model, &vertices, &TextureStorage::default());
+                upper.push(rightmost(renderer.as_ref(), 20).unwrap());
+                lower.push(rightmost(renderer.as_ref(), 44).unwrap());
+            }
+
+            // edge above center moves left and edge below center moves right, never back
+            assert!(upper.windows(2).all(|w| w[1] <= w[0]));
+            assert!(lower.windows(2).all(|w| w[1] >= w[0]));
+            assert!(upper.first() != upper.last() && lower.first() != lower.last());
+        }
+    }
+
+    #[test]
+    fn draw_line_clipped_by_near_plane() {
+        let renderers =
+            renderers_with_camera(64, 64, || Camera::new(1.0, 100.0, 1.0, 45f32.to_radians()));
+        for mut renderer in renderers {
+            renderer.clear_depth();
+            // from x = 0.1 in NDC to behind camera, crosses near plane at x = -0.9 in NDC,
+            // without clipping the end point behind camera would be projected to x = 0.6
+            renderer.draw_line_3d(
+                &math::Mat4::identity(),
+                Vertex::new(math::Vec3::new(0.5, 0.0, -5.0), Default::default()),
+                Vertex::new(math::Vec3::new(-3.0, 0.0, 5.0), Default::default()),
+                &TextureStorage::default(),
+            );
+
+            let depth = renderer.get_target().depth.as_ref().unwrap();
+            let drawn: Vec<u32> = (0..64)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .filter(|(x, y)| depth.get(*x, *y) < f32::MAX)
+                .map(|(x, _)| x)
+                .collect();
+            assert!(drawn.iter().all(|x| *x <= 35));
+            assert!(drawn.iter().any(|x| *x < 10));
+
+            // totally behind near plane
+            renderer.clear_depth();
+            renderer.draw_line_3d(
+                &math::Mat4::identity(),
+                Vertex::new(math::Vec3::new(0.5, 0.0, -0.5), Default::default()),
+                Vertex::new(math::Vec3::new(-3.0, 0.0, 5.0), Default::default()),
+                &TextureStorage::default(),
+            );
+            let depth = renderer.get_target().depth.as_ref().unwrap();
+            assert!((0..64).all(|y| (0..64).all(|x| depth.get(x, y) == f32::MAX)));
+        }
+    }
+
+    #[test]
+    fn sample_clamp_out_of_range() {
+        let mut image = image::RgbaImage::new(3, 1);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(2, 0, image::Rgba([0, 0, 255, 255]));
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::ImageRgba8(image), "columns");
+        let texture = storage.get_by_id(id).unwrap();
+
+        assert_eq!(
+            texture_sample(texture, &math::Vec2::new(1.2, 0.0)),
+            texture.get(2, 0)
+        );
+        assert_eq!(
+            texture_sample(texture, &math::Vec2::new(-0.3, 2.0)),
+            texture.get(0, 0)
+        );
+    }
+
+    #[test]
+    fn bilinear_sample_checkerboard() {
+        let mut image = image::RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::ImageRgba8(image), "checkerboard");
+        let texture = storage.get_by_id(id).unwrap();
+
+        let center = texture_sample_bilinear(texture, &math::Vec2::new(0.5, 0.5));
+        assert!((center - math::Vec4::new(0.5, 0.5, 0.5, 1.0)).length() < 1e-5);
+
+        // corners match nearest sampling, including texcoord 1.0
+        for texcoord in [math::Vec2::new(0.0, 0.0), math::Vec2::new(1.0, 1.0)] {
+            assert_eq!(
+                texture_sample_bilinear(texture, &texcoord),
+                texture_sample(texture, &texcoord)
+            );
+        }
+    }
+
+    #[test]
+    fn validate_and_repair_triangles() {
+        let vertex = |x, y| Vertex::new(math::Vec3::new(x, y, -1.0), Default::default());
+        let good = [vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0)];
+        let degenerate = [vertex(0.0, 0.0), vertex(1.0, 1.0), vertex(2.0, 2.0)];
+        let nan = [vertex(0.0, 0.0), vertex(f32::NAN, 0.0), vertex(0.0, 1.0)];
+
+        assert_eq!(validate_triangles(&good), Ok(()));
+        assert_eq!(
+            validate_triangles(&good[..2]),
+            Err(ValidationError::BadCount(2))
+        );
+        assert_eq!(
+            validate_triangles(&[good, degenerate].concat()),
+            Err(ValidationError::Degenerate(1))
+        );
+        assert_eq!(
+            validate_triangles(&[nan, degenerate].concat()),
+            Err(ValidationError::NaN(0))
+        );
+
+        let repaired =
+            repair_triangles(&[&good[..], &nan, &degenerate, &good, &good[..1]].concat());
+        assert_eq!(repaired.len(), 6);
+        assert_eq!(validate_triangles(&repaired), Ok(()));
+    }
+
+    #[test]
+    fn back_face_framework_color() {
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let front = test_triangle();
+        let back = [front[0], front[2], front[1]];
+
+        let draw = |renderer: &mut Box<dyn RendererInterface>, vertices: &[Vertex]| {
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                vertices,
+                &TextureStorage::default(),
+            );
+            renderer
+                .get_rendered_image()
+                .chunks_exact(3)
+                .filter(|pixel| *pixel != [0, 0, 0])
+                .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect::<Vec<_>>()
+        };
+
+        for mut renderer in renderers(64, 64) {
+            renderer.set_front_face(FrontFace::CCW);
+            renderer.set_back_face_framework_color(Some(red));
+            renderer.enable_framework();
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, _| white);
+
+            let front_pixels = draw(&mut renderer, &front);
+            assert!(!front_pixels.is_empty());
+            assert!(front_pixels.iter().all(|pixel| *pixel == [255, 255, 255]));
+
+            let back_pixels = draw(&mut renderer, &back);
+            assert!(!back_pixels.is_empty());
+            assert!(back_pixels.iter().all(|pixel| *pixel == [255, 0, 0]));
+        }
+    }
+
+    #[test]
+    fn discard_left_half() {
+        let renderers = renderers(64, 64);
+        let vertices: Vec<Vertex> = model::primitives::plane(2.0, 2.0, 1)
+            .vertices
+            .iter()
+            .map(|v| {
+                let mut attr = shader::Attributes::default();
+                attr.set_vec2(0, v.texcoord);
+                Vertex::new(v.position, attr)
+            })
+            .collect();
+        // plane faces +Y, tilt it to face the camera
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -3.0))
+            * math::create_eular_rotate_x(90f32.to_radians());
+        let clear_color = math::Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let white = math::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading_discard = Some(Box::new(move |attr, _, _, _| {
+                if attr.vec2[0].x < 0.5 {
+                    None
+                } else {
+                    Some(white)
+                }
+            }));
+            renderer.clear(&clear_color);
+            renderer.clear_depth();
+            renderer.draw_triangle(&model, &vertices, &TextureStorage::default());
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            assert_eq!(color.get(28, 32), clear_color);
+            assert_eq!(renderer.get_depth_at(28, 32), Some(f32::MAX));
+            assert_eq!(color.get(36, 32), white);
+            assert!(renderer.get_depth_at(36, 32).unwrap() < f32::MAX);
+        }
+    }
+
+    #[test]
+    fn discard_line_into_depth_only_target() {
+        let renderers = renderers(64, 64);
+        let vertex = |x: f32, u: f32| {
+            let mut attr = shader::Attributes::default();
+            attr.set_float(0, u);
+            Vertex::new(math::Vec3::new(x, 0.0, -3.0), attr)
+        };
+
+        for mut renderer in renderers {
+            renderer.bind_target(RenderTarget::depth_only(64, 64));
+            renderer.get_shader().pixel_shading_discard = Some(Box::new(|attr, _, _, _| {
+                if attr.float[0] < 0.5 {
+                    None
+                } else {
+                    Some(math::Vec4::zero())
+                }
+            }));
+            renderer.clear_depth();
+            renderer.draw_line_3d(
+                &math::Mat4::identity(),
+                vertex(-1.0, 0.0),
+                vertex(1.0, 1.0),
+                &TextureStorage::default(),
+            );
+
+            let depth = renderer.get_target().depth.as_ref().unwrap();
+            let drawn: Vec<u32> = (0..64)
+                .filter(|&x| (0..64).any(|y| depth.get(x, y) < f32::MAX))
+                .collect();
+            // only right half of line(about x in [21, 41]) is kept
+            assert!(drawn.iter().all(|&x| x >= 30));
+            assert!(drawn.contains(&40));
+        }
+    }
+
+    #[test]
+    fn frag_coord_gradient() {
+        let renderers = renderers(64, 64);
+        // large plane covers whole screen
+        let vertices: Vec<Vertex> = model::primitives::plane(20.0, 20.0, 1)
+            .vertices
+            .iter()
+            .map(|v| Vertex::new(v.position, Default::default()))
+            .collect();
+        let model = math::create_translate(&math::Vec3::new(0.0, 0.0, -3.0))
+            * math::create_eular_rotate_x(90f32.to_radians());
+
+        for mut renderer in renderers {
+            let width = renderer.get_canva_width() as f32;
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, context| {
+                assert!((context.depth + 3.0).abs() < 1e-3);
+                math::Vec4::new(context.frag_coord.x / width, 0.0, 0.0, 1.0)
+            });
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(&model, &vertices, &TextureStorage::default());
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            for y in [0, 32, 63] {
+                for x in 0..64 {
+                    assert!((color.get(x, y).x - x as f32 / width).abs() <= 1.0 / 255.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shared_edge_drawn_once() {
+        // edges of the rectangle go through pixel centers
+        let renderers = renderers_with_camera(65, 65, pixel_ortho_camera);
+        let [a, b, c, d] = [
+            math::Vec3::new(-16.0, -8.0, -3.0),
+            math::Vec3::new(16.0, -8.0, -3.0),
+            math::Vec3::new(16.0, 8.0, -3.0),
+            math::Vec3::new(-16.0, 8.0, -3.0),
+        ];
+        let vertices = [a, b, c, a, c, d].map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            renderer.set_blend_mode(BlendMode::AlphaBlend);
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 0.5));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let image = renderer.get_rendered_image();
+            // pixel drawn twice would be 0.75 gray
+            assert!(image.iter().all(|&c| c == 0 || (127..=128).contains(&c)));
+            assert_eq!(image.chunks(3).filter(|c| c[0] != 0).count(), 32 * 16);
+        }
+    }
+
+    #[test]
+    fn clip_triangle_by_far_plane() {
+        let renderers = renderers(64, 64);
+        // top vertex is beyond far plane
+        let vertices = [
+            math::Vec3::new(-2.0, -2.0, -4.0),
+            math::Vec3::new(2.0, -2.0, -4.0),
+            math::Vec3::new(0.0, 4.0, -16.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            let depths = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let captured = depths.clone();
+            renderer.get_shader().pixel_shading = Box::new(move |_, _, _, context| {
+                captured.borrow_mut().push(context.depth);
+                math::Vec4::new(1.0, 1.0, 1.0, 1.0)
+            });
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let depths = depths.borrow();
+            assert!(!depths.is_empty());
+            assert!(depths.iter().all(|&z| z >= -10.0 - 1e-3));
+            // drawn until far plane rather than dropped
+            assert!(depths.iter().any(|&z| z < -9.5));
+        }
+    }
+
+    #[test]
+    fn skip_triangle_beyond_far_plane() {
+        let renderers = renderers(64, 64);
+        let vertices = [
+            math::Vec3::new(-1.0, -1.0, -20.0),
+            math::Vec3::new(1.0, -1.0, -20.0),
+            math::Vec3::new(0.0, 1.0, -20.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+            assert!(renderer.get_rendered_image().iter().all(|&c| c == 0));
+        }
+    }
+
+    #[test]
+    fn skip_nan_triangle() {
+        let renderers = renderers(64, 64);
+        let vertices = [
+            math::Vec3::new(-1.0, -1.0, -3.0),
+            math::Vec3::new(1.0, -1.0, -3.0),
+            math::Vec3::new(0.0, f32::NAN, -3.0),
+        ]
+        .map(|p| Vertex::new(p, Default::default()));
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(1.0, 1.0, 1.0, 1.0));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+            assert!(renderer.get_rendered_image().iter().all(|&c| c == 0));
+        }
+    }
+
+    #[test]
+    fn ortho_linear_interpolation() {
+        let renderers = renderers_with_camera(65, 65, pixel_ortho_camera);
+        // u and view z are both linear in x, far side is deeper
+        let vertex = |x: f32, y: f32, z: f32, u: f32| {
+            let mut attr = shader::Attributes::default();
+            attr.set_float(0, u);
+            Vertex::new(math::Vec3::new(x, y, z), attr)
+        };
+        let vertices = [
+            vertex(-16.0, -8.0, -2.0, 0.0),
+            vertex(16.0, -8.0, -8.0, 1.0),
+            vertex(-16.0, 8.0, -2.0, 0.0),
+        ];
+
+        for mut renderer in renderers {
+            renderer.get_shader().pixel_shading =
+                Box::new(|attr, _, _, _| math::Vec4::new(attr.float[0], 0.0, 0.0, 1.0));
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &vertices,
+                &TextureStorage::default(),
+            );
+
+            let depth_range = renderer.get_depth_range();
+            let color = renderer.get_target().color.as_ref().unwrap();
+            let mut covered = 0;
+            for y in 0..65 {
+                for x in 0..65 {
+                    let depth = renderer.get_depth_at(x, y).unwrap();
+                    if depth == f32::MAX {
+                        continue;
+                    }
+                    covered += 1;
+                    let u = (x as f32 - 16.0) / 32.0;
+                    assert!((color.get(x, y).x - u).abs() <= 1.0 / 255.0 + 1e-4);
+                    let expect = depth_range.map(-2.0 - 6.0 * u, 1.0, 10.0);
+                    assert!((depth - expect).abs() < 1e-4);
+                }
+            }
+            assert!(covered > 100);
+        }
+    }
+
+    #[test]
+    fn soft_particle_over_quad() {
+        let background = quad(-1.0, 1.0, -1.0, 1.0, -3.0);
+        // left particle is far in front of background, right one almost touches it
+        let mut particles = quad(-0.5, -0.1, -0.3, 0.3, -2.0).to_vec();
+        particles.extend(quad(0.1, 0.5, -0.3, 0.3, -2.95));
+
+        for mut renderer in renderers(64, 64) {
+            renderer.clear(&math::Vec4::new(0.0, 0.0, 0.0, 1.0));
+            renderer.clear_depth();
+            renderer.get_shader().pixel_shading =
+                Box::new(|_, _, _, _| math::Vec4::new(0.0, 0.0, 1.0, 1.0));
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &background,
+                &TextureStorage::default(),
+            );
+
+            renderer.set_blend_mode(BlendMode::AlphaBlend);
+            renderer.get_shader().pixel_shading = Box::new(|_, _, _, context| {
+                let alpha =
+                    shader::soft_particle_factor(context.frag_coord.z, context.scene_depth, 0.05);
+                math::Vec4::new(1.0, 0.0, 0.0, alpha)
+            });
+            renderer.draw_triangle(
+                &math::Mat4::identity(),
+                &particles,
+                &TextureStorage::default(),
+            );
+
+            let color = renderer.get_target().color.as_ref().unwrap();
+            let left = color.get(27, 32);
+            assert!(left.x > 0.99 && left.z < 0.01);
+            // depth differs by (3.0 - 2.95) / 9, which is 1/9 of fade range
+            let right = color.get(35, 32);
+            let alpha = 0.05 / 9.0 / 0.05;
+            assert!((right.x - alpha).abs() < 0.02);
+            assert!((right.z - (1.0 - alpha)).abs() < 0.02);
+        }
+    }
+}
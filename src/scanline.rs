@@ -18,7 +18,8 @@ pub struct Trapezoid {
 impl Trapezoid {
     pub fn from_triangle(vertices: &[Vertex; 3]) -> [Option<Self>; 2] {
         let mut vertices = *vertices;
-        vertices.sort_by(|a, b| a.position.y.partial_cmp(&b.position.y).unwrap());
+        // total order, NaN mustn't panic here
+        vertices.sort_by(|a, b| a.position.y.total_cmp(&b.position.y));
 
         if (vertices[0].position.x == vertices[1].position.x
             && vertices[0].position.x == vertices[2].position.x)
@@ -148,8 +149,7 @@ impl Scanline {
 
         let vertex_left = lerp_vertex(&trap.left.v1, &trap.left.v2, t1);
         let vertex_right = lerp_vertex(&trap.right.v1, &trap.right.v2, t2);
-        let width = vertex_right.position.x - vertex_left.position.x;
-        let rh_width = 1.0 / width;
+        let rh_width = 1.0 / (vertex_right.position.x - vertex_left.position.x);
 
         let position_step = (vertex_right.position - vertex_left.position) * rh_width;
         let attribute_step = interp_attributes(
@@ -159,8 +159,20 @@ impl Scanline {
             rh_width,
         );
 
+        // cover pixels in [ceil(left), ceil(right)), the same rule as top and bottom of trapezoid,
+        // so covered pixels only depend on edge positions
+        let start_x = vertex_left.position.x.ceil();
+        let width = vertex_right.position.x.ceil() - start_x;
+        let offset = start_x - vertex_left.position.x;
+        let mut attribute_offset = attribute_step;
+        attributes_foreach(&mut attribute_offset, |value| value * offset);
+        let vertex = Vertex {
+            position: vertex_left.position + position_step * offset,
+            attributes: attributes_add(&vertex_left.attributes, &attribute_offset),
+        };
+
         Scanline {
-            vertex: vertex_left,
+            vertex,
             step: Vertex {
                 position: position_step,
                 attributes: attribute_step,
@@ -171,59 +183,235 @@ impl Scanline {
     }
 }
 
+/// plane `Ax + By + Cz + Dw = 0` in homogeneous coordinates, clipping keeps its positive side
+#[derive(Clone, Copy, Debug)]
+pub struct Plane {
+    /// `(A, B, C, D)`
+    pub equation: math::Vec4,
+}
+
+impl Plane {
+    pub fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+        Self {
+            equation: math::Vec4::new(a, b, c, d),
+        }
+    }
+
+    /// signed distance scaled by length of `(A, B, C)`, positive side is kept
+    pub fn distance(&self, position: &math::Vec4) -> f32 {
+        self.equation.dot(position)
+    }
+}
+
+/// clip convex polygon against `plane`, result is a convex polygon(maybe empty) in the same winding,
+/// fan-triangulate it by `fan_triangles`
+pub fn clip_polygon_against_plane(vertices: &[Vertex], plane: Plane) -> Vec<Vertex> {
+    clip_polygon(vertices, |position| plane.distance(position))
+}
+
+/// split convex polygon into triangles sharing its first vertex
+pub fn fan_triangles(polygon: &[Vertex]) -> impl Iterator<Item = [Vertex; 3]> + '_ {
+    (2..polygon.len()).map(move |k| [polygon[0], polygon[k - 1], polygon[k]])
+}
+
+/// clip triangle which straddles `plane`, at least one vertex must be on the positive side
+fn clip_triangle_against_plane(
+    vertices: &[Vertex],
+    plane: Plane,
+) -> ([Vertex; 3], Option<[Vertex; 3]>) {
+    let polygon = clip_polygon_against_plane(vertices, plane);
+    let mut faces = fan_triangles(&polygon);
+    let face1 = faces.next().expect("triangle is totally clipped");
+    (face1, faces.next())
+}
+
+/// clip triangle in view space against near plane `z = -near`, at least one vertex must be behind near plane.
+/// Two vertices in front of near plane give one triangle, one vertex gives two
 pub(crate) fn near_plane_clip(
     vertices: &[Vertex],
     near: f32,
 ) -> ([Vertex; 3], Option<[Vertex; 3]>) {
-    let near = -near;
-    if vertices[0].position.z > near {
-        if vertices[1].position.z > near {
-            let new_vertex1 = near_plane_clip_line(&vertices[0], &vertices[2], near);
-            let new_vertex2 = near_plane_clip_line(&vertices[1], &vertices[2], near);
-            ([new_vertex1, new_vertex2, vertices[2]], None)
-        } else if vertices[2].position.z > near {
-            let new_vertex1 = near_plane_clip_line(&vertices[0], &vertices[1], near);
-            let new_vertex2 = near_plane_clip_line(&vertices[2], &vertices[1], near);
-            return ([new_vertex1, vertices[1], new_vertex2], None);
-        } else {
-            let new_vertex1 = near_plane_clip_line(&vertices[0], &vertices[1], near);
-            let new_vertex2 = near_plane_clip_line(&vertices[0], &vertices[2], near);
-            return (
-                [vertices[1], new_vertex2, new_vertex1],
-                Some([vertices[1], vertices[2], new_vertex2]),
-            );
+    clip_triangle_against_plane(vertices, Plane::new(0.0, 0.0, -1.0, -near))
+}
+
+/// clip triangle in view space against far plane `z = -far`, at least one vertex must be in front of far plane.
+/// Two vertices beyond far plane give one triangle, one vertex gives two
+pub(crate) fn far_plane_clip(vertices: &[Vertex], far: f32) -> ([Vertex; 3], Option<[Vertex; 3]>) {
+    clip_triangle_against_plane(vertices, Plane::new(0.0, 0.0, 1.0, far))
+}
+
+/// intersection of edge `a`-`b` and plane `(A, B, C, D)`(`Ax + By + Cz + Dw = 0`), `a` and `b` must be on different sides of plane
+pub fn clip_vertex_against_plane(a: &Vertex, b: &Vertex, plane: &math::Vec4) -> Vertex {
+    let distance_a = plane.dot(&a.position);
+    let distance_b = plane.dot(&b.position);
+    let t = distance_a / (distance_a - distance_b);
+
+    Vertex {
+        position: math::lerp(a.position, b.position, t),
+        attributes: attributes_lerp(&a.attributes, &b.attributes, t),
+    }
+}
+
+/// [Sutherland-Hodgman](https://en.wikipedia.org/wiki/Sutherland%E2%80%93Hodgman_algorithm) clipping of a convex polygon,
+/// keep the part where `distance(position) >= 0`, `distance` must be linear in position
+pub(crate) fn clip_polygon<F>(polygon: &[Vertex], distance: F) -> Vec<Vertex>
+where
+    F: Fn(&math::Vec4) -> f32,
+{
+    let mut result = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let distance_current = distance(&current.position);
+        let distance_next = distance(&next.position);
+        if distance_current >= 0.0 {
+            result.push(*current);
         }
-    } else if vertices[1].position.z > near {
-        if vertices[2].position.z > near {
-            let new_vertex1 = near_plane_clip_line(&vertices[1], &vertices[0], near);
-            let new_vertex2 = near_plane_clip_line(&vertices[2], &vertices[0], near);
-            return ([vertices[0], new_vertex1, new_vertex2], None);
-        } else {
-            let new_vertex1 = near_plane_clip_line(&vertices[2], &vertices[1], near);
-            let new_vertex2 = near_plane_clip_line(&vertices[0], &vertices[1], near);
-            return (
-                [vertices[0], new_vertex2, new_vertex1],
-                Some([vertices[0], new_vertex1, vertices[2]]),
-            );
+        if (distance_current >= 0.0) != (distance_next >= 0.0) {
+            let t = distance_current / (distance_current - distance_next);
+            result.push(Vertex {
+                position: math::lerp(current.position, next.position, t),
+                attributes: attributes_lerp(&current.attributes, &next.attributes, t),
+            });
         }
-    } else {
-        let new_vertex1 = near_plane_clip_line(&vertices[2], &vertices[0], near);
-        let new_vertex2 = near_plane_clip_line(&vertices[2], &vertices[1], near);
-        return (
-            [vertices[0], new_vertex2, new_vertex1],
-            Some([vertices[0], vertices[1], new_vertex2]),
-        );
     }
+    result
 }
 
-fn near_plane_clip_line(out: &Vertex, inner: &Vertex, near_plane_z: f32) -> Vertex {
-    let proportion = (near_plane_z - inner.position.z) / (out.position.z - inner.position.z);
-    let position = proportion * (out.position - inner.position) + inner.position;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    let attributes = interp_attributes(&inner.attributes, &out.attributes, math::lerp, proportion);
+    #[test]
+    fn clip_against_near_plane() {
+        let near = 1.0;
+        let mut inner_attr = Attributes::default();
+        inner_attr.set_vec3(0, math::Vec3::new(1.0, 0.0, 0.0));
+        let mut out_attr = Attributes::default();
+        out_attr.set_vec3(0, math::Vec3::new(0.0, 1.0, 0.0));
+        let inner = Vertex::new(math::Vec3::new(-1.0, 2.0, -5.0), inner_attr);
+        let out = Vertex::new(math::Vec3::new(3.0, -2.0, 1.0), out_attr);
 
-    Vertex {
-        position,
-        attributes,
+        let result = clip_vertex_against_plane(&inner, &out, &math::Vec4::new(0.0, 0.0, 1.0, near));
+
+        // same as the formula of near plane clip
+        let proportion = (-near - inner.position.z) / (out.position.z - inner.position.z);
+        let expect_position = proportion * (out.position - inner.position) + inner.position;
+        let expect_attr =
+            interp_attributes(&inner.attributes, &out.attributes, math::lerp, proportion);
+        assert!((result.position - expect_position).length() < 1e-5);
+        assert!((result.attributes.vec3[0] - expect_attr.vec3[0]).length() < 1e-5);
+        assert!((result.position.z + near).abs() < 1e-5);
+
+        let out2 = Vertex::new(math::Vec3::new(3.0, 2.0, 1.0), out_attr);
+        let (face, none) = near_plane_clip(&[inner, out, out2], near);
+        assert!(none.is_none());
+        assert!(face
+            .iter()
+            .any(|v| (v.position - result.position).length() < 1e-5));
+    }
+
+    #[test]
+    fn clip_triangle_to_quad() {
+        let triangle = [
+            math::Vec3::new(-1.0, 0.0, 0.0),
+            math::Vec3::new(1.0, 0.0, 0.0),
+            math::Vec3::new(0.0, 2.0, 0.0),
+        ]
+        .map(|p| Vertex::new(p, Attributes::default()));
+
+        // keep y <= 1
+        let polygon = clip_polygon(&triangle, |p| 1.0 - p.y);
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|v| v.position.y <= 1.0));
+        assert!(polygon
+            .iter()
+            .any(|v| (v.position - math::Vec4::new(0.5, 1.0, 0.0, 1.0)).length() < 1e-6));
+
+        assert!(clip_polygon(&triangle, |p| p.y - 3.0).is_empty());
+        assert_eq!(clip_polygon(&triangle, |p| p.y + 1.0).len(), 3);
+    }
+
+    #[test]
+    fn clip_against_far_plane() {
+        let far = 10.0;
+        let triangle = [
+            math::Vec3::new(-1.0, 0.0, -5.0),
+            math::Vec3::new(1.0, 0.0, -5.0),
+            math::Vec3::new(0.0, 1.0, -15.0),
+        ]
+        .map(|p| Vertex::new(p, Attributes::default()));
+
+        // one vertex beyond far plane, remaining quad is split into two triangles
+        let (face1, face2) = far_plane_clip(&triangle, far);
+        let face2 = face2.unwrap();
+        for v in face1.iter().chain(face2.iter()) {
+            assert!(v.position.z >= -far - 1e-5);
+        }
+        assert!(face1
+            .iter()
+            .chain(face2.iter())
+            .any(|v| (v.position - math::Vec4::new(0.5, 0.5, -10.0, 1.0)).length() < 1e-5));
+
+        // two vertices beyond far plane
+        let triangle = [
+            math::Vec3::new(-1.0, 0.0, -5.0),
+            math::Vec3::new(1.0, 0.0, -15.0),
+            math::Vec3::new(0.0, 1.0, -15.0),
+        ]
+        .map(|p| Vertex::new(p, Attributes::default()));
+        let (face, none) = far_plane_clip(&triangle, far);
+        assert!(none.is_none());
+        assert!(face.iter().all(|v| v.position.z >= -far - 1e-5));
+    }
+
+    #[test]
+    fn trapezoid_from_nan_triangle() {
+        let triangle = [
+            math::Vec3::new(0.0, f32::NAN, 0.0),
+            math::Vec3::new(1.0, 0.0, 0.0),
+            math::Vec3::new(0.0, 2.0, 0.0),
+        ]
+        .map(|p| Vertex::new(p, Attributes::default()));
+        // only must not panic
+        let _ = Trapezoid::from_triangle(&triangle);
+    }
+
+    #[test]
+    fn clip_square_against_plane() {
+        let square = [
+            math::Vec3::new(0.0, 0.0, 0.0),
+            math::Vec3::new(2.0, 0.0, 0.0),
+            math::Vec3::new(2.0, 2.0, 0.0),
+            math::Vec3::new(0.0, 2.0, 0.0),
+        ]
+        .map(|p| {
+            let mut attr = Attributes::default();
+            attr.set_float(0, p.x);
+            Vertex::new(p, attr)
+        });
+
+        // keep x <= 1, attribute is interpolated to the plane
+        let polygon = clip_polygon_against_plane(&square, Plane::new(-1.0, 0.0, 0.0, 1.0));
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|v| v.position.x <= 1.0));
+        assert_eq!(
+            polygon
+                .iter()
+                .filter(|v| v.position.x == 1.0 && v.attributes.float[0] == 1.0)
+                .count(),
+            2
+        );
+        assert_eq!(fan_triangles(&polygon).count(), 2);
+
+        // cut a corner, keep x + y <= 1
+        let polygon = clip_polygon_against_plane(&square, Plane::new(-1.0, -1.0, 0.0, 1.0));
+        assert_eq!(polygon.len(), 3);
+        // cut a corner off, keep x + y >= 1
+        let polygon = clip_polygon_against_plane(&square, Plane::new(1.0, 1.0, 0.0, -1.0));
+        assert_eq!(polygon.len(), 5);
+        assert_eq!(fan_triangles(&polygon).count(), 3);
+
+        assert!(clip_polygon_against_plane(&square, Plane::new(1.0, 0.0, 0.0, 3.0)).len() == 4);
+        assert!(clip_polygon_against_plane(&square, Plane::new(1.0, 0.0, 0.0, -3.0)).is_empty());
     }
 }
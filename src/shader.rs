@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ops::{Mul, Sub};
 
 use crate::{math, texture::TextureStorage};
 
@@ -10,6 +11,9 @@ pub struct Attributes {
     pub vec2: [math::Vec2; MAX_ATTRIBUTES_NUM],
     pub vec3: [math::Vec3; MAX_ATTRIBUTES_NUM],
     pub vec4: [math::Vec4; MAX_ATTRIBUTES_NUM],
+    /// written by renderer when `Shader::world_position` is set, doesn't take a `vec3` slot.
+    /// Pixel shading reads it from `FragmentContext::world_position`
+    pub world_position: math::Vec3,
 }
 
 impl Attributes {
@@ -37,13 +41,14 @@ impl Default for Attributes {
             vec2: [math::Vec2::zero(); MAX_ATTRIBUTES_NUM],
             vec3: [math::Vec3::zero(); MAX_ATTRIBUTES_NUM],
             vec4: [math::Vec4::zero(); MAX_ATTRIBUTES_NUM],
+            world_position: math::Vec3::zero(),
         }
     }
 }
 
 pub fn lerp_vertex(start: &Vertex, end: &Vertex, t: f32) -> Vertex {
     let position = start.position + (end.position - start.position) * t;
-    let attributes = interp_attributes(&start.attributes, &end.attributes, math::lerp, t);
+    let attributes = attributes_lerp(&start.attributes, &end.attributes, t);
 
     Vertex {
         position,
@@ -58,6 +63,107 @@ pub fn vertex_rhw_init(vertex: &mut Vertex) {
     attributes_foreach(&mut vertex.attributes, |value| value * rhw_z);
 }
 
+/// like `vertex_rhw_init`, but only perspective correct slots are scaled
+pub fn vertex_rhw_init_with_modes(vertex: &mut Vertex, modes: &InterpModes) {
+    let rhw_z = 1.0 / vertex.position.z;
+    vertex.position.z = rhw_z;
+
+    modes.foreach_perspective(&mut vertex.attributes, |value| value * rhw_z);
+}
+
+/// interpolation qualifier of an attribute slot, like GLSL `flat` and `noperspective`
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum InterpMode {
+    #[default]
+    Perspective,
+    /// linear in screen space
+    NoPerspective,
+    /// value of the provoking vertex(first vertex of the submitted triangle)
+    Flat,
+}
+
+/// interpolation mode of every attribute slot, same layout as `Attributes`
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct InterpModes {
+    pub float: [InterpMode; MAX_ATTRIBUTES_NUM],
+    pub vec2: [InterpMode; MAX_ATTRIBUTES_NUM],
+    pub vec3: [InterpMode; MAX_ATTRIBUTES_NUM],
+    pub vec4: [InterpMode; MAX_ATTRIBUTES_NUM],
+    pub world_position: InterpMode,
+}
+
+impl InterpModes {
+    /// perspective correct slots become linear, orthographic projection doesn't need perspective correction
+    pub fn without_perspective(&self) -> Self {
+        let linear = |mode: InterpMode| {
+            if mode == InterpMode::Perspective {
+                InterpMode::NoPerspective
+            } else {
+                mode
+            }
+        };
+        Self {
+            float: self.float.map(linear),
+            vec2: self.vec2.map(linear),
+            vec3: self.vec3.map(linear),
+            vec4: self.vec4.map(linear),
+            world_position: linear(self.world_position),
+        }
+    }
+
+    /// copy flat slots of the provoking vertex into the others, so any interpolation keeps them constant
+    pub fn flatten(&self, vertices: &mut [Vertex; 3]) {
+        let provoking = vertices[0].attributes;
+        for v in &mut vertices[1..] {
+            for index in 0..MAX_ATTRIBUTES_NUM {
+                if self.float[index] == InterpMode::Flat {
+                    v.attributes.float[index] = provoking.float[index];
+                }
+                if self.vec2[index] == InterpMode::Flat {
+                    v.attributes.vec2[index] = provoking.vec2[index];
+                }
+                if self.vec3[index] == InterpMode::Flat {
+                    v.attributes.vec3[index] = provoking.vec3[index];
+                }
+                if self.vec4[index] == InterpMode::Flat {
+                    v.attributes.vec4[index] = provoking.vec4[index];
+                }
+            }
+            if self.world_position == InterpMode::Flat {
+                v.attributes.world_position = provoking.world_position;
+            }
+        }
+    }
+
+    /// like `attributes_foreach`, but only perspective correct slots are changed
+    pub fn foreach_perspective<F>(&self, attr: &mut Attributes, f: F)
+    where
+        F: Fn(f32) -> f32,
+    {
+        for index in 0..MAX_ATTRIBUTES_NUM {
+            if self.float[index] == InterpMode::Perspective {
+                attr.float[index] = f(attr.float[index]);
+            }
+            if self.vec2[index] == InterpMode::Perspective {
+                let value = attr.vec2[index];
+                attr.vec2[index] = math::Vec2::new(f(value.x), f(value.y));
+            }
+            if self.vec3[index] == InterpMode::Perspective {
+                let value = attr.vec3[index];
+                attr.vec3[index] = math::Vec3::new(f(value.x), f(value.y), f(value.z));
+            }
+            if self.vec4[index] == InterpMode::Perspective {
+                let value = attr.vec4[index];
+                attr.vec4[index] = math::Vec4::new(f(value.x), f(value.y), f(value.z), f(value.w));
+            }
+        }
+        if self.world_position == InterpMode::Perspective {
+            let value = attr.world_position;
+            attr.world_position = math::Vec3::new(f(value.x), f(value.y), f(value.z));
+        }
+    }
+}
+
 pub fn interp_attributes<F>(attr1: &Attributes, attr2: &Attributes, f: F, t: f32) -> Attributes
 where
     F: Fn(f32, f32, f32) -> f32,
@@ -104,9 +210,67 @@ where
         );
     }
 
+    let value1 = attr1.world_position;
+    let value2 = attr2.world_position;
+    attributes.world_position = math::Vec3::new(
+        f(value1.x, value2.x, t),
+        f(value1.y, value2.y, t),
+        f(value1.z, value2.z, t),
+    );
+
     attributes
 }
 
+/// same as `interp_attributes(attr1, attr2, |v1, v2, _| v1 + v2, 0.0)` but without closure call per component
+pub fn attributes_add(attr1: &Attributes, attr2: &Attributes) -> Attributes {
+    let mut attributes = *attr1;
+    for index in 0..MAX_ATTRIBUTES_NUM {
+        attributes.float[index] += attr2.float[index];
+        attributes.vec2[index] += attr2.vec2[index];
+        attributes.vec3[index] += attr2.vec3[index];
+        attributes.vec4[index] += attr2.vec4[index];
+    }
+    attributes.world_position += attr2.world_position;
+    attributes
+}
+
+/// same as `interp_attributes(attr1, attr2, math::lerp, t)` but without closure call per component
+pub fn attributes_lerp(attr1: &Attributes, attr2: &Attributes, t: f32) -> Attributes {
+    let mut attributes = *attr1;
+    for index in 0..MAX_ATTRIBUTES_NUM {
+        attributes.float[index] = math::lerp(attr1.float[index], attr2.float[index], t);
+        attributes.vec2[index] = math::lerp(attr1.vec2[index], attr2.vec2[index], t);
+        attributes.vec3[index] = math::lerp(attr1.vec3[index], attr2.vec3[index], t);
+        attributes.vec4[index] = math::lerp(attr1.vec4[index], attr2.vec4[index], t);
+    }
+    attributes.world_position = math::lerp(attr1.world_position, attr2.world_position, t);
+    attributes
+}
+
+/// interpolate custom attributes, `a`, `b` and `out` must have the same length
+pub fn interp_slice(a: &[f32], b: &[f32], t: f32, out: &mut [f32]) {
+    debug_assert!(a.len() == b.len() && a.len() == out.len());
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = math::lerp(*a, *b, t);
+    }
+}
+
+/// perspective correct version of `interp_slice`, `rhw_a` and `rhw_b` are the reciprocal of depth of `a` and `b`
+pub fn interp_slice_perspective(
+    a: &[f32],
+    b: &[f32],
+    rhw_a: f32,
+    rhw_b: f32,
+    t: f32,
+    out: &mut [f32],
+) {
+    debug_assert!(a.len() == b.len() && a.len() == out.len());
+    let rhw = math::lerp(rhw_a, rhw_b, t);
+    for ((out, a), b) in out.iter_mut().zip(a).zip(b) {
+        *out = math::lerp(*a * rhw_a, *b * rhw_b, t) / rhw;
+    }
+}
+
 pub fn attributes_foreach<F>(attr: &mut Attributes, f: F)
 where
     F: Fn(f32) -> f32,
@@ -132,6 +296,125 @@ where
             math::Vec4::new(f(value.x), f(value.y), f(value.z), f(value.w)),
         );
     }
+
+    let value = attr.world_position;
+    attr.world_position = math::Vec3::new(f(value.x), f(value.y), f(value.z));
+}
+
+/// gradient of an affine function over a triangle, values at `e1`, `e2` are relative to the first vertex
+fn plane_gradient<T>(v: [T; 3], e1: &math::Vec2, e2: &math::Vec2, inv_det: f32) -> (T, T)
+where
+    T: Sub<Output = T> + Mul<f32, Output = T> + Copy,
+{
+    let d1 = v[1] - v[0];
+    let d2 = v[2] - v[0];
+    (
+        (d1 * e2.y - d2 * e1.y) * inv_det,
+        (d2 * e1.x - d1 * e2.x) * inv_det,
+    )
+}
+
+/// screen space derivatives of interpolated attributes over one triangle, computed once per triangle.
+///
+/// `attribute / z` and `1 / z` are affine in screen space, their planes are stored here and
+/// perspective correct derivatives at a pixel are evaluated by quotient rule
+pub struct TriangleGradients {
+    origin: math::Vec2,
+    rhw: f32,
+    rhw_ddx: f32,
+    rhw_ddy: f32,
+    attributes: Attributes,
+    attributes_ddx: Attributes,
+    attributes_ddy: Attributes,
+}
+
+impl TriangleGradients {
+    /// `vertices` are in screen space with view space z kept in `position.z`
+    /// (what the rasterizer holds), `None` if the triangle is degenerate
+    pub fn new(vertices: &[Vertex; 3]) -> Option<Self> {
+        let origin = vertices[0].position.truncated_to_vec2();
+        let e1 = vertices[1].position.truncated_to_vec2() - origin;
+        let e2 = vertices[2].position.truncated_to_vec2() - origin;
+        let det = e1.cross(&e2);
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let rhws = vertices.map(|v| 1.0 / v.position.z);
+        let premultiplied = [0, 1, 2].map(|i| {
+            let mut attr = vertices[i].attributes;
+            attributes_foreach(&mut attr, |value| value * rhws[i]);
+            attr
+        });
+
+        let (rhw_ddx, rhw_ddy) = plane_gradient(rhws, &e1, &e2, inv_det);
+        let mut attributes_ddx = Attributes::default();
+        let mut attributes_ddy = Attributes::default();
+        for index in 0..MAX_ATTRIBUTES_NUM {
+            (attributes_ddx.float[index], attributes_ddy.float[index]) =
+                plane_gradient(premultiplied.map(|a| a.float[index]), &e1, &e2, inv_det);
+            (attributes_ddx.vec2[index], attributes_ddy.vec2[index]) =
+                plane_gradient(premultiplied.map(|a| a.vec2[index]), &e1, &e2, inv_det);
+            (attributes_ddx.vec3[index], attributes_ddy.vec3[index]) =
+                plane_gradient(premultiplied.map(|a| a.vec3[index]), &e1, &e2, inv_det);
+            (attributes_ddx.vec4[index], attributes_ddy.vec4[index]) =
+                plane_gradient(premultiplied.map(|a| a.vec4[index]), &e1, &e2, inv_det);
+        }
+        (attributes_ddx.world_position, attributes_ddy.world_position) =
+            plane_gradient(premultiplied.map(|a| a.world_position), &e1, &e2, inv_det);
+
+        Some(Self {
+            origin,
+            rhw: rhws[0],
+            rhw_ddx,
+            rhw_ddy,
+            attributes: premultiplied[0],
+            attributes_ddx,
+            attributes_ddy,
+        })
+    }
+
+    /// (d/dx, d/dy) of every attribute at screen point `pt`
+    pub fn at(&self, pt: &math::Vec2) -> (Attributes, Attributes) {
+        let offset = *pt - self.origin;
+        let rhw = self.rhw + self.rhw_ddx * offset.x + self.rhw_ddy * offset.y;
+        let mut step_x = self.attributes_ddx;
+        let mut step_y = self.attributes_ddy;
+        attributes_foreach(&mut step_x, |value| value * offset.x);
+        attributes_foreach(&mut step_y, |value| value * offset.y);
+        let premultiplied = attributes_add(&self.attributes, &attributes_add(&step_x, &step_y));
+
+        // d(q / w) = (dq - (q / w) * dw) / w
+        let derivative = |ddq: &Attributes, ddw: f32| {
+            let mut out = Attributes::default();
+            for index in 0..MAX_ATTRIBUTES_NUM {
+                out.float[index] =
+                    (ddq.float[index] - premultiplied.float[index] / rhw * ddw) / rhw;
+                out.vec2[index] = (ddq.vec2[index] - premultiplied.vec2[index] / rhw * ddw) / rhw;
+                out.vec3[index] = (ddq.vec3[index] - premultiplied.vec3[index] / rhw * ddw) / rhw;
+                out.vec4[index] = (ddq.vec4[index] - premultiplied.vec4[index] / rhw * ddw) / rhw;
+            }
+            out.world_position =
+                (ddq.world_position - premultiplied.world_position / rhw * ddw) / rhw;
+            out
+        };
+
+        (
+            derivative(&self.attributes_ddx, self.rhw_ddx),
+            derivative(&self.attributes_ddy, self.rhw_ddy),
+        )
+    }
+}
+
+/// fade factor of soft particle, goes from 1 to 0 as fragment approaches the scene behind it.
+/// `frag_depth` and `scene_depth` are in the same space as depth buffer(less is closer),
+/// like `FragmentContext::frag_coord.z` and `FragmentContext::scene_depth`
+pub fn soft_particle_factor(frag_depth: f32, scene_depth: f32, fade_range: f32) -> f32 {
+    if fade_range <= 0.0 {
+        return if frag_depth < scene_depth { 1.0 } else { 0.0 };
+    }
+    ((scene_depth - frag_depth) / fade_range).clamp(0.0, 1.0)
 }
 
 #[derive(Default)]
@@ -141,18 +424,54 @@ pub struct Uniforms {
     pub vec2: HashMap<u32, math::Vec2>,
     pub vec3: HashMap<u32, math::Vec3>,
     pub vec4: HashMap<u32, math::Vec4>,
+    pub mat3: HashMap<u32, math::Mat3>,
     pub mat4: HashMap<u32, math::Mat4>,
     pub texture: HashMap<u32, u32>,
 }
 
 impl Uniforms {
+    pub fn set_int(&mut self, location: u32, value: i32) {
+        self.int.insert(location, value);
+    }
+
+    pub fn set_float(&mut self, location: u32, value: f32) {
+        self.float.insert(location, value);
+    }
+
+    pub fn set_vec2(&mut self, location: u32, value: math::Vec2) {
+        self.vec2.insert(location, value);
+    }
+
+    pub fn set_vec3(&mut self, location: u32, value: math::Vec3) {
+        self.vec3.insert(location, value);
+    }
+
+    pub fn set_vec4(&mut self, location: u32, value: math::Vec4) {
+        self.vec4.insert(location, value);
+    }
+
+    pub fn set_mat3(&mut self, location: u32, value: math::Mat3) {
+        self.mat3.insert(location, value);
+    }
+
+    pub fn set_mat4(&mut self, location: u32, value: math::Mat4) {
+        self.mat4.insert(location, value);
+    }
+
+    /// `texture_id` is the id in `TextureStorage`
+    pub fn set_texture(&mut self, location: u32, texture_id: u32) {
+        self.texture.insert(location, texture_id);
+    }
+
     pub fn clear(&mut self) {
         self.int.clear();
         self.float.clear();
         self.vec2.clear();
         self.vec3.clear();
         self.vec4.clear();
+        self.mat3.clear();
         self.mat4.clear();
+        self.texture.clear();
     }
 }
 
@@ -172,11 +491,53 @@ impl Vertex {
 }
 
 pub type VertexChanging = Box<dyn Fn(&Vertex, &Uniforms, &TextureStorage) -> Vertex>;
-pub type PixelShading = Box<dyn Fn(&Attributes, &Uniforms, &TextureStorage) -> math::Vec4>;
+/// builtin inputs of pixel shading, like `gl_FragCoord` in GLSL
+#[derive(Clone, Copy, Debug)]
+pub struct FragmentContext {
+    /// x, y in window space, z the value written to depth buffer, w the rhw
+    pub frag_coord: math::Vec4,
+    /// interpolated view space z
+    pub depth: f32,
+    /// depth buffer value before this fragment is written, in the same space as `frag_coord.z`,
+    /// `f32::MAX` if nothing is drawn there. See `soft_particle_factor`
+    pub scene_depth: f32,
+    /// interpolated world position, zero unless `Shader::world_position` is set
+    pub world_position: math::Vec3,
+}
+
+impl FragmentContext {
+    /// `rhw` is 1 for orthographic projection
+    pub fn new(x: u32, y: u32, depth_value: f32, view_z: f32, rhw: f32, scene_depth: f32) -> Self {
+        Self {
+            frag_coord: math::Vec4::new(x as f32, y as f32, depth_value, rhw),
+            depth: view_z,
+            scene_depth,
+            world_position: math::Vec3::zero(),
+        }
+    }
+
+    /// set `world_position` from interpolated attributes
+    pub fn with_world_position(mut self, attribute: &Attributes) -> Self {
+        self.world_position = attribute.world_position;
+        self
+    }
+}
+
+pub type PixelShading =
+    Box<dyn Fn(&Attributes, &Uniforms, &TextureStorage, &FragmentContext) -> math::Vec4>;
+/// pixel shading which returns `None` to discard the fragment, neither color nor depth is written
+pub type PixelShadingDiscard =
+    Box<dyn Fn(&Attributes, &Uniforms, &TextureStorage, &FragmentContext) -> Option<math::Vec4>>;
 
 pub struct Shader {
     pub vertex_changing: VertexChanging,
     pub pixel_shading: PixelShading,
+    /// used instead of `pixel_shading` if set
+    pub pixel_shading_discard: Option<PixelShadingDiscard>,
+    pub interp_modes: InterpModes,
+    /// if set, renderer interpolates world position(model transformed position after vertex changing)
+    /// into `FragmentContext::world_position`, so pixel shading gets it without passing it by hand
+    pub world_position: bool,
 
     pub uniforms: Uniforms,
 }
@@ -191,13 +552,36 @@ impl Shader {
         (self.vertex_changing)(vertex, uniforms, texture_storage)
     }
 
+    /// write world position of `vertex` into `Attributes::world_position`, nothing happens if `world_position` is false
+    pub fn write_world_position(&self, model: &math::Mat4, vertex: &mut Vertex) {
+        if self.world_position {
+            let world = *model * vertex.position;
+            vertex.attributes.world_position = world.truncated_to_vec3();
+        }
+    }
+
+    /// `None` if fragment is discarded, only `pixel_shading_discard` can discard
     pub fn call_pixel_shading(
         &self,
         attribute: &Attributes,
         uniforms: &Uniforms,
         texture_storage: &TextureStorage,
-    ) -> math::Vec4 {
-        (self.pixel_shading)(attribute, uniforms, texture_storage)
+        context: &FragmentContext,
+    ) -> Option<math::Vec4> {
+        match &self.pixel_shading_discard {
+            Some(shading) => shading(attribute, uniforms, texture_storage, context),
+            None => Some((self.pixel_shading)(
+                attribute,
+                uniforms,
+                texture_storage,
+                context,
+            )),
+        }
+    }
+
+    /// depth only pass still has to shade fragments which may be discarded
+    pub fn may_discard(&self) -> bool {
+        self.pixel_shading_discard.is_some()
     }
 }
 
@@ -205,8 +589,208 @@ impl Default for Shader {
     fn default() -> Self {
         Self {
             vertex_changing: Box::new(|vertex, _, _| *vertex),
-            pixel_shading: Box::new(|_, _, _| math::Vec4::new(0.0, 0.0, 0.0, 1.0)),
+            pixel_shading: Box::new(|_, _, _, _| math::Vec4::new(0.0, 0.0, 0.0, 1.0)),
+            pixel_shading_discard: None,
+            interp_modes: Default::default(),
+            world_position: false,
             uniforms: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interp_custom_slice() {
+        let a = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let b = [2.0, 3.0, 4.0, 5.0, -4.0];
+        let mut out = [0.0; 5];
+
+        interp_slice(&a, &b, 0.5, &mut out);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 0.0]);
+
+        // same depth is the same as linear interpolation
+        interp_slice_perspective(&a, &b, 0.5, 0.5, 0.5, &mut out);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 0.0]);
+
+        // nearer vertex has more weight
+        interp_slice_perspective(&a, &b, 1.0, 0.25, 0.5, &mut out);
+        assert!((out[0] - 0.4).abs() < 1e-5);
+    }
+
+    fn attributes_for_test(offset: f32) -> Attributes {
+        let mut attr = Attributes::default();
+        for i in 0..MAX_ATTRIBUTES_NUM {
+            let v = offset + i as f32 * 0.37;
+            attr.set_float(i, v);
+            attr.set_vec2(i, math::Vec2::new(v, -v));
+            attr.set_vec3(i, math::Vec3::new(v, v * 2.0, -v));
+            attr.set_vec4(i, math::Vec4::new(v, v * 0.5, v * 3.0, 1.0));
+        }
+        attr
+    }
+
+    fn assert_attributes_eq(attr1: &Attributes, attr2: &Attributes) {
+        assert_eq!(attr1.float, attr2.float);
+        assert_eq!(attr1.vec2, attr2.vec2);
+        assert_eq!(attr1.vec3, attr2.vec3);
+        assert_eq!(attr1.vec4, attr2.vec4);
+    }
+
+    #[test]
+    fn fast_attributes_match_generic() {
+        let attr1 = attributes_for_test(1.5);
+        let attr2 = attributes_for_test(-4.25);
+
+        assert_attributes_eq(
+            &attributes_add(&attr1, &attr2),
+            &interp_attributes(&attr1, &attr2, |v1, v2, _| v1 + v2, 0.0),
+        );
+        for t in [0.0, 0.3, 0.5, 1.0] {
+            assert_attributes_eq(
+                &attributes_lerp(&attr1, &attr2, t),
+                &interp_attributes(&attr1, &attr2, math::lerp, t),
+            );
+        }
+    }
+
+    // run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_attributes_add() {
+        const COUNT: usize = 1_000_000;
+        let step = attributes_for_test(0.001);
+
+        let mut attr = attributes_for_test(0.0);
+        let begin = std::time::Instant::now();
+        for _ in 0..COUNT {
+            attr = interp_attributes(&attr, &step, |v1, v2, _| v1 + v2, 0.0);
+        }
+        let generic = begin.elapsed();
+        let generic_result = attr;
+
+        let mut attr = attributes_for_test(0.0);
+        let begin = std::time::Instant::now();
+        for _ in 0..COUNT {
+            attr = attributes_add(&attr, &step);
+        }
+        let fast = begin.elapsed();
+
+        assert_attributes_eq(&attr, &generic_result);
+        println!(
+            "interp_attributes: {:?}, attributes_add: {:?}",
+            generic, fast
+        );
+    }
+
+    #[test]
+    fn soft_particle_fade() {
+        assert_eq!(soft_particle_factor(0.2, 0.8, 0.1), 1.0);
+        assert!((soft_particle_factor(0.75, 0.8, 0.1) - 0.5).abs() < 1e-5);
+        assert!(soft_particle_factor(0.799, 0.8, 0.1) < 0.02);
+        assert_eq!(soft_particle_factor(0.8, 0.8, 0.1), 0.0);
+        assert_eq!(soft_particle_factor(0.9, 0.8, 0.1), 0.0);
+    }
+
+    #[test]
+    fn triangle_gradients_match_finite_difference() {
+        // screen space triangle with different view depth per vertex
+        let mut vertices = [
+            Vertex::new(math::Vec3::new(10.0, 10.0, -1.0), Attributes::default()),
+            Vertex::new(math::Vec3::new(90.0, 20.0, -4.0), Attributes::default()),
+            Vertex::new(math::Vec3::new(30.0, 80.0, -9.0), Attributes::default()),
+        ];
+        vertices[0]
+            .attributes
+            .set_vec2(0, math::Vec2::new(0.0, 0.0));
+        vertices[1]
+            .attributes
+            .set_vec2(0, math::Vec2::new(1.0, 0.0));
+        vertices[2]
+            .attributes
+            .set_vec2(0, math::Vec2::new(0.0, 1.0));
+
+        // perspective correct interpolation, same as the rasterizer
+        let texcoord = |pt: &math::Vec2| {
+            let triangle = vertices.map(|v| v.position.truncated_to_vec2());
+            let berycentric = math::Berycentric::new(pt, &triangle);
+            let weights = [
+                berycentric.alpha() / vertices[0].position.z,
+                berycentric.beta() / vertices[1].position.z,
+                berycentric.gamma() / vertices[2].position.z,
+            ];
+            let rhw: f32 = weights.iter().sum();
+            (vertices[0].attributes.vec2[0] * weights[0]
+                + vertices[1].attributes.vec2[0] * weights[1]
+                + vertices[2].attributes.vec2[0] * weights[2])
+                / rhw
+        };
+
+        let gradients = TriangleGradients::new(&vertices).unwrap();
+        let h = 0.01;
+        for pt in [math::Vec2::new(30.0, 30.0), math::Vec2::new(50.0, 40.0)] {
+            let (ddx, ddy) = gradients.at(&pt);
+            let expect_ddx = (texcoord(&(pt + math::Vec2::new(h, 0.0)))
+                - texcoord(&(pt - math::Vec2::new(h, 0.0))))
+                / (2.0 * h);
+            let expect_ddy = (texcoord(&(pt + math::Vec2::new(0.0, h)))
+                - texcoord(&(pt - math::Vec2::new(0.0, h))))
+                / (2.0 * h);
+            assert!((ddx.vec2[0] - expect_ddx).length() < 1e-3);
+            assert!((ddy.vec2[0] - expect_ddy).length() < 1e-3);
+
+            // first order reconstruction of texcoord one pixel away, only second order error is left
+            let step = math::Vec2::new(1.0, 1.0);
+            let expect = texcoord(&(pt + step));
+            let reconstructed = texcoord(&pt) + ddx.vec2[0] * step.x + ddy.vec2[0] * step.y;
+            let change = (expect - texcoord(&pt)).length();
+            assert!((reconstructed - expect).length() < 0.1 * change);
+        }
+
+        let degenerate = [vertices[0], vertices[0], vertices[1]];
+        assert!(TriangleGradients::new(&degenerate).is_none());
+    }
+
+    #[test]
+    fn clear_all_uniforms() {
+        let mut uniforms = Uniforms::default();
+        uniforms.int.insert(0, 1);
+        uniforms.float.insert(0, 1.0);
+        uniforms.vec2.insert(0, math::Vec2::zero());
+        uniforms.vec3.insert(0, math::Vec3::zero());
+        uniforms.vec4.insert(0, math::Vec4::zero());
+        uniforms.mat3.insert(0, math::Mat3::identity());
+        uniforms.mat4.insert(0, math::Mat4::identity());
+        uniforms.texture.insert(0, 1);
+
+        uniforms.clear();
+        assert!(uniforms.int.is_empty());
+        assert!(uniforms.float.is_empty());
+        assert!(uniforms.vec2.is_empty());
+        assert!(uniforms.vec3.is_empty());
+        assert!(uniforms.vec4.is_empty());
+        assert!(uniforms.mat3.is_empty());
+        assert!(uniforms.mat4.is_empty());
+        assert!(uniforms.texture.is_empty());
+    }
+
+    #[test]
+    fn set_and_get_mat3() {
+        let model = math::create_eular_rotate_y(30f32.to_radians())
+            * math::create_scale(&math::Vec3::new(1.0, 2.0, 3.0));
+        let normal_matrix = model.truncated_to_mat3().inverse().unwrap().transpose();
+
+        let mut uniforms = Uniforms::default();
+        uniforms.set_mat3(3, normal_matrix);
+        uniforms.set_vec3(3, math::Vec3::zero());
+        let stored = uniforms.mat3.get(&3).unwrap();
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(stored.get(x, y), normal_matrix.get(x, y));
+            }
+        }
+        assert!(!uniforms.mat3.contains_key(&0));
+    }
+}
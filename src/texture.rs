@@ -1,23 +1,61 @@
 use std::collections::HashMap;
 
 use crate::math;
-use image::{self, GenericImageView, ImageBuffer, Pixel, Rgba};
+use image::{self, GenericImageView};
 
 pub struct Texture {
     image: image::DynamicImage,
+    // mipmap level 1, 2, ..., level 0 is `image`
+    mipmaps: Vec<image::DynamicImage>,
     id: u32,
     name: String,
 }
 
 impl Texture {
-    fn load(filename: &str, id: u32, name: &str) -> image::ImageResult<Texture> {
-        let image = image::open(filename).expect(&format!("{} File not found!", filename)).flipv();
-
-        Ok(Self {
+    fn from_image(image: image::DynamicImage, id: u32, name: &str) -> Texture {
+        Self {
             image,
+            mipmaps: vec![],
             id,
             name: name.to_string(),
-        })
+        }
+    }
+
+    /// generate mipmaps down to 1x1, each level is half size of the previous one
+    pub fn generate_mipmaps(&mut self) {
+        self.mipmaps.clear();
+        let mut level = &self.image;
+        let mut mipmaps = vec![];
+        while level.width() > 1 || level.height() > 1 {
+            mipmaps.push(level.resize_exact(
+                (level.width() / 2).max(1),
+                (level.height() / 2).max(1),
+                image::imageops::FilterType::Triangle,
+            ));
+            level = mipmaps.last().unwrap();
+        }
+        self.mipmaps = mipmaps;
+    }
+
+    /// level count include level 0
+    pub fn mip_levels(&self) -> u32 {
+        self.mipmaps.len() as u32 + 1
+    }
+
+    fn level(&self, level: u32) -> &image::DynamicImage {
+        if level == 0 {
+            &self.image
+        } else {
+            &self.mipmaps[level as usize - 1]
+        }
+    }
+
+    pub fn level_width(&self, level: u32) -> u32 {
+        self.level(level).width()
+    }
+
+    pub fn level_height(&self, level: u32) -> u32 {
+        self.level(level).height()
     }
 
     pub fn id(&self) -> u32 {
@@ -37,7 +75,11 @@ impl Texture {
     }
 
     pub fn get(&self, x: u32, y: u32) -> math::Vec4 {
-        let pixel = self.image.get_pixel(x, y);
+        self.get_level(0, x, y)
+    }
+
+    pub fn get_level(&self, level: u32, x: u32, y: u32) -> math::Vec4 {
+        let pixel = self.level(level).get_pixel(x, y);
         let data = &pixel.0;
         math::Vec4::new(
             data[0] as f32 / 255.0,
@@ -48,26 +90,163 @@ impl Texture {
     }
 }
 
+/// how to sample texcoord out of `[0, 1]`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    /// use the nearest edge texel
+    ClampToEdge,
+    /// use the border color of sampler
+    ClampToBorder,
+    /// use the fractional part, for tiled texture
+    Repeat,
+    /// reflect across integer boundaries
+    Mirror,
+}
+
+impl WrapMode {
+    /// map texcoord component into `[0, 1]`
+    pub fn wrap(&self, coord: f32) -> f32 {
+        match self {
+            WrapMode::ClampToEdge | WrapMode::ClampToBorder => coord.clamp(0.0, 1.0),
+            WrapMode::Repeat => coord - coord.floor(),
+            WrapMode::Mirror => {
+                let t = coord.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        }
+    }
+}
+
+/// sampling state for texture with mipmaps
+#[derive(Clone, Copy, Debug)]
+pub struct Sampler {
+    base_level: u32,
+    max_level: u32,
+    wrap_mode: WrapMode,
+    border_color: math::Vec4,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            base_level: 0,
+            max_level: u32::MAX,
+            wrap_mode: WrapMode::ClampToEdge,
+            border_color: math::Vec4::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl Sampler {
+    /// only mipmap levels in `[base, max]` are used, `lod = 0` means `base` level
+    pub fn set_mip_range(&mut self, base: u32, max: u32) {
+        self.base_level = base;
+        self.max_level = max.max(base);
+    }
+
+    pub fn base_level(&self) -> u32 {
+        self.base_level
+    }
+
+    pub fn max_level(&self) -> u32 {
+        self.max_level
+    }
+
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+    }
+
+    pub fn wrap_mode(&self) -> WrapMode {
+        self.wrap_mode
+    }
+
+    /// color returned by `WrapMode::ClampToBorder`
+    pub fn set_border_color(&mut self, color: math::Vec4) {
+        self.border_color = color;
+    }
+
+    pub fn border_color(&self) -> &math::Vec4 {
+        &self.border_color
+    }
+
+    /// level of detail from screen space texcoord derivatives(e.g. from `shader::TriangleGradients`)
+    pub fn lod(&self, texture: &Texture, ddx: &math::Vec2, ddy: &math::Vec2) -> f32 {
+        let size = math::Vec2::new(texture.width() as f32, texture.height() as f32);
+        let rho = (*ddx * size).length().max((*ddy * size).length());
+        rho.max(f32::MIN_POSITIVE).log2().max(0.0)
+    }
+
+    pub fn select_level(&self, texture: &Texture, lod: f32) -> u32 {
+        let max_level = self.max_level.min(texture.mip_levels() - 1);
+        let base_level = self.base_level.min(max_level);
+        (base_level + lod.max(0.0).round() as u32).min(max_level)
+    }
+
+    /// nearest sample on the level selected by `lod`
+    pub fn sample(&self, texture: &Texture, texcoord: &math::Vec2, lod: f32) -> math::Vec4 {
+        let out_of_range = !(0.0..=1.0).contains(&texcoord.x) || !(0.0..=1.0).contains(&texcoord.y);
+        if self.wrap_mode == WrapMode::ClampToBorder && out_of_range {
+            return self.border_color;
+        }
+
+        let level = self.select_level(texture, lod);
+        let x = (self.wrap_mode.wrap(texcoord.x) * (texture.level_width(level) - 1) as f32) as u32;
+        let y = (self.wrap_mode.wrap(texcoord.y) * (texture.level_height(level) - 1) as f32) as u32;
+        texture.get_level(level, x, y)
+    }
+}
+
 #[derive(Default)]
 pub struct TextureStorage {
     cur_id: u32,
     images: HashMap<u32, Texture>,
     name_id_map: HashMap<String, u32>,
+    max_texture_size: Option<u32>,
 }
 
 impl TextureStorage {
     pub fn load(&mut self, filename: &str, name: &str) -> image::ImageResult<u32> {
+        let image = image::open(filename)?.flipv();
+        Ok(self.load_from_image(image, name))
+    }
+
+    /// load from image in memory, unlike `load`, the image won't be flipped
+    pub fn load_from_image(&mut self, image: image::DynamicImage, name: &str) -> u32 {
+        let image = match self.max_texture_size {
+            Some(max_size) if image.width() > max_size || image.height() > max_size => {
+                image.resize(max_size, max_size, image::imageops::FilterType::Triangle)
+            }
+            _ => image,
+        };
         let id = self.cur_id;
         self.cur_id += 1;
-        self.images.insert(id, Texture::load(filename, id, name)?);
+        self.images.insert(id, Texture::from_image(image, id, name));
         self.name_id_map.insert(name.to_string(), id);
-        Ok(id)
+        id
+    }
+
+    /// textures loaded later whose width or height is larger than `max_size` are downscaled(keep aspect ratio),
+    /// `None` means no limit
+    pub fn set_max_texture_size(&mut self, max_size: Option<u32>) {
+        self.max_texture_size = max_size;
+    }
+
+    pub fn max_texture_size(&self) -> Option<u32> {
+        self.max_texture_size
     }
 
     pub fn get_by_id(&self, id: u32) -> Option<&Texture> {
         self.images.get(&id)
     }
 
+    pub fn get_by_id_mut(&mut self, id: u32) -> Option<&mut Texture> {
+        self.images.get_mut(&id)
+    }
+
     pub fn get_by_name(&self, name: &str) -> Option<&Texture> {
         let id = self.name_id_map.get(name)?;
         self.images.get(id)
@@ -76,4 +255,196 @@ impl TextureStorage {
     pub fn get_id(&self, name: &str) -> Option<&u32> {
         self.name_id_map.get(name)
     }
+
+    /// return whether a texture is removed, the id of removed texture won't be reused
+    pub fn unload_by_id(&mut self, id: u32) -> bool {
+        match self.images.remove(&id) {
+            Some(texture) => {
+                // name may be taken by a later loaded texture
+                if self.name_id_map.get(texture.name()) == Some(&id) {
+                    self.name_id_map.remove(texture.name());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// return whether a texture is removed, the id of removed texture won't be reused
+    pub fn unload_by_name(&mut self, name: &str) -> bool {
+        match self.name_id_map.remove(name) {
+            Some(id) => self.images.remove(&id).is_some(),
+            None => false,
+        }
+    }
+
+    /// iterate all loaded textures in arbitrary order
+    pub fn iter(&self) -> impl Iterator<Item = (&u32, &Texture)> {
+        self.images.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sampler_mip_range() {
+        let image = image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([(x * 60) as u8, (y * 60) as u8, 255, 255])
+        });
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::ImageRgba8(image), "pattern");
+        let texture = storage.get_by_id_mut(id).unwrap();
+        texture.generate_mipmaps();
+        assert_eq!(texture.mip_levels(), 3);
+        assert_eq!(texture.level_width(2), 1);
+
+        let mut sampler = Sampler::default();
+        sampler.set_mip_range(2, 2);
+        let expect = texture.get_level(2, 0, 0);
+        for lod in [0.0, 0.4, 1.0, 10.0] {
+            assert_eq!(
+                sampler.sample(texture, &math::Vec2::new(0.1, 0.9), lod),
+                expect
+            );
+        }
+    }
+
+    #[test]
+    fn sampler_lod() {
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::new_rgb8(4, 4), "lod");
+        let texture = storage.get_by_id(id).unwrap();
+        let sampler = Sampler::default();
+
+        let ddx = math::Vec2::new(0.5, 0.0);
+        let ddy = math::Vec2::new(0.0, 0.25);
+        assert_eq!(sampler.lod(texture, &ddx, &ddy), 1.0);
+        assert_eq!(
+            sampler.lod(texture, &math::Vec2::zero(), &math::Vec2::zero()),
+            0.0
+        );
+    }
+
+    #[test]
+    fn sampler_border_color() {
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([0, 255, 0, 255]));
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::ImageRgba8(image), "green");
+        let texture = storage.get_by_id(id).unwrap();
+        let red = math::Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let green = math::Vec4::new(0.0, 1.0, 0.0, 1.0);
+
+        let mut sampler = Sampler::default();
+        sampler.set_border_color(red);
+        assert_eq!(
+            sampler.sample(texture, &math::Vec2::new(2.0, 0.5), 0.0),
+            green
+        );
+
+        sampler.set_wrap_mode(WrapMode::ClampToBorder);
+        assert_eq!(
+            sampler.sample(texture, &math::Vec2::new(2.0, 0.5), 0.0),
+            red
+        );
+        assert_eq!(
+            sampler.sample(texture, &math::Vec2::new(0.5, 0.5), 0.0),
+            green
+        );
+    }
+
+    #[test]
+    fn sampler_wrap_modes() {
+        let mut image = image::RgbaImage::new(5, 1);
+        for x in 0..5 {
+            image.put_pixel(x, 0, image::Rgba([x as u8 * 50, 0, 0, 255]));
+        }
+        let mut storage = TextureStorage::default();
+        let id = storage.load_from_image(image::DynamicImage::ImageRgba8(image), "columns");
+        let texture = storage.get_by_id(id).unwrap();
+        let sample = |wrap_mode: WrapMode, u: f32| {
+            let mut sampler = Sampler::default();
+            sampler.set_wrap_mode(wrap_mode);
+            sampler.sample(texture, &math::Vec2::new(u, 0.0), 0.0)
+        };
+
+        assert_eq!(sample(WrapMode::Repeat, 1.5), sample(WrapMode::Repeat, 0.5));
+        assert_eq!(
+            sample(WrapMode::Repeat, -0.75),
+            sample(WrapMode::Repeat, 0.25)
+        );
+        assert_eq!(
+            sample(WrapMode::Mirror, 1.25),
+            sample(WrapMode::Mirror, 0.75)
+        );
+        assert_eq!(
+            sample(WrapMode::Mirror, -0.25),
+            sample(WrapMode::Mirror, 0.25)
+        );
+        assert_eq!(sample(WrapMode::ClampToEdge, 1.5), texture.get(4, 0));
+        assert_eq!(sample(WrapMode::ClampToEdge, -0.5), texture.get(0, 0));
+    }
+
+    #[test]
+    fn iterate_textures() {
+        let mut storage = TextureStorage::default();
+        assert!(storage.is_empty());
+        let id1 = storage.load_from_image(image::DynamicImage::new_rgb8(1, 1), "first");
+        let id2 = storage.load_from_image(image::DynamicImage::new_rgb8(2, 2), "second");
+
+        assert_eq!(storage.len(), 2);
+        let mut names: Vec<(u32, &str)> = storage
+            .iter()
+            .map(|(id, texture)| (*id, texture.name()))
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![(id1, "first"), (id2, "second")]);
+    }
+
+    #[test]
+    fn unload_textures() {
+        let mut storage = TextureStorage::default();
+        let id1 = storage.load_from_image(image::DynamicImage::new_rgb8(1, 1), "first");
+        let id2 = storage.load_from_image(image::DynamicImage::new_rgb8(1, 1), "second");
+
+        assert!(storage.unload_by_id(id1));
+        assert!(!storage.unload_by_id(id1));
+        assert!(storage.get_by_id(id1).is_none());
+        assert!(storage.get_by_name("first").is_none());
+
+        assert!(storage.unload_by_name("second"));
+        assert!(!storage.unload_by_name("second"));
+        assert!(storage.get_by_id(id2).is_none());
+        assert!(storage.get_id("second").is_none());
+        assert!(storage.is_empty());
+
+        // ids are not reused
+        let id3 = storage.load_from_image(image::DynamicImage::new_rgb8(1, 1), "third");
+        assert!(id3 != id1 && id3 != id2);
+    }
+
+    #[test]
+    fn max_texture_size() {
+        let mut storage = TextureStorage::default();
+        storage.set_max_texture_size(Some(16));
+
+        let id = storage.load_from_image(image::DynamicImage::new_rgb8(64, 32), "large");
+        let texture = storage.get_by_id(id).unwrap();
+        assert_eq!(texture.width(), 16);
+        assert_eq!(texture.height(), 8);
+
+        let id = storage.load_from_image(image::DynamicImage::new_rgb8(8, 4), "small");
+        let texture = storage.get_by_id(id).unwrap();
+        assert_eq!(texture.width(), 8);
+        assert_eq!(texture.height(), 4);
+    }
 }
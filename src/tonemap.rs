@@ -0,0 +1,57 @@
+//! tone mapping operators, map HDR color into `[0, 1]`
+
+use crate::math;
+
+/// scale color by `2^ev`
+pub fn exposure(color: &math::Vec3, ev: f32) -> math::Vec3 {
+    *color * 2f32.powf(ev)
+}
+
+/// `c / (1 + c)`
+pub fn reinhard(color: &math::Vec3) -> math::Vec3 {
+    math::Vec3::new(
+        color.x / (1.0 + color.x),
+        color.y / (1.0 + color.y),
+        color.z / (1.0 + color.z),
+    )
+}
+
+/// [Krzysztof Narkowicz's ACES filmic curve fit](https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/)
+pub fn aces_filmic(color: &math::Vec3) -> math::Vec3 {
+    let f = |x: f32| ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0);
+    math::Vec3::new(f(color.x), f(color.y), f(color.z))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn in_ldr_range(color: &math::Vec3) -> bool {
+        [color.x, color.y, color.z]
+            .iter()
+            .all(|c| (0.0..=1.0).contains(c))
+    }
+
+    #[test]
+    fn bright_color_below_one() {
+        let hdr = math::Vec3::new(100.0, 50.0, 20.0);
+        assert!(in_ldr_range(&reinhard(&hdr)));
+        assert!(reinhard(&hdr).x < 1.0);
+        // ACES curve goes beyond 1.0 for very bright color, so it is clamped
+        assert!(in_ldr_range(&aces_filmic(&hdr)));
+    }
+
+    #[test]
+    fn mid_tone_preserved() {
+        let mid = math::Vec3::new(0.18, 0.18, 0.18);
+        assert!((reinhard(&mid) - mid).length() < 0.1 * 3f32.sqrt());
+        assert!((aces_filmic(&mid) - mid).length() < 0.1 * 3f32.sqrt());
+    }
+
+    #[test]
+    fn exposure_scale() {
+        let color = math::Vec3::new(0.1, 0.2, 0.3);
+        assert_eq!(exposure(&color, 1.0), color * 2.0);
+        assert_eq!(exposure(&color, 0.0), color);
+    }
+}